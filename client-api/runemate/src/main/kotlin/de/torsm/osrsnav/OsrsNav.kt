@@ -3,7 +3,6 @@ package de.torsm.osrsnav
 import com.google.gson.FieldNamingPolicy
 import com.google.gson.GsonBuilder
 import com.google.gson.TypeAdapter
-import com.google.gson.reflect.TypeToken
 import com.google.gson.stream.JsonReader
 import com.google.gson.stream.JsonToken
 import com.google.gson.stream.JsonWriter
@@ -72,7 +71,7 @@ object OsrsNav {
             .uri(URI("$NAV_URL/path"))
             .POST(HttpRequest.BodyPublishers.ofString(json))
             .build()
-        return doHttpRequest(httpRequest, TypeToken.getParameterized(List::class.java, Edge::class.java).type)
+        return doHttpRequest<PathResponse>(httpRequest)?.steps
     }
 
     fun buildBetween(start: Coordinate, end: Coordinate, gameState: GameState = GameState.fromGame()): List<Edge>? {
@@ -96,6 +95,11 @@ object OsrsNav {
     }
 }
 
+data class PathResponse(
+    val steps: List<Edge>,
+    val cost: Int,
+)
+
 data class PathGenerationRequest(
     val start: Coordinate,
     val end: Coordinate,
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::Parser;
+use expect_exit::ExpectedWithError;
+
+use pathfinder::ch::ContractionHierarchy;
+
+#[derive(Parser)]
+struct Options {
+    /// Path to NavGrid file
+    #[clap(short, long)]
+    navgrid: PathBuf,
+    /// File that the contraction hierarchy is written to, next to the NavGrid with extension `ch` by default
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let options = Options::parse();
+    let nav_grid = model::util::load_nav_grid(&options.navgrid).or_exit_e_("Error loading NavGrid");
+    let checksum = model::util::read_header(&options.navgrid).or_exit_e_("Error loading NavGrid").map_or(0, |header| header.checksum);
+
+    println!("Contracting...");
+    let begin = Instant::now();
+    let hierarchy = ContractionHierarchy::build(&nav_grid, checksum);
+    println!("Contracted {} nodes into {} arcs in {:?}", hierarchy.node_count(), hierarchy.arc_count(), Instant::now() - begin);
+
+    println!("Exporting hierarchy...");
+    let output = options.output.unwrap_or_else(|| options.navgrid.with_extension("ch"));
+    hierarchy.save(&output).or_exit_e_("Error exporting hierarchy");
+
+    println!("Complete");
+}
@@ -0,0 +1,38 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use clap::Parser;
+use expect_exit::ExpectedWithError;
+
+#[derive(Parser)]
+struct Options {
+    /// Path to the NavGrid file of the previous revision
+    old: PathBuf,
+    /// Path to the NavGrid file of the new revision
+    new: PathBuf,
+    /// File that the full report of changed tiles and edges is written to as YAML
+    #[clap(short, long)]
+    report: Option<PathBuf>,
+}
+
+fn main() {
+    let options = Options::parse();
+    let old = model::util::load_nav_grid(&options.old).or_exit_e_("Error loading old NavGrid");
+    let new = model::util::load_nav_grid(&options.new).or_exit_e_("Error loading new NavGrid");
+
+    let diff = model::diff::diff(&old, &new);
+    if diff.is_empty() {
+        println!("No changes");
+        return;
+    }
+    for (region, changes) in &diff.regions {
+        println!("Region {:>5}: {} tiles added, {} tiles removed, {} flags changed, {} edges changed",
+                 region, changes.added_tiles.len(), changes.removed_tiles.len(), changes.changed_flags.len(), changes.edges.len());
+    }
+    println!("{} regions changed, {} teleports changed", diff.regions.len(), diff.teleports.len());
+
+    if let Some(report) = options.report {
+        let file = File::create(report).or_exit_e_("Error creating report");
+        serde_yaml::to_writer(file, &diff).or_exit_e_("Error writing report");
+    }
+}
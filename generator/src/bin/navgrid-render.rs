@@ -0,0 +1,219 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::Parser;
+use expect_exit::{Expected, ExpectedWithError};
+
+use model::{Coordinate, NavGrid};
+use model::constants::{FLAG_E, FLAG_N, FLAG_S, FLAG_W};
+use model::definitions::GameState;
+use pathfinder::{Step, StepKind};
+
+const BLOCKED: [u8; 3] = [32, 32, 32];
+const WALKABLE: [u8; 3] = [200, 200, 200];
+const WALL: [u8; 3] = [90, 90, 90];
+const EXTRA_EDGES: [u8; 3] = [240, 150, 30];
+const TELEPORT_DESTINATION: [u8; 3] = [160, 60, 220];
+const PATH: [u8; 3] = [220, 40, 40];
+const PATH_EDGE: [u8; 3] = [40, 110, 230];
+
+/// Tile given as `x,y`
+#[derive(Clone, Copy)]
+struct Tile {
+    x: u16,
+    y: u16,
+}
+
+impl FromStr for Tile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Tile, String> {
+        let (x, y) = s.split_once(',').ok_or("Expected x,y")?;
+        Ok(Tile {
+            x: x.trim().parse().map_err(|_| format!("Invalid x: {}", x))?,
+            y: y.trim().parse().map_err(|_| format!("Invalid y: {}", y))?,
+        })
+    }
+}
+
+#[derive(Parser)]
+struct Options {
+    /// Path to NavGrid file
+    #[clap(short, long)]
+    navgrid: PathBuf,
+    /// PNG file that the image is written to
+    #[clap(short, long)]
+    output: PathBuf,
+    /// Plane to render
+    #[clap(long, default_value = "0")]
+    plane: u8,
+    /// South-west corner of the rendered area, as x,y
+    #[clap(long)]
+    min: Tile,
+    /// North-east corner of the rendered area, as x,y
+    #[clap(long)]
+    max: Tile,
+    /// Pixels per tile. Walls between tiles are drawn from 3 pixels per tile
+    #[clap(long, default_value = "4")]
+    scale: u32,
+    /// Overlay the path from this tile on the rendered plane, as x,y
+    #[clap(long, requires = "end")]
+    start: Option<Tile>,
+    /// Overlay the path to this tile on the rendered plane, as x,y
+    #[clap(long, requires = "start")]
+    end: Option<Tile>,
+    /// YAML or JSON file with the game state the path is searched with, the default game state if absent
+    #[clap(long)]
+    game_state: Option<PathBuf>,
+}
+
+/// RGB image of the tiles in `min..=max`, with north at the top
+struct Canvas {
+    min: Tile,
+    max: Tile,
+    scale: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(min: Tile, max: Tile, scale: u32) -> Canvas {
+        let mut canvas = Canvas { min, max, scale, pixels: Vec::new() };
+        canvas.pixels = vec![0; (canvas.width() * canvas.height() * 3) as usize];
+        canvas
+    }
+
+    fn width(&self) -> u32 {
+        (self.max.x - self.min.x + 1) as u32 * self.scale
+    }
+
+    fn height(&self) -> u32 {
+        (self.max.y - self.min.y + 1) as u32 * self.scale
+    }
+
+    fn contains(&self, x: u16, y: u16) -> bool {
+        (self.min.x..=self.max.x).contains(&x) && (self.min.y..=self.max.y).contains(&y)
+    }
+
+    fn set_pixel(&mut self, px: u32, py: u32, color: [u8; 3]) {
+        let offset = ((py * self.width() + px) * 3) as usize;
+        self.pixels[offset..offset + 3].copy_from_slice(&color);
+    }
+
+    /// Fills the pixels of the tile at `x, y` within `inset` pixels of its border
+    fn fill_tile(&mut self, x: u16, y: u16, inset: u32, color: [u8; 3]) {
+        if !self.contains(x, y) || inset * 2 >= self.scale {
+            return;
+        }
+        let left = (x - self.min.x) as u32 * self.scale;
+        let top = (self.max.y - y) as u32 * self.scale;
+        for py in top + inset..top + self.scale - inset {
+            for px in left + inset..left + self.scale - inset {
+                self.set_pixel(px, py, color);
+            }
+        }
+    }
+
+    /// Draws a wall on the sides of the tile at `x, y` that can't be crossed
+    fn draw_walls(&mut self, x: u16, y: u16, flags: u8) {
+        let left = (x - self.min.x) as u32 * self.scale;
+        let top = (self.max.y - y) as u32 * self.scale;
+        let last = self.scale - 1;
+        for i in 0..self.scale {
+            if flags & FLAG_N == 0 {
+                self.set_pixel(left + i, top, WALL);
+            }
+            if flags & FLAG_S == 0 {
+                self.set_pixel(left + i, top + last, WALL);
+            }
+            if flags & FLAG_W == 0 {
+                self.set_pixel(left, top + i, WALL);
+            }
+            if flags & FLAG_E == 0 {
+                self.set_pixel(left + last, top + i, WALL);
+            }
+        }
+    }
+
+    fn draw_grid(&mut self, nav_grid: &NavGrid, plane: u8) {
+        for y in self.min.y..=self.max.y {
+            for x in self.min.x..=self.max.x {
+                let index = Coordinate { x, y, plane }.index() as usize;
+                let vertex = nav_grid.vertices.get(index).copied().unwrap_or_default();
+                if vertex.flags == 0 {
+                    self.fill_tile(x, y, 0, BLOCKED);
+                    continue;
+                }
+                self.fill_tile(x, y, 0, if vertex.has_extra_edges() { EXTRA_EDGES } else { WALKABLE });
+                if self.scale >= 3 {
+                    self.draw_walls(x, y, vertex.flags);
+                }
+            }
+        }
+        for teleport in &nav_grid.teleports {
+            if teleport.destination.plane == plane {
+                self.fill_tile(teleport.destination.x, teleport.destination.y, 0, TELEPORT_DESTINATION);
+            }
+        }
+    }
+
+    /// Marks the tiles walked over, and the tiles edges are taken from and edges and teleports lead to
+    fn draw_path(&mut self, steps: &[Step], plane: u8) {
+        let inset = self.scale / 4;
+        // Edges are drawn last, so they aren't hidden by the walking steps before and after them
+        for walk in [true, false] {
+            for step in steps.iter().filter(|step| step.is_walk() == walk) {
+                let color = if walk { PATH } else { PATH_EDGE };
+                let from = Some(step.from).filter(|_| step.kind != StepKind::Teleport);
+                for tile in from.into_iter().chain([step.to]).filter(|tile| tile.plane == plane) {
+                    self.fill_tile(tile.x, tile.y, inset, color);
+                }
+            }
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width(), self.height());
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)
+    }
+}
+
+fn main() {
+    let options = Options::parse();
+    let max = Coordinate { x: options.max.x, y: options.max.y, plane: options.plane };
+    if options.min.x > options.max.x || options.min.y > options.max.y || !max.validate() {
+        eprintln!("The area must lie within the map, with the minimum corner south-west of the maximum corner");
+        std::process::exit(1);
+    }
+    let nav_grid = model::util::load_nav_grid(&options.navgrid).or_exit_e_("Error loading NavGrid");
+
+    let mut canvas = Canvas::new(options.min, options.max, options.scale.max(1));
+    canvas.draw_grid(&nav_grid, options.plane);
+
+    if let (Some(start), Some(end)) = (options.start, options.end) {
+        let game_state: GameState = match &options.game_state {
+            Some(path) => {
+                let file = File::open(path).or_exit_e_("Error opening game state file");
+                serde_yaml::from_reader(BufReader::new(file)).or_exit_e_("Error parsing game state file")
+            }
+            None => GameState::default(),
+        };
+        let start = Coordinate { x: start.x, y: start.y, plane: options.plane };
+        let end = Coordinate { x: end.x, y: end.y, plane: options.plane };
+        if !start.validate() || !end.validate() {
+            eprintln!("Start and end must lie within the map");
+            std::process::exit(1);
+        }
+        let (_, _, path) = pathfinder::dijkstra(&nav_grid, &start, &end, &game_state);
+        let path = path.or_exit_("No path found");
+        println!("Path of {} steps costing {}", path.steps.len(), path.cost);
+        canvas.draw_path(&path.steps, options.plane);
+    }
+
+    canvas.save(&options.output).or_exit_e_("Error writing image");
+    println!("Rendered {}x{} pixels", canvas.width(), canvas.height());
+}
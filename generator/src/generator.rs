@@ -94,7 +94,7 @@ impl NavGenerator {
         if let 0..=3 = loc.r#type {
             if let (Some(name), Some(actions)) = (&config.name, &config.actions) {
                 if (name == "Door" || name == "Gate" || name == "Large door") && actions.contains(&Some("Open".to_string())) {
-                    self.add_door(adjusted_c, loc.id, loc.rotation);
+                    self.add_door(adjusted_c, loc.id, name, loc.rotation);
                 }
             }
         }
@@ -191,7 +191,7 @@ impl NavGenerator {
         }
     }
 
-    fn add_door(&mut self, c: &Coordinate, id: u32, rotation: u8) {
+    fn add_door(&mut self, c: &Coordinate, id: u32, name: &str, rotation: u8) {
         let (dx, dy) = match rotation {
             0 => (-1, 0),
             1 => (0, 1),
@@ -202,6 +202,7 @@ impl NavGenerator {
         let c2 = c.derive(dx, dy, 0);
         let def = EdgeDefinition::Door {
             id,
+            name: Some(name.to_string()),
             position: c.clone(),
             action: Regex::new("^Open$").expect("Invalid regex"),
         };
@@ -210,12 +211,16 @@ impl NavGenerator {
             cost: 2,
             definition: def.clone(),
             requirements: vec![],
+            instance_plane: None,
+            arrival: None,
         });
         self.nav_grid.edges.insert(c2.index(), Edge {
             destination: c.clone(),
             cost: 2,
             definition: def,
             requirements: vec![],
+            instance_plane: None,
+            arrival: None,
         });
     }
 
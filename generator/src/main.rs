@@ -1,20 +1,18 @@
 extern crate core;
 
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::BufReader;
 use std::path::PathBuf;
 
 use clap::Parser;
 use expect_exit::{Expected, ExpectedWithError};
-use flate2::Compression;
-use flate2::write::GzEncoder;
 use rs3cache::cli::Config;
 use rs3cache::definitions::location_configs::LocationConfig;
 use rs3cache::definitions::mapsquares::MapSquares;
 use serde::{Deserialize, Serialize};
 
 use generator::NavGenerator;
-use model::{Coordinate, Edge, NavGrid};
+use model::{Coordinate, Diagnostic, Edge, NavGrid, PointOfInterest};
 use model::definitions::RequirementDefinition;
 use model::util::RegionCache;
 
@@ -39,6 +37,12 @@ struct Options {
     /// YAML file with generator configuration
     #[clap(long)]
     config: Option<PathBuf>,
+    /// Write an uncompressed NavGrid that can be memory-mapped
+    #[clap(long, conflicts_with = "regions")]
+    mapped: bool,
+    /// Write a NavGrid sharded by region, which can be opened limited to some regions
+    #[clap(long)]
+    regions: bool,
 }
 
 fn main() {
@@ -85,23 +89,31 @@ fn main() {
         nav_grid.vertices[*index as usize].set_extra_edges(true);
     }
     create_groups(&mut nav_grid);
-    nav_grid.iter_edges_mut().flat_map(|e| e.requirements.iter_mut()).for_each(|r| {
+    nav_grid.iter_edges_mut().flat_map(|e| e.requirements.iter_mut()).flat_map(RequirementDefinition::leaves_mut).for_each(|r| {
         if let RequirementDefinition::Skill { skill, .. } = r {
             *skill = skill.to_uppercase();
         }
     });
 
+    println!("Validating...");
+    let diagnostics = nav_grid.validate();
+    for diagnostic in &diagnostics {
+        println!("{} {}", if diagnostic.is_fatal() { "error:" } else { "warning:" }, diagnostic);
+    }
+    if diagnostics.iter().any(Diagnostic::is_fatal) {
+        eprintln!("The grid has errors, not exporting it");
+        std::process::exit(1);
+    }
+
     println!("Exporting nav...");
     std::fs::create_dir_all(&options.output.parent().or_exit_("Invalid output path")).or_exit_e_("Error creating output directory");
-    let nav_file = File::create(&options.output).or_exit_e_("Error creating output file");
-    let encoder = GzEncoder::new(nav_file, Compression::default());
-    let mut writer = BufWriter::new(encoder);
-    for vertex in &nav_grid.vertices {
-        let bytes = [vertex.flags, vertex.extra_edges_and_group];
-        writer.write(&bytes).or_exit_e_("Error serializing vertices");
+    if options.mapped {
+        model::util::save_nav_grid_mapped(&nav_grid, &options.output).or_exit_e_("Error exporting nav");
+    } else if options.regions {
+        model::regions::save_nav_grid_regions(&nav_grid, &options.output).or_exit_e_("Error exporting nav");
+    } else {
+        model::util::save_nav_grid(&nav_grid, &options.output).or_exit_e_("Error exporting nav");
     }
-    ciborium::ser::into_writer(&nav_grid.edges, &mut writer).or_exit_e_("Error serializing edges");
-    ciborium::ser::into_writer(&nav_grid.teleports, &mut writer).or_exit_e_("Error serializing teleports");
 
     println!("Complete");
 }
@@ -150,6 +162,8 @@ struct CustomEdges {
     edges: Vec<CustomEdge>,
     #[serde(default)]
     teleports: Vec<Edge>,
+    #[serde(default)]
+    points_of_interest: Vec<PointOfInterest>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -174,5 +188,6 @@ fn load_custom_edges(nav_grid: &mut NavGrid, file: File) -> serde_yaml::Result<(
         nav_grid.edges.insert(edge.source.index(), edge.edge);
     }
     nav_grid.teleports.append(&mut edges.teleports);
+    nav_grid.points_of_interest.append(&mut edges.points_of_interest);
     Ok(())
 }
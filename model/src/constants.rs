@@ -1,6 +1,10 @@
 pub const HORIZONTAL_REGIONS: u32 = 100;
 pub const VERTICAL_REGIONS: u32 = 200;
-pub const PLANES: u32 = 4;
+pub const GAME_PLANES: u32 = 4;
+#[cfg(not(feature = "surface_only"))]
+pub const PLANES: u32 = GAME_PLANES;
+#[cfg(feature = "surface_only")]
+pub const PLANES: u32 = 1;
 pub const REGION_SIZE: u32 = 64;
 
 pub const WIDTH: u32 = HORIZONTAL_REGIONS * REGION_SIZE;
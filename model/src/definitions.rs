@@ -1,13 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 
 pub use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::Coordinate;
+use crate::{Coordinate, Edge, MultiMap};
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct GameState {
-    #[serde(default)]
+    /// Whether the account is a member on a members world, which is required by [RequirementDefinition::Membership]
+    #[serde(default, alias = "members")]
     pub member: bool,
     #[serde(default)]
     pub skills: HashMap<String, u8>,
@@ -17,19 +22,408 @@ pub struct GameState {
     pub varps: HashMap<u32, i32>,
     #[serde(default)]
     pub varbits: HashMap<u32, i32>,
+    /// Progress of quests by name, quests missing are not started
+    #[serde(default)]
+    pub quests: HashMap<String, QuestState>,
+    /// Highest completed achievement diary tier by region, regions missing have no tier completed
+    #[serde(default)]
+    pub diaries: HashMap<String, DiaryTier>,
+    /// Run energy in percent
+    #[serde(default)]
+    pub run_energy: u8,
+    /// Weight of the carried and worn items in kg
+    #[serde(default)]
+    pub weight: i32,
+    /// Whether requirements on varps and varbits missing from the game state are met
+    #[serde(default)]
+    pub unknown_defaults_to: UnknownPolicy,
+}
+
+impl GameState {
+    pub fn builder() -> GameStateBuilder {
+        GameStateBuilder::default()
+    }
+
+    /// Builds a game state from values read by a client, keeping only those in the data selection.
+    /// Item names are kept if they match any of the selected item patterns, skill names are case insensitive.
+    pub fn from_dump(dump: GameStateDump, selection: &DataSelection) -> Result<GameState, DumpError> {
+        let item_patterns: Vec<Regex> = selection.items.iter().filter_map(|item| Regex::new(item).ok()).collect();
+        let mut game_state = GameState { member: dump.member, ..GameState::default() };
+        for (skill, level) in dump.skills {
+            let skill = skill.to_uppercase();
+            if !selection.skills.iter().any(|s| s.eq_ignore_ascii_case(&skill)) {
+                continue;
+            }
+            let level = u8::try_from(level).ok().filter(|level| (1..=99).contains(level)).ok_or(DumpError::SkillLevel(skill.clone(), level))?;
+            game_state.skills.insert(skill, level);
+        }
+        for (item, quantity) in dump.items {
+            if !item_patterns.iter().any(|pattern| pattern.is_match(&item)) {
+                continue;
+            }
+            let quantity = u32::try_from(quantity).map_err(|_| DumpError::ItemQuantity(item.clone(), quantity))?;
+            game_state.items.insert(item, quantity);
+        }
+        game_state.varps = dump.varps.into_iter().filter(|(index, _)| selection.varps.contains(index)).collect();
+        game_state.varbits = dump.varbits.into_iter().filter(|(index, _)| selection.varbits.contains(index)).collect();
+        game_state.quests = dump.quests.into_iter().filter(|(quest, _)| selection.quests.contains(quest)).collect();
+        game_state.diaries = dump.diaries.into_iter().filter(|(region, _)| selection.diaries.contains(region)).collect();
+        Ok(game_state)
+    }
+
+    /// Total quantity of all items matching the pattern
+    pub fn item_quantity(&self, item: &Regex) -> u32 {
+        self.items.iter()
+            .filter(|(i, _)| item.is_match(i))
+            .map(|(_, q)| q)
+            .sum()
+    }
+}
+
+/// Builds a [GameState] value by value, e.g. from the values a client was able to read
+#[derive(Default)]
+pub struct GameStateBuilder {
+    game_state: GameState,
+}
+
+impl GameStateBuilder {
+    pub fn member(mut self, member: bool) -> GameStateBuilder {
+        self.game_state.member = member;
+        self
+    }
+
+    pub fn skill(mut self, skill: &str, level: u8) -> GameStateBuilder {
+        self.game_state.skills.insert(skill.to_uppercase(), level);
+        self
+    }
+
+    pub fn item(mut self, item: &str, quantity: u32) -> GameStateBuilder {
+        *self.game_state.items.entry(item.to_string()).or_insert(0) += quantity;
+        self
+    }
+
+    pub fn varp(mut self, index: u32, value: i32) -> GameStateBuilder {
+        self.game_state.varps.insert(index, value);
+        self
+    }
+
+    pub fn varbit(mut self, index: u32, value: i32) -> GameStateBuilder {
+        self.game_state.varbits.insert(index, value);
+        self
+    }
+
+    pub fn quest(mut self, name: &str, state: QuestState) -> GameStateBuilder {
+        self.game_state.quests.insert(name.to_string(), state);
+        self
+    }
+
+    pub fn diary(mut self, region: &str, tier: DiaryTier) -> GameStateBuilder {
+        self.game_state.diaries.insert(region.to_string(), tier);
+        self
+    }
+
+    pub fn run_energy(mut self, run_energy: u8) -> GameStateBuilder {
+        self.game_state.run_energy = run_energy;
+        self
+    }
+
+    pub fn weight(mut self, weight: i32) -> GameStateBuilder {
+        self.game_state.weight = weight;
+        self
+    }
+
+    /// Whether requirements on varps and varbits that weren't added are met, not met by default
+    pub fn unknown_defaults_to(mut self, policy: UnknownPolicy) -> GameStateBuilder {
+        self.game_state.unknown_defaults_to = policy;
+        self
+    }
+
+    pub fn build(self) -> GameState {
+        self.game_state
+    }
+}
+
+/// Whether requirements that can't be evaluated, because the game state lacks their value, are assumed to be met
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum UnknownPolicy {
+    Met,
+    #[default]
+    NotMet,
+}
+
+/// Result of evaluating a requirement against a game state that may lack some values
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Evaluation {
+    Met,
+    NotMet,
+    /// Depends on values missing from the game state
+    Unknown,
+}
+
+impl Evaluation {
+    fn of(met: bool) -> Evaluation {
+        if met { Evaluation::Met } else { Evaluation::NotMet }
+    }
+
+    /// Whether the requirement is met, assuming unknown requirements are met according to the policy
+    pub fn resolve(self, policy: UnknownPolicy) -> bool {
+        match self {
+            Evaluation::Met => true,
+            Evaluation::NotMet => false,
+            Evaluation::Unknown => policy == UnknownPolicy::Met,
+        }
+    }
+}
+
+/// Data points of the game state that are needed to evaluate all edges of a NavGrid
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DataSelection {
+    pub varps: HashSet<u32>,
+    pub varbits: HashSet<u32>,
+    /// Item name patterns
+    pub items: HashSet<String>,
+    pub skills: HashSet<String>,
+    pub quests: HashSet<String>,
+    /// Achievement diary regions
+    pub diaries: HashSet<String>,
+}
+
+impl DataSelection {
+    /// Hash of the selected data points of the game state. Game states with equal fingerprints evaluate all
+    /// requirements on the selected data the same way, items are hashed by their total quantity per pattern.
+    pub fn fingerprint(&self, game_state: &GameState) -> u64 {
+        fn sorted<T: Ord>(set: &HashSet<T>) -> Vec<&T> {
+            let mut sorted: Vec<&T> = set.iter().collect();
+            sorted.sort_unstable();
+            sorted
+        }
+        let mut hasher = DefaultHasher::new();
+        (game_state.member, game_state.unknown_defaults_to as u8).hash(&mut hasher);
+        sorted(&self.varps).into_iter().for_each(|index| game_state.varps.get(index).hash(&mut hasher));
+        sorted(&self.varbits).into_iter().for_each(|index| game_state.varbits.get(index).hash(&mut hasher));
+        sorted(&self.items).into_iter().for_each(|item| Regex::new(item).map(|item| game_state.item_quantity(&item)).ok().hash(&mut hasher));
+        sorted(&self.skills).into_iter().for_each(|skill| game_state.skills.get(skill).hash(&mut hasher));
+        sorted(&self.quests).into_iter().for_each(|quest| game_state.quests.get(quest).map(|state| *state as u8).hash(&mut hasher));
+        sorted(&self.diaries).into_iter().for_each(|region| game_state.diaries.get(region).map(|tier| *tier as u8).hash(&mut hasher));
+        hasher.finish()
+    }
+}
+
+/// Game state values as read by a client, before being checked by [GameState::from_dump]
+#[derive(Debug, Default, Deserialize)]
+pub struct GameStateDump {
+    #[serde(default)]
+    pub member: bool,
+    #[serde(default)]
+    pub skills: HashMap<String, i64>,
+    #[serde(default)]
+    pub items: HashMap<String, i64>,
+    #[serde(default)]
+    pub varps: HashMap<u32, i32>,
+    #[serde(default)]
+    pub varbits: HashMap<u32, i32>,
+    #[serde(default)]
+    pub quests: HashMap<String, QuestState>,
+    #[serde(default)]
+    pub diaries: HashMap<String, DiaryTier>,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum DumpError {
+    /// Skill level outside of 1 to 99
+    SkillLevel(String, i64),
+    /// Negative or too large item quantity
+    ItemQuantity(String, i64),
+}
+
+impl Display for DumpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpError::SkillLevel(skill, level) => write!(f, "{} level {} is not between 1 and 99", skill, level),
+            DumpError::ItemQuantity(item, quantity) => write!(f, "{} quantity {} is not a valid quantity", item, quantity),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+/// Ids of the distinct requirement lists of the edges of a NavGrid, see [NavGrid::requirement_ids](crate::NavGrid::requirement_ids).
+/// Edges without requirements have id 0.
+#[derive(Debug)]
+pub struct RequirementIds {
+    /// Distinct requirement lists, positioned by their id
+    lists: Vec<Vec<RequirementDefinition>>,
+    /// Ids of the requirement lists of the edges of every source, in the order of its edges
+    edges: HashMap<u32, Vec<u32>>,
+}
+
+impl RequirementIds {
+    pub fn new(edges: &MultiMap<u32, Edge>) -> RequirementIds {
+        let mut lists = vec![Vec::new()];
+        let mut ids: HashMap<&[RequirementDefinition], u32> = HashMap::from([(&[][..], 0)]);
+        let edges = edges.iter_all().map(|(source, edges)| {
+            let edge_ids = edges.iter().map(|edge| *ids.entry(&edge.requirements).or_insert_with(|| {
+                lists.push(edge.requirements.clone());
+                lists.len() as u32 - 1
+            })).collect();
+            (*source, edge_ids)
+        }).collect();
+        RequirementIds { lists, edges }
+    }
+
+    /// Id of the requirement list of the edge at `position` among the edges of `source`
+    pub fn edge(&self, source: u32, position: usize) -> Option<u32> {
+        self.edges.get(&source)?.get(position).copied()
+    }
+
+    /// Number of distinct requirement lists, including the empty one
+    pub fn count(&self) -> usize {
+        self.lists.len()
+    }
+}
+
+/// Evaluates requirements against a game state for the duration of a search.
+/// Item patterns are matched against every held item, so their quantities are memoized by pattern.
+pub struct RequirementEvaluator<'a> {
+    game_state: &'a GameState,
+    item_quantities: HashMap<&'a str, u32>,
+    ids: Option<&'a RequirementIds>,
+    /// Bit set of the ids whose requirement lists are met, filled in before the first edge is tested
+    lists_met: Vec<u64>,
+    ignore_requirements: bool,
+}
+
+impl<'a> RequirementEvaluator<'a> {
+    pub fn new(game_state: &'a GameState) -> RequirementEvaluator<'a> {
+        RequirementEvaluator { game_state, item_quantities: HashMap::new(), ids: None, lists_met: Vec::new(), ignore_requirements: false }
+    }
+
+    /// Treats all requirements as met if set, e.g. to find out how much unmet requirements cost
+    pub fn ignore_requirements(mut self, ignore_requirements: bool) -> RequirementEvaluator<'a> {
+        self.ignore_requirements = ignore_requirements;
+        self
+    }
+
+    /// Tests edges by the ids of their requirement lists. Every list is evaluated once before the first edge is
+    /// tested, after which testing an edge is a single bit test, however many requirements it has.
+    pub fn requirement_ids(mut self, ids: &'a RequirementIds) -> RequirementEvaluator<'a> {
+        self.ids = Some(ids);
+        self
+    }
+
+    pub fn game_state(&self) -> &'a GameState {
+        self.game_state
+    }
+
+    pub fn is_met(&mut self, requirement: &'a RequirementDefinition) -> bool {
+        if self.ignore_requirements {
+            return true;
+        }
+        match requirement {
+            RequirementDefinition::Item { item, quantity, .. } => {
+                let game_state = self.game_state;
+                *self.item_quantities.entry(item.as_str()).or_insert_with(|| game_state.item_quantity(item)) >= *quantity
+            }
+            RequirementDefinition::Any(requirements) => requirements.iter().any(|req| self.is_met(req)),
+            RequirementDefinition::All(requirements) => self.all_met(requirements),
+            _ => requirement.is_met(self.game_state),
+        }
+    }
+
+    pub fn all_met(&mut self, requirements: &'a [RequirementDefinition]) -> bool {
+        requirements.iter().all(|req| self.is_met(req))
+    }
+
+    /// Whether the `requirements` of the edge at `position` among the edges of `source` are met. Without ids, or
+    /// for edges the ids don't know, the requirements are evaluated one by one.
+    pub fn edge_met(&mut self, source: u32, position: usize, requirements: &'a [RequirementDefinition]) -> bool {
+        if self.ignore_requirements || requirements.is_empty() {
+            return true;
+        }
+        let (ids, id) = match self.ids.and_then(|ids| Some((ids, ids.edge(source, position)? as usize))) {
+            Some(found) => found,
+            None => return self.all_met(requirements),
+        };
+        if self.lists_met.is_empty() {
+            let mut lists_met = vec![0; ids.count().div_ceil(64)];
+            for (id, list) in ids.lists.iter().enumerate() {
+                if self.all_met(list) {
+                    lists_met[id / 64] |= 1 << (id % 64);
+                }
+            }
+            self.lists_met = lists_met;
+        }
+        self.lists_met[id / 64] & (1 << (id % 64)) != 0
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type")]
 pub enum EdgeDefinition {
     Step { position: Coordinate },
-    Door { id: u32, position: Coordinate, #[serde(with = "serde_regex")] action: Regex },
-    GameObject { id: u32, position: Coordinate, #[serde(with = "serde_regex")] action: Regex },
+    /// Door or gate at `position`, opened by choosing `action` on the object `id`
+    Door { id: u32, #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>, position: Coordinate, #[serde(with = "serde_regex")] #[cfg_attr(feature = "schema", schemars(with = "String"))] action: Regex },
+    /// Object at `position`, e.g. a ladder or an agility shortcut, used by choosing `action` on the object `id`
+    GameObject { id: u32, #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>, position: Coordinate, #[serde(with = "serde_regex")] #[cfg_attr(feature = "schema", schemars(with = "String"))] action: Regex },
+    /// NPC found at `position`, e.g. a ferryman or a boat captain, talked to by choosing `action` on the NPC `id`
+    Npc { id: u32, #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>, position: Coordinate, #[serde(with = "serde_regex")] #[cfg_attr(feature = "schema", schemars(with = "String"))] action: Regex },
     SpellTeleport { spell: String },
-    ItemTeleport { #[serde(with = "serde_regex")] item: Regex, #[serde(with = "serde_regex")] action: Regex },
+    ItemTeleport { #[serde(with = "serde_regex")] #[cfg_attr(feature = "schema", schemars(with = "String"))] item: Regex, #[serde(with = "serde_regex")] #[cfg_attr(feature = "schema", schemars(with = "String"))] action: Regex },
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl PartialEq for EdgeDefinition {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (EdgeDefinition::Step { position: p1 }, EdgeDefinition::Step { position: p2 }) => p1 == p2,
+            // Names are only informative, the id identifies the object or NPC
+            (EdgeDefinition::Door { id: i1, position: p1, action: a1, .. }, EdgeDefinition::Door { id: i2, position: p2, action: a2, .. }) |
+            (EdgeDefinition::GameObject { id: i1, position: p1, action: a1, .. }, EdgeDefinition::GameObject { id: i2, position: p2, action: a2, .. }) |
+            (EdgeDefinition::Npc { id: i1, position: p1, action: a1, .. }, EdgeDefinition::Npc { id: i2, position: p2, action: a2, .. }) => {
+                i1 == i2 && p1 == p2 && a1.as_str() == a2.as_str()
+            }
+            (EdgeDefinition::SpellTeleport { spell: s1 }, EdgeDefinition::SpellTeleport { spell: s2 }) => s1 == s2,
+            (EdgeDefinition::ItemTeleport { item: i1, action: a1 }, EdgeDefinition::ItemTeleport { item: i2, action: a2 }) => {
+                i1.as_str() == i2.as_str() && a1.as_str() == a2.as_str()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl EdgeDefinition {
+    /// Name of the door, object or NPC, which [PartialEq] ignores
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            EdgeDefinition::Door { name, .. } | EdgeDefinition::GameObject { name, .. } | EdgeDefinition::Npc { name, .. } => name.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Progress of a quest, ordered from not started to finished
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum QuestState {
+    NotStarted,
+    InProgress,
+    Finished,
+}
+
+/// Achievement diary tier, ordered from easy to elite
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum DiaryTier {
+    Easy,
+    Medium,
+    Hard,
+    Elite,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Compare {
     LT,
     LE,
@@ -53,28 +447,138 @@ impl Compare {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum RequirementDefinition {
     Membership,
     Skill { skill: String, level: u8 },
-    Item { #[serde(with = "serde_regex")] item: Regex, quantity: u32 },
+    Item { #[serde(with = "serde_regex")] #[cfg_attr(feature = "schema", schemars(with = "String"))] item: Regex, quantity: u32, #[serde(default)] consumed: bool },
     Varp { index: u32, value: i32, compare: Compare },
     Varbit { index: u32, value: i32, compare: Compare },
+    /// Met if the quest has progressed at least to the state
+    Quest { name: String, state: QuestState },
+    /// Met if at least the tier of the achievement diary of the region is completed
+    Diary { region: String, tier: DiaryTier },
+    /// Met if any of the nested requirements is met
+    Any(Vec<RequirementDefinition>),
+    /// Met if all of the nested requirements are met
+    All(Vec<RequirementDefinition>),
+}
+
+impl PartialEq for RequirementDefinition {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RequirementDefinition::Membership, RequirementDefinition::Membership) => true,
+            (RequirementDefinition::Skill { skill: s1, level: l1 }, RequirementDefinition::Skill { skill: s2, level: l2 }) => s1 == s2 && l1 == l2,
+            (RequirementDefinition::Item { item: i1, quantity: q1, consumed: c1 }, RequirementDefinition::Item { item: i2, quantity: q2, consumed: c2 }) => {
+                i1.as_str() == i2.as_str() && q1 == q2 && c1 == c2
+            }
+            (RequirementDefinition::Varp { index: i1, value: v1, compare: c1 }, RequirementDefinition::Varp { index: i2, value: v2, compare: c2 }) |
+            (RequirementDefinition::Varbit { index: i1, value: v1, compare: c1 }, RequirementDefinition::Varbit { index: i2, value: v2, compare: c2 }) => {
+                i1 == i2 && v1 == v2 && c1 == c2
+            }
+            (RequirementDefinition::Quest { name: n1, state: s1 }, RequirementDefinition::Quest { name: n2, state: s2 }) => n1 == n2 && s1 == s2,
+            (RequirementDefinition::Diary { region: r1, tier: t1 }, RequirementDefinition::Diary { region: r2, tier: t2 }) => r1 == r2 && t1 == t2,
+            (RequirementDefinition::Any(r1), RequirementDefinition::Any(r2)) |
+            (RequirementDefinition::All(r1), RequirementDefinition::All(r2)) => r1 == r2,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for RequirementDefinition {}
+
+/// Consistent with [PartialEq], so item patterns are hashed by their source
+impl Hash for RequirementDefinition {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            RequirementDefinition::Membership => {}
+            RequirementDefinition::Skill { skill, level } => (skill, level).hash(state),
+            RequirementDefinition::Item { item, quantity, consumed } => (item.as_str(), quantity, consumed).hash(state),
+            RequirementDefinition::Varp { index, value, compare } | RequirementDefinition::Varbit { index, value, compare } => (index, value, compare).hash(state),
+            RequirementDefinition::Quest { name, state: quest_state } => (name, quest_state).hash(state),
+            RequirementDefinition::Diary { region, tier } => (region, tier).hash(state),
+            RequirementDefinition::Any(requirements) | RequirementDefinition::All(requirements) => requirements.hash(state),
+        }
+    }
+}
+
+/// Resolves the plane offset of an instanced destination from the game state
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum PlaneOffset {
+    Varp { index: u32 },
+    Varbit { index: u32 },
+}
+
+impl PlaneOffset {
+    pub fn resolve(&self, game_state: &GameState) -> i32 {
+        match self {
+            PlaneOffset::Varp { index } => game_state.varps.get(index).copied().unwrap_or(0),
+            PlaneOffset::Varbit { index } => game_state.varbits.get(index).copied().unwrap_or(0),
+        }
+    }
 }
 
 impl RequirementDefinition {
+    /// Whether the requirement is met, deciding requirements on missing varps and varbits by the game state's
+    /// [UnknownPolicy]
     pub fn is_met(&self, game_state: &GameState) -> bool {
+        self.evaluate(game_state).resolve(game_state.unknown_defaults_to)
+    }
+
+    /// Evaluates the requirement, which is [Unknown](Evaluation::Unknown) if it depends on varps or varbits missing
+    /// from the game state. Groups are only unknown if the known requirements don't decide them already.
+    pub fn evaluate(&self, game_state: &GameState) -> Evaluation {
+        match self {
+            RequirementDefinition::Membership => Evaluation::of(game_state.member),
+            RequirementDefinition::Skill { skill, level } => Evaluation::of(game_state.skills.get(skill).unwrap_or(&1) >= level),
+            RequirementDefinition::Item { item, quantity, .. } => Evaluation::of(game_state.item_quantity(item) >= *quantity),
+            RequirementDefinition::Varp { index, value, compare } => game_state.varps.get(index).map_or(Evaluation::Unknown, |val| Evaluation::of(compare.test(value, val))),
+            RequirementDefinition::Varbit { index, value, compare } => game_state.varbits.get(index).map_or(Evaluation::Unknown, |val| Evaluation::of(compare.test(value, val))),
+            RequirementDefinition::Quest { name, state } => Evaluation::of(game_state.quests.get(name).unwrap_or(&QuestState::NotStarted) >= state),
+            RequirementDefinition::Diary { region, tier } => Evaluation::of(game_state.diaries.get(region).map_or(false, |completed| completed >= tier)),
+            RequirementDefinition::Any(requirements) => {
+                let evaluations: Vec<Evaluation> = requirements.iter().map(|req| req.evaluate(game_state)).collect();
+                if evaluations.contains(&Evaluation::Met) {
+                    Evaluation::Met
+                } else if evaluations.contains(&Evaluation::Unknown) {
+                    Evaluation::Unknown
+                } else {
+                    Evaluation::NotMet
+                }
+            }
+            RequirementDefinition::All(requirements) => {
+                let evaluations: Vec<Evaluation> = requirements.iter().map(|req| req.evaluate(game_state)).collect();
+                if evaluations.contains(&Evaluation::NotMet) {
+                    Evaluation::NotMet
+                } else if evaluations.contains(&Evaluation::Unknown) {
+                    Evaluation::Unknown
+                } else {
+                    Evaluation::Met
+                }
+            }
+        }
+    }
+
+    /// All requirements nested in [Any](RequirementDefinition::Any) and [All](RequirementDefinition::All) groups,
+    /// or the requirement itself if it isn't a group
+    pub fn leaves(&self) -> Vec<&RequirementDefinition> {
+        match self {
+            RequirementDefinition::Any(requirements) | RequirementDefinition::All(requirements) => {
+                requirements.iter().flat_map(RequirementDefinition::leaves).collect()
+            }
+            _ => vec![self],
+        }
+    }
+
+    /// Mutable counterpart of [leaves](RequirementDefinition::leaves)
+    pub fn leaves_mut(&mut self) -> Vec<&mut RequirementDefinition> {
         match self {
-            RequirementDefinition::Membership => game_state.member,
-            RequirementDefinition::Skill { skill, level } => game_state.skills.get(skill).unwrap_or(&1) >= level,
-            RequirementDefinition::Item { item, quantity } => {
-                let total: u32 = game_state.items.iter()
-                    .filter(|(i, _)| item.is_match(i))
-                    .map(|(_, q)| q)
-                    .sum();
-                total >= *quantity
+            RequirementDefinition::Any(requirements) | RequirementDefinition::All(requirements) => {
+                requirements.iter_mut().flat_map(RequirementDefinition::leaves_mut).collect()
             }
-            RequirementDefinition::Varp { index, value, compare } => game_state.varps.get(index).map(|val| compare.test(value, val)).unwrap_or(false),
-            RequirementDefinition::Varbit { index, value, compare } => game_state.varbits.get(index).map(|val| compare.test(value, val)).unwrap_or(false),
+            _ => vec![self],
         }
     }
 }
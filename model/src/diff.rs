@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{Coordinate, Edge, NavGrid};
+use crate::constants::REGION_SIZE;
+
+/// Changes between two NavGrids, e.g. generated for consecutive game revisions, grouped by the region they are in
+#[derive(Default, Serialize)]
+pub struct NavGridDiff {
+    /// Changes by region id, `x / 64 << 8 | y / 64` like the game's region ids, of all planes
+    pub regions: BTreeMap<u16, RegionDiff>,
+    pub teleports: Vec<EdgeChange>,
+}
+
+#[derive(Default, Serialize)]
+pub struct RegionDiff {
+    /// Tiles that became walkable
+    pub added_tiles: Vec<Coordinate>,
+    /// Tiles that are no longer walkable
+    pub removed_tiles: Vec<Coordinate>,
+    /// Walkable tiles whose movement flags changed
+    pub changed_flags: Vec<FlagChange>,
+    /// Changes of the edges starting in the region
+    pub edges: Vec<EdgeChange>,
+}
+
+#[derive(Serialize)]
+pub struct FlagChange {
+    pub coordinate: Coordinate,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Edges are matched by their source and definition, so an edge whose destination, cost, requirements or name changed
+/// is reported as changed rather than as removed and added
+#[derive(Serialize)]
+#[serde(tag = "change")]
+pub enum EdgeChange {
+    Added { source: Option<Coordinate>, edge: Edge },
+    Removed { source: Option<Coordinate>, edge: Edge },
+    Changed { source: Option<Coordinate>, old: Edge, new: Edge },
+}
+
+impl NavGridDiff {
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty() && self.teleports.is_empty()
+    }
+}
+
+fn region_id(coordinate: &Coordinate) -> u16 {
+    ((coordinate.x / REGION_SIZE as u16) << 8) | (coordinate.y / REGION_SIZE as u16)
+}
+
+/// Compares the walkable tiles, movement flags, edges and teleports of two NavGrids. Groups aren't compared, since
+/// they are renumbered by size whenever the map changes.
+pub fn diff(old: &NavGrid, new: &NavGrid) -> NavGridDiff {
+    let mut diff = NavGridDiff::default();
+    let len = old.vertices.len().max(new.vertices.len());
+    for index in 0..len {
+        let old_flags = old.vertices.get(index).map_or(0, |v| v.flags);
+        let new_flags = new.vertices.get(index).map_or(0, |v| v.flags);
+        if old_flags == new_flags {
+            continue;
+        }
+        let coordinate = Coordinate::from_index(index as u32);
+        let region = diff.regions.entry(region_id(&coordinate)).or_default();
+        match (old_flags, new_flags) {
+            (0, _) => region.added_tiles.push(coordinate),
+            (_, 0) => region.removed_tiles.push(coordinate),
+            (old, new) => region.changed_flags.push(FlagChange { coordinate, old, new }),
+        }
+    }
+
+    let sources = old.edges.keys().chain(new.edges.keys().filter(|source| !old.edges.contains_key(source)));
+    for source in sources {
+        let coordinate = Coordinate::from_index(*source);
+        let changes = diff_edges(Some(coordinate), old.edges.get_vec(source).map_or(&[], Vec::as_slice), new.edges.get_vec(source).map_or(&[], Vec::as_slice));
+        if !changes.is_empty() {
+            diff.regions.entry(region_id(&coordinate)).or_default().edges.extend(changes);
+        }
+    }
+    diff.teleports = diff_edges(None, &old.teleports, &new.teleports);
+    diff
+}
+
+/// Matches each old edge with the first unmatched new edge of the same definition
+fn diff_edges(source: Option<Coordinate>, old: &[Edge], new: &[Edge]) -> Vec<EdgeChange> {
+    let mut changes = Vec::new();
+    let mut matched = vec![false; new.len()];
+    for old_edge in old {
+        let counterpart = (0..new.len()).find(|i| !matched[*i] && new[*i].definition == old_edge.definition);
+        match counterpart {
+            Some(i) => {
+                matched[i] = true;
+                // Edges are equal regardless of the names of objects and NPCs, but clients show them, so a renamed
+                // object is reported as changed as well
+                if *old_edge != new[i] || old_edge.definition.name() != new[i].definition.name() {
+                    changes.push(EdgeChange::Changed { source, old: old_edge.clone(), new: new[i].clone() });
+                }
+            }
+            None => changes.push(EdgeChange::Removed { source, edge: old_edge.clone() }),
+        }
+    }
+    changes.extend(new.iter().zip(matched).filter(|(_, matched)| !matched).map(|(edge, _)| EdgeChange::Added { source, edge: edge.clone() }));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Area;
+    use crate::definitions::{EdgeDefinition, Regex};
+
+    fn c(x: u16, y: u16) -> Coordinate {
+        Coordinate { x, y, plane: 0 }
+    }
+
+    fn grid(door_name: &str, door_cost: u32) -> NavGrid {
+        let door = Edge {
+            destination: c(12, 10),
+            cost: door_cost,
+            definition: EdgeDefinition::Door { id: 1, name: Some(door_name.to_string()), position: c(11, 10), action: Regex::new("Open").unwrap() },
+            requirements: Vec::new(),
+            instance_plane: None,
+            arrival: None,
+        };
+        NavGrid::builder().walkable_area(&Area { min: c(8, 8), max: c(12, 12) }).edge(c(10, 10), door).build()
+    }
+
+    fn edge_changes(diff: &NavGridDiff) -> usize {
+        diff.regions.values().map(|region| region.edges.len()).sum()
+    }
+
+    #[test]
+    fn identical_edges_are_unchanged() {
+        assert!(diff(&grid("Door", 2), &grid("Door", 2)).is_empty());
+    }
+
+    #[test]
+    fn edges_with_another_cost_are_changed() {
+        let diff = diff(&grid("Door", 2), &grid("Door", 3));
+        assert_eq!(edge_changes(&diff), 1);
+        assert!(matches!(diff.regions.values().next().unwrap().edges[0], EdgeChange::Changed { .. }));
+    }
+
+    #[test]
+    fn renamed_edges_are_changed() {
+        let diff = diff(&grid("Door", 2), &grid("Large door", 2));
+        assert_eq!(edge_changes(&diff), 1);
+        assert!(matches!(diff.regions.values().next().unwrap().edges[0], EdgeChange::Changed { .. }));
+    }
+}
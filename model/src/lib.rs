@@ -1,29 +1,56 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::ops::{Index, IndexMut, Range};
+use std::str::FromStr;
+use std::sync::OnceLock;
 
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
 pub use multimap::MultiMap;
 use num_traits::One;
 use serde::{Deserialize, Serialize};
 
 use crate::constants::*;
-use crate::definitions::{EdgeDefinition, RequirementDefinition};
+use crate::definitions::{DataSelection, EdgeDefinition, GameState, PlaneOffset, RequirementDefinition, RequirementIds};
+use crate::regions::RegionVertices;
 
 pub mod definitions;
 pub mod constants;
+pub mod diff;
+pub mod regions;
 pub mod util;
 
 pub struct NavGrid {
-    pub vertices: Vec<Vertex>,
+    pub vertices: Vertices,
     pub edges: MultiMap<u32, Edge>,
     pub teleports: Vec<Edge>,
+    pub points_of_interest: Vec<PointOfInterest>,
+    requirement_ids: OnceLock<RequirementIds>,
 }
 
 impl NavGrid {
     pub fn new() -> NavGrid {
-        NavGrid {
-            vertices: vec![Vertex::default(); (WIDTH * HEIGHT * PLANES) as usize],
-            edges: MultiMap::new(),
-            teleports: Vec::new(),
-        }
+        NavGrid::from_parts(Vertices::Owned(vec![Vertex::default(); (WIDTH * HEIGHT * PLANES) as usize]), MultiMap::new(), Vec::new(), Vec::new())
+    }
+
+    pub fn from_parts(vertices: Vertices, edges: MultiMap<u32, Edge>, teleports: Vec<Edge>, points_of_interest: Vec<PointOfInterest>) -> NavGrid {
+        NavGrid { vertices, edges, teleports, points_of_interest, requirement_ids: OnceLock::new() }
+    }
+
+    /// Ids of the distinct requirement lists of the edges, which searches evaluate once instead of for every edge.
+    /// They are built by the first search, so the edges must not change afterwards.
+    pub fn requirement_ids(&self) -> &RequirementIds {
+        self.requirement_ids.get_or_init(|| RequirementIds::new(&self.edges))
+    }
+
+    /// Builds a small NavGrid in code, e.g. for tests and examples
+    pub fn builder() -> NavGridBuilder {
+        NavGridBuilder::default()
+    }
+
+    /// Points of interest of the kind, e.g. all banks
+    pub fn points_of_interest(&self, kind: PoiKind) -> impl Iterator<Item=&PointOfInterest> {
+        self.points_of_interest.iter().filter(move |poi| poi.kind == kind)
     }
 
     pub fn iter_edges(&self) -> impl Iterator<Item=&Edge> {
@@ -33,8 +60,400 @@ impl NavGrid {
     pub fn iter_edges_mut(&mut self) -> impl Iterator<Item=&mut Edge> {
         self.edges.iter_all_mut().flat_map(|(_, v)| v).chain(self.teleports.iter_mut())
     }
+
+    /// Whether the vertex at `index` can be left in the direction of `flag`. A diagonal move additionally requires both
+    /// orthogonal moves it consists of to be possible in either order, since the player can't cut the corner of a wall.
+    #[inline]
+    pub fn can_move(&self, index: u32, flag: u8, dx: i32, dy: i32) -> bool {
+        // Grids built with NavGrid::builder only have the vertices up to the highest tile used
+        let v = match self.vertices.get(index as usize) {
+            Some(v) => v,
+            None => return false,
+        };
+        if (v.flags & flag) == 0 {
+            return false;
+        }
+        if dx == 0 || dy == 0 {
+            return true;
+        }
+        let flag_x = if dx > 0 { FLAG_E } else { FLAG_W };
+        let flag_y = if dy > 0 { FLAG_N } else { FLAG_S };
+        let flags_at = |i: i32| usize::try_from(i).ok().and_then(|i| self.vertices.get(i)).map_or(0, |v| v.flags);
+        (v.flags & flag_x) != 0 && (v.flags & flag_y) != 0
+            && (flags_at(index as i32 + dx) & flag_y) != 0
+            && (flags_at(index as i32 + WIDTH as i32 * dy) & flag_x) != 0
+    }
+
+    /// Checks the NavGrid for inconsistencies between its vertices, edges, teleports and groups. Grids with
+    /// [fatal](Diagnostic::is_fatal) diagnostics must not be searched. Destinations of edges into instances depend on
+    /// the game state and aren't checked.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            if vertex.has_extra_edges() && !self.edges.contains_key(&(index as u32)) {
+                diagnostics.push(Diagnostic::MissingExtraEdges(Coordinate::from_index(index as u32)));
+            }
+            if vertex.flags != 0 {
+                for (flag, dx, dy) in &DIRECTIONS {
+                    if self.can_move(index as u32, *flag, *dx, *dy) {
+                        self.check_groups(&mut diagnostics, index as u32, (index as i32 + WIDTH as i32 * dy + dx) as u32);
+                    }
+                }
+            }
+        }
+        for (index, edges) in self.edges.iter_all() {
+            let source = Coordinate::from_index(*index);
+            if !self.vertices.get(*index as usize).map_or(false, Vertex::has_extra_edges) {
+                diagnostics.push(Diagnostic::UnflaggedExtraEdges(source));
+            }
+            if self.vertex_at(&source).map_or(true, |vertex| vertex.flags == 0) {
+                diagnostics.push(Diagnostic::EdgeFromBlockedTile(source));
+            }
+            for edge in edges.iter().filter(|edge| edge.instance_plane.is_none()) {
+                let destination = edge.destination;
+                match self.vertex_at(&destination) {
+                    None => diagnostics.push(Diagnostic::EdgeOutOfBounds { source, destination }),
+                    Some(vertex) if vertex.flags == 0 => diagnostics.push(Diagnostic::EdgeToBlockedTile { source, destination }),
+                    Some(_) => self.check_groups(&mut diagnostics, *index, destination.index()),
+                }
+                if let EdgeDefinition::Door { id, .. } = &edge.definition {
+                    let returns = self.edges.get_vec(&destination.index()).into_iter().flatten()
+                        .any(|back| back.destination == source && back.definition == edge.definition);
+                    if !returns {
+                        diagnostics.push(Diagnostic::AsymmetricDoor { source, destination, id: *id });
+                    }
+                }
+            }
+        }
+        for teleport in self.teleports.iter().filter(|teleport| teleport.instance_plane.is_none()) {
+            match self.vertex_at(&teleport.destination) {
+                None => diagnostics.push(Diagnostic::TeleportOutOfBounds(teleport.destination)),
+                Some(vertex) if vertex.flags == 0 => diagnostics.push(Diagnostic::TeleportToBlockedTile(teleport.destination)),
+                Some(_) => {}
+            }
+        }
+        diagnostics
+    }
+
+    /// Vertex of the coordinate, `None` if it lies outside of the map or beyond the vertices of the grid
+    fn vertex_at(&self, coordinate: &Coordinate) -> Option<&Vertex> {
+        if coordinate.validate() { self.vertices.get(coordinate.index() as usize) } else { None }
+    }
+
+    /// Searches skip groups other than the one of the end, so a vertex that can be moved to from another group must
+    /// share its group, as a flood fill would have labeled it
+    fn check_groups(&self, diagnostics: &mut Vec<Diagnostic>, from: u32, to: u32) {
+        let from_group = self.vertices[from as usize].get_group();
+        if let Some(vertex) = self.vertices.get(to as usize).filter(|vertex| vertex.flags != 0 && vertex.get_group() != from_group) {
+            diagnostics.push(Diagnostic::GroupMismatch { from: Coordinate::from_index(from), to: Coordinate::from_index(to), from_group, to_group: vertex.get_group() });
+        }
+    }
+
+    /// Collects the data points of the game state needed to evaluate all edges and teleports
+    pub fn data_selection(&self) -> DataSelection {
+        select_data(self.iter_edges())
+    }
+
+    /// Collects the data points of the game state needed to evaluate the edges and teleports a path starting in the
+    /// group could take. Those are the edges of all groups reachable from it by edges or teleports, regardless of
+    /// their requirements.
+    pub fn group_data_selection(&self, group: u8) -> DataSelection {
+        let mut links: HashMap<u8, HashSet<u8>> = HashMap::new();
+        for (index, edges) in self.edges.iter_all() {
+            let from = self.vertices[*index as usize].get_group();
+            for edge in edges {
+                links.entry(from).or_default().insert(self.vertices[edge.destination.index() as usize].get_group());
+            }
+        }
+        let mut reached: HashSet<u8> = self.teleports.iter().map(|t| self.vertices[t.destination.index() as usize].get_group()).collect();
+        reached.insert(group);
+        let mut queue: Vec<u8> = reached.iter().copied().collect();
+        while let Some(group) = queue.pop() {
+            for next in links.get(&group).into_iter().flatten() {
+                if reached.insert(*next) {
+                    queue.push(*next);
+                }
+            }
+        }
+        let edges = self.edges.iter_all()
+            .filter(|(index, _)| reached.contains(&self.vertices[**index as usize].get_group()))
+            .flat_map(|(_, edges)| edges);
+        select_data(edges.chain(self.teleports.iter()))
+    }
+
+    /// Hash of the data points of the game state that paths starting in the group can depend on, so requests whose
+    /// game states only differ in irrelevant data share it. Collecting the data points takes a pass over all edges, so
+    /// callers fingerprinting many game states should keep the [group_data_selection](NavGrid::group_data_selection)
+    /// and use [DataSelection::fingerprint].
+    pub fn relevant_state_fingerprint(&self, game_state: &GameState, group: u8) -> u64 {
+        self.group_data_selection(group).fingerprint(game_state)
+    }
+}
+
+/// Defines the walkable tiles, edges and teleports of a NavGrid. Only the vertices up to the highest tile used are
+/// allocated instead of the whole map, so a grid of a few tiles takes a few kilobytes if they are placed near the origin.
+/// The vertices of such a grid are shorter than [WIDTH] * [HEIGHT] * [PLANES], coordinates beyond them must not be
+/// searched.
+#[derive(Default)]
+pub struct NavGridBuilder {
+    /// Movement flags of the walkable tiles, `None` to derive them from the walkable neighbors
+    tiles: BTreeMap<u32, Option<u8>>,
+    edges: Vec<(Coordinate, Edge)>,
+    teleports: Vec<Edge>,
+    points_of_interest: Vec<PointOfInterest>,
+}
+
+impl NavGridBuilder {
+    /// Makes the tile walkable, it can be moved to from and left to its walkable neighbors
+    pub fn walkable(mut self, coordinate: Coordinate) -> NavGridBuilder {
+        self.tiles.entry(coordinate.index()).or_insert(None);
+        self
+    }
+
+    /// Makes all tiles of the area walkable
+    pub fn walkable_area(mut self, area: &Area) -> NavGridBuilder {
+        for y in area.min.y..=area.max.y {
+            for x in area.min.x..=area.max.x {
+                self = self.walkable(Coordinate { x, y, plane: area.min.plane });
+            }
+        }
+        self
+    }
+
+    /// Makes the tile walkable with the movement flags given, instead of the ones derived from its neighbors,
+    /// e.g. to place a wall or a one-way passage
+    pub fn flags(mut self, coordinate: Coordinate, flags: u8) -> NavGridBuilder {
+        self.tiles.insert(coordinate.index(), Some(flags));
+        self
+    }
+
+    /// Adds an edge leaving the tile at `source`
+    pub fn edge(mut self, source: Coordinate, edge: Edge) -> NavGridBuilder {
+        self.edges.push((source, edge));
+        self
+    }
+
+    pub fn teleport(mut self, teleport: Edge) -> NavGridBuilder {
+        self.teleports.push(teleport);
+        self
+    }
+
+    pub fn point_of_interest(mut self, poi: PointOfInterest) -> NavGridBuilder {
+        self.points_of_interest.push(poi);
+        self
+    }
+
+    /// Derives the flags of the tiles and flags the sources of edges. All walkable tiles are in group 1, searches don't
+    /// tell unreachable ends early unless the groups are labeled afterwards.
+    pub fn build(self) -> NavGrid {
+        let len = self.tiles.keys().copied()
+            .chain(self.edges.iter().flat_map(|(source, edge)| [source.index(), edge.destination.index()]))
+            .chain(self.teleports.iter().map(|teleport| teleport.destination.index()))
+            .chain(self.points_of_interest.iter().map(|poi| poi.position.index()))
+            .max()
+            .map_or(0, |index| index as usize + 1);
+        let mut vertices = vec![Vertex::default(); len];
+        for (index, flags) in &self.tiles {
+            let flags = flags.unwrap_or_else(|| {
+                let coordinate = Coordinate::from_index(*index);
+                DIRECTIONS.iter()
+                    .filter(|(_, dx, dy)| {
+                        let (x, y) = (coordinate.x as i32 + dx, coordinate.y as i32 + dy);
+                        let neighbor = Coordinate { x: x as u16, y: y as u16, plane: coordinate.plane };
+                        x >= 0 && y >= 0 && neighbor.validate() && self.tiles.contains_key(&neighbor.index())
+                    })
+                    .fold(0, |flags, (flag, _, _)| flags | flag)
+            });
+            let vertex = &mut vertices[*index as usize];
+            vertex.flags = flags;
+            vertex.set_group(1);
+        }
+        let mut edges = MultiMap::new();
+        for (source, edge) in self.edges {
+            vertices[source.index() as usize].set_extra_edges(true);
+            edges.insert(source.index(), edge);
+        }
+        NavGrid::from_parts(Vertices::Owned(vertices), edges, self.teleports, self.points_of_interest)
+    }
+}
+
+fn select_data<'a>(edges: impl Iterator<Item=&'a Edge>) -> DataSelection {
+    let mut data_selection = DataSelection::default();
+    for edge in edges {
+        match &edge.instance_plane {
+            Some(PlaneOffset::Varp { index }) => data_selection.varps.insert(*index),
+            Some(PlaneOffset::Varbit { index }) => data_selection.varbits.insert(*index),
+            None => false,
+        };
+        edge.requirements.iter().flat_map(RequirementDefinition::leaves).for_each(|r| {
+            match r {
+                RequirementDefinition::Varp { index, .. } => data_selection.varps.insert(*index),
+                RequirementDefinition::Varbit { index, .. } => data_selection.varbits.insert(*index),
+                RequirementDefinition::Item { item, .. } => data_selection.items.insert(item.to_string()),
+                RequirementDefinition::Skill { skill, .. } => data_selection.skills.insert(skill.clone()),
+                RequirementDefinition::Quest { name, .. } => data_selection.quests.insert(name.clone()),
+                RequirementDefinition::Diary { region, .. } => data_selection.diaries.insert(region.clone()),
+                _ => false
+            };
+        });
+    }
+    data_selection
+}
+
+/// Inconsistency found by [NavGrid::validate]
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Diagnostic {
+    /// Vertex is flagged to have extra edges, but has none
+    MissingExtraEdges(Coordinate),
+    /// Vertex has extra edges, but isn't flagged to have any, so they are never taken
+    UnflaggedExtraEdges(Coordinate),
+    /// Vertex has extra edges, but isn't walkable, so the player can't get there to take them
+    EdgeFromBlockedTile(Coordinate),
+    /// Edge leads outside of the map or beyond the vertices of the grid, searches taking it would index out of bounds
+    EdgeOutOfBounds { source: Coordinate, destination: Coordinate },
+    /// Edge leads to a tile that isn't walkable, where paths taking it get stuck
+    EdgeToBlockedTile { source: Coordinate, destination: Coordinate },
+    /// Door can only be opened from one side, the destination lacks the same door leading back to the source
+    AsymmetricDoor { source: Coordinate, destination: Coordinate, id: u32 },
+    /// Teleport leads outside of the map or beyond the vertices of the grid
+    TeleportOutOfBounds(Coordinate),
+    /// Teleport lands on a tile that isn't walkable
+    TeleportToBlockedTile(Coordinate),
+    /// Vertex can be walked to or reached by an edge from a vertex of another group, so searches between them are
+    /// wrongly ruled out
+    GroupMismatch { from: Coordinate, to: Coordinate, from_group: u8, to_group: u8 },
+}
+
+impl Diagnostic {
+    /// Whether searches on the grid can fail, rather than return worse paths or miss some
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Diagnostic::EdgeOutOfBounds { .. } | Diagnostic::TeleportOutOfBounds(_))
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::MissingExtraEdges(c) => write!(f, "{} is flagged to have extra edges, but has none", c),
+            Diagnostic::UnflaggedExtraEdges(c) => write!(f, "{} has extra edges, but isn't flagged to have any", c),
+            Diagnostic::EdgeFromBlockedTile(c) => write!(f, "{} has edges, but isn't walkable", c),
+            Diagnostic::EdgeOutOfBounds { source, destination } => write!(f, "{} has an edge to {}, which is out of bounds", source, destination),
+            Diagnostic::EdgeToBlockedTile { source, destination } => write!(f, "{} has an edge to {}, which isn't walkable", source, destination),
+            Diagnostic::AsymmetricDoor { source, destination, id } => write!(f, "door {} leads from {} to {}, but not back", id, source, destination),
+            Diagnostic::TeleportOutOfBounds(c) => write!(f, "teleport to {} is out of bounds", c),
+            Diagnostic::TeleportToBlockedTile(c) => write!(f, "teleport to {} lands on a tile that isn't walkable", c),
+            Diagnostic::GroupMismatch { from, to, from_group, to_group } => write!(f, "{} of group {} leads to {} of group {}", from, from_group, to, to_group),
+        }
+    }
+}
+
+/// Vertices of a NavGrid, either allocated, memory-mapped from a file opened with [NavGrid::open_mmap] or read by
+/// region from a file opened with [NavGrid::load_regions]
+pub enum Vertices {
+    Owned(Vec<Vertex>),
+    /// Copy-on-write mapping of `len` vertices starting at `offset`, modifications aren't written to the file
+    #[cfg(feature = "mmap")]
+    Mapped { map: MmapMut, offset: usize, len: usize },
+    /// Read-only vertices of the regions selected when the file was opened, other vertices are blocked
+    Regions(RegionVertices),
+}
+
+impl Vertices {
+    pub fn len(&self) -> usize {
+        match self {
+            Vertices::Owned(vertices) => vertices.len(),
+            #[cfg(feature = "mmap")]
+            Vertices::Mapped { len, .. } => *len,
+            Vertices::Regions(_) => (WIDTH * HEIGHT * PLANES) as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Vertex> {
+        match self {
+            Vertices::Owned(vertices) => vertices.get(index),
+            // Vertex is two bytes without alignment requirements, so any byte range can be viewed as vertices
+            #[cfg(feature = "mmap")]
+            Vertices::Mapped { map, offset, len } => (index < *len).then(|| unsafe { &*(map[*offset + index * 2..].as_ptr() as *const Vertex) }),
+            Vertices::Regions(regions) => regions.get(index),
+        }
+    }
+
+    pub fn iter(&self) -> VertexIter<'_> {
+        VertexIter { vertices: self, range: 0..self.len() }
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Vertex> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Panics for vertices opened with [NavGrid::load_regions], which are read-only and not contiguous in memory
+    pub fn as_mut_slice(&mut self) -> &mut [Vertex] {
+        match self {
+            Vertices::Owned(vertices) => vertices,
+            #[cfg(feature = "mmap")]
+            Vertices::Mapped { map, offset, len } => unsafe { std::slice::from_raw_parts_mut(map[*offset..*offset + *len * 2].as_mut_ptr() as *mut Vertex, *len) },
+            Vertices::Regions(_) => panic!("Vertices read by region are read-only"),
+        }
+    }
+}
+
+impl Index<usize> for Vertices {
+    type Output = Vertex;
+
+    fn index(&self, index: usize) -> &Vertex {
+        match self.get(index) {
+            Some(vertex) => vertex,
+            None => panic!("Vertex index {} out of range for {} vertices", index, self.len()),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vertices {
+    fn index_mut(&mut self, index: usize) -> &mut Vertex {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+pub struct VertexIter<'a> {
+    vertices: &'a Vertices,
+    range: Range<usize>,
+}
+
+impl<'a> Iterator for VertexIter<'a> {
+    type Item = &'a Vertex;
+
+    fn next(&mut self) -> Option<&'a Vertex> {
+        self.range.next().map(|index| &self.vertices[index])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'a> IntoIterator for &'a Vertices {
+    type Item = &'a Vertex;
+    type IntoIter = VertexIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
+impl<'a> IntoIterator for &'a mut Vertices {
+    type Item = &'a mut Vertex;
+    type IntoIter = std::slice::IterMut<'a, Vertex>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[repr(C)]
 #[derive(Default, Eq, PartialEq, Clone, Copy, Debug)]
 pub struct Vertex {
     pub flags: u8,
@@ -59,7 +478,9 @@ impl Vertex {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Edges are equal if they lead to the same destination for the same cost and requirements, with definitions equal
+/// as defined by [EdgeDefinition]'s [PartialEq]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Edge {
     pub destination: Coordinate,
     #[serde(default = "u32::one")]
@@ -67,9 +488,30 @@ pub struct Edge {
     pub definition: EdgeDefinition,
     #[serde(default)]
     pub requirements: Vec<RequirementDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_plane: Option<PlaneOffset>,
+    /// Area the player ends up anywhere in after taking the edge, e.g. for teleports landing on a random tile.
+    /// Clients can treat the step as done once the player is inside. If absent, the player ends up on the destination.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arrival: Option<Area>,
+}
+
+impl Edge {
+    /// Destination of this edge, with the plane shifted by the instance offset if the edge leads into an instance
+    pub fn resolve_destination(&self, game_state: &GameState) -> Option<Coordinate> {
+        match &self.instance_plane {
+            None => Some(self.destination),
+            Some(offset) => {
+                let plane = self.destination.plane as i32 + offset.resolve(game_state);
+                let destination = Coordinate { plane: u8::try_from(plane).ok()?, ..self.destination };
+                if destination.validate() { Some(destination) } else { None }
+            }
+        }
+    }
 }
 
 #[derive(Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Coordinate {
     pub x: u16,
     pub y: u16,
@@ -113,6 +555,20 @@ impl Coordinate {
         self.x < WIDTH as u16 && self.y < HEIGHT as u16 && self.plane < PLANES as u8
     }
 
+    /// Wilderness level, increasing every 8 tiles to the north on the surface and in the dungeons below it.
+    /// 0 outside of the Wilderness.
+    pub fn wilderness_level(&self) -> u8 {
+        if !(2944..3392).contains(&self.x) {
+            return 0;
+        }
+        let south_edge = match self.y {
+            3520..=3967 => 3520,
+            9920..=10367 => 9920,
+            _ => return 0,
+        };
+        ((self.y - south_edge) / 8 + 1) as u8
+    }
+
     pub fn derive(&self, dx: i16, dy: i16, dplane: i8) -> Coordinate {
         Coordinate {
             x: (self.x as i16 + dx) as u16,
@@ -133,3 +589,212 @@ impl Display for Coordinate {
         write!(f, "({}, {}, {})", self.x, self.y, self.plane)
     }
 }
+
+/// Place on the map paths can be searched to by its kind, e.g. a bank. Points of interest are stored in the NavGrid,
+/// and can be extended by a sidecar file next to it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PointOfInterest {
+    pub kind: PoiKind,
+    /// Tile to stand on to use it, e.g. in front of a bank booth
+    pub position: Coordinate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum PoiKind {
+    Bank,
+    Altar,
+    Furnace,
+    Anvil,
+    FairyRing,
+    SpiritTree,
+}
+
+impl PoiKind {
+    pub const ALL: [PoiKind; 6] = [PoiKind::Bank, PoiKind::Altar, PoiKind::Furnace, PoiKind::Anvil, PoiKind::FairyRing, PoiKind::SpiritTree];
+
+    /// Name the kind is queried by, e.g. `fairy_ring`
+    pub fn tag(&self) -> &'static str {
+        match self {
+            PoiKind::Bank => "bank",
+            PoiKind::Altar => "altar",
+            PoiKind::Furnace => "furnace",
+            PoiKind::Anvil => "anvil",
+            PoiKind::FairyRing => "fairy_ring",
+            PoiKind::SpiritTree => "spirit_tree",
+        }
+    }
+}
+
+impl FromStr for PoiKind {
+    type Err = String;
+
+    /// Parses the tag of a kind, ignoring case and underscores so that the variant names are accepted too
+    fn from_str(s: &str) -> Result<PoiKind, String> {
+        let normalized: String = s.chars().filter(|c| *c != '_').map(|c| c.to_ascii_lowercase()).collect();
+        PoiKind::ALL.into_iter()
+            .find(|kind| kind.tag().replace('_', "") == normalized)
+            .ok_or_else(|| format!("Unknown point of interest: {}", s))
+    }
+}
+
+/// Rectangle of tiles on the plane of `min`, including the tiles at `min` and `max`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Area {
+    pub min: Coordinate,
+    pub max: Coordinate,
+}
+
+impl Area {
+    pub fn contains(&self, coordinate: &Coordinate) -> bool {
+        coordinate.plane == self.min.plane
+            && (self.min.x..=self.max.x).contains(&coordinate.x)
+            && (self.min.y..=self.max.y).contains(&coordinate.y)
+    }
+
+    pub fn validate(&self) -> bool {
+        self.min.validate() && self.max.validate() && self.min.plane == self.max.plane && self.min.x <= self.max.x && self.min.y <= self.max.y
+    }
+}
+
+impl Display for Area {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::Regex;
+
+    fn c(x: u16, y: u16) -> Coordinate {
+        Coordinate { x, y, plane: 0 }
+    }
+
+    /// Corridor from (10, 10) to (14, 10) with a door from its east end back to its west end
+    fn corridor_with_door() -> NavGrid {
+        let door = Edge {
+            destination: c(10, 10),
+            cost: 2,
+            definition: EdgeDefinition::Door { id: 1, name: None, position: c(14, 10), action: Regex::new("Open").unwrap() },
+            requirements: Vec::new(),
+            instance_plane: None,
+            arrival: None,
+        };
+        NavGrid::builder().walkable_area(&Area { min: c(10, 10), max: c(14, 10) }).edge(c(14, 10), door).build()
+    }
+
+    #[test]
+    fn edges_tested_by_requirement_ids_are_met_like_their_requirements() {
+        use crate::definitions::{Compare, DiaryTier, QuestState, RequirementEvaluator};
+        let skill = RequirementDefinition::Skill { skill: "AGILITY".to_string(), level: 50 };
+        let varbit = RequirementDefinition::Varbit { index: 4, value: 1, compare: Compare::EQ };
+        let quest = RequirementDefinition::Quest { name: "Dragon Slayer I".to_string(), state: QuestState::Finished };
+        let diary = RequirementDefinition::Diary { region: "Varrock".to_string(), tier: DiaryTier::Hard };
+        let item = RequirementDefinition::Item { item: Regex::new("Coins").unwrap(), quantity: 10, consumed: true };
+        let lists = [
+            vec![skill.clone()],
+            vec![varbit.clone(), item.clone()],
+            vec![RequirementDefinition::Any(vec![quest.clone(), diary.clone()])],
+            vec![RequirementDefinition::All(vec![skill.clone(), quest.clone()]), RequirementDefinition::Membership],
+            vec![skill.clone()],
+            Vec::new(),
+        ];
+        let mut builder = NavGrid::builder().walkable_area(&Area { min: c(10, 10), max: c(14, 10) });
+        for requirements in &lists {
+            let mut edge = corridor_with_door().edges.get(&c(14, 10).index()).unwrap().clone();
+            edge.requirements = requirements.clone();
+            builder = builder.edge(c(14, 10), edge);
+        }
+        let nav_grid = builder.build();
+        let source = c(14, 10).index();
+        let ids = nav_grid.requirement_ids();
+        assert_eq!(ids.count(), 5);
+        assert_eq!(ids.edge(source, 0), ids.edge(source, 4));
+        assert_eq!(ids.edge(source, 5), Some(0));
+        let game_states = [
+            GameState::default(),
+            GameState::builder().skill("Agility", 50).varbit(4, 1).item("Coins", 10).member(true).build(),
+            GameState::builder().skill("Agility", 50).quest("Dragon Slayer I", QuestState::Finished).build(),
+            GameState::builder().diary("Varrock", DiaryTier::Elite).item("Coins", 9).build(),
+        ];
+        for game_state in &game_states {
+            let mut evaluator = RequirementEvaluator::new(game_state).requirement_ids(ids);
+            for (position, requirements) in lists.iter().enumerate() {
+                let met = requirements.iter().all(|requirement| requirement.is_met(game_state));
+                assert_eq!(evaluator.edge_met(source, position, requirements), met, "{:?} with {:?}", requirements, game_state);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_reports_extra_edge_flags_without_edges() {
+        let mut nav_grid = corridor_with_door();
+        nav_grid.vertices[c(12, 10).index() as usize].set_extra_edges(true);
+        assert!(nav_grid.validate().contains(&Diagnostic::MissingExtraEdges(c(12, 10))));
+        assert!(!nav_grid.validate().iter().any(Diagnostic::is_fatal));
+    }
+
+    #[test]
+    fn validate_reports_edges_without_flag() {
+        let mut nav_grid = corridor_with_door();
+        nav_grid.vertices[c(14, 10).index() as usize].set_extra_edges(false);
+        assert!(nav_grid.validate().contains(&Diagnostic::UnflaggedExtraEdges(c(14, 10))));
+        assert!(!nav_grid.validate().iter().any(Diagnostic::is_fatal));
+    }
+
+    #[test]
+    fn validate_reports_doors_opening_one_way() {
+        let nav_grid = corridor_with_door();
+        assert!(nav_grid.validate().contains(&Diagnostic::AsymmetricDoor { source: c(14, 10), destination: c(10, 10), id: 1 }));
+        let door = nav_grid.edges.get(&c(14, 10).index()).unwrap().clone();
+        let back = Edge { destination: c(14, 10), ..door.clone() };
+        let nav_grid = NavGrid::builder().walkable_area(&Area { min: c(10, 10), max: c(14, 10) }).edge(c(14, 10), door).edge(c(10, 10), back).build();
+        assert_eq!(nav_grid.validate(), []);
+    }
+
+    #[test]
+    fn validate_reports_edges_and_teleports_leading_nowhere() {
+        let door = corridor_with_door().edges.get(&c(14, 10).index()).unwrap().clone();
+        let teleport = Edge { definition: EdgeDefinition::SpellTeleport { spell: "Home Teleport".to_string() }, ..door.clone() };
+        let out_of_bounds = Coordinate { x: WIDTH as u16, y: 10, plane: 0 };
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&Area { min: c(10, 10), max: c(14, 10) })
+            .edge(c(14, 10), Edge { destination: c(12, 9), ..door.clone() })
+            .edge(c(10, 10), Edge { destination: out_of_bounds, ..door.clone() })
+            .edge(c(11, 9), Edge { destination: c(11, 10), ..door })
+            .teleport(Edge { destination: c(13, 9), ..teleport.clone() })
+            .teleport(Edge { destination: out_of_bounds, ..teleport })
+            .build();
+        let diagnostics = nav_grid.validate();
+        let fatal: Vec<&Diagnostic> = diagnostics.iter().filter(|diagnostic| diagnostic.is_fatal()).collect();
+        assert_eq!(fatal, [&Diagnostic::EdgeOutOfBounds { source: c(10, 10), destination: out_of_bounds }, &Diagnostic::TeleportOutOfBounds(out_of_bounds)]);
+        assert!(diagnostics.contains(&Diagnostic::EdgeToBlockedTile { source: c(14, 10), destination: c(12, 9) }));
+        assert!(diagnostics.contains(&Diagnostic::EdgeFromBlockedTile(c(11, 9))));
+        assert!(diagnostics.contains(&Diagnostic::TeleportToBlockedTile(c(13, 9))));
+    }
+
+    #[test]
+    fn validate_reports_tiles_reachable_from_other_groups() {
+        let mut nav_grid = corridor_with_door();
+        nav_grid.vertices[c(12, 10).index() as usize].set_group(2);
+        let diagnostics = nav_grid.validate();
+        assert!(diagnostics.contains(&Diagnostic::GroupMismatch { from: c(11, 10), to: c(12, 10), from_group: 1, to_group: 2 }));
+        assert!(diagnostics.contains(&Diagnostic::GroupMismatch { from: c(12, 10), to: c(13, 10), from_group: 2, to_group: 1 }));
+        assert!(!diagnostics.iter().any(Diagnostic::is_fatal));
+    }
+
+    #[test]
+    #[cfg(feature = "surface_only")]
+    fn surface_only_rejects_upper_planes() {
+        assert!(Coordinate { x: 3222, y: 3218, plane: 0 }.validate());
+        assert!(!Coordinate { x: 3222, y: 3218, plane: 1 }.validate());
+        let upstairs = Area { min: Coordinate { x: 3205, y: 3209, plane: 1 }, max: Coordinate { x: 3212, y: 3225, plane: 1 } };
+        assert!(!upstairs.validate());
+    }
+}
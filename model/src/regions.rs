@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::ptr::null_mut;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use flate2::{Compression, CrcReader, CrcWriter};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use multimap::MultiMap;
+
+use crate::{Coordinate, Edge, NavGrid, Vertex, Vertices};
+use crate::constants::*;
+use crate::util::{HEADER_SIZE, Header, NavGridError};
+
+/// Identifies region-sharded NavGrid files written by [save_nav_grid_regions]
+pub const REGIONS_MAGIC: [u8; 4] = *b"ONRG";
+const REGION_VERTICES: usize = (REGION_SIZE * REGION_SIZE) as usize;
+/// Regions of all game planes, which are always present in files so they can be read by any build
+const FILE_REGIONS: usize = (HORIZONTAL_REGIONS * VERTICAL_REGIONS * GAME_PLANES) as usize;
+const REGIONS: usize = (HORIZONTAL_REGIONS * VERTICAL_REGIONS * PLANES) as usize;
+const ENTRY_SIZE: usize = 16;
+const BLOCKED: Vertex = Vertex { flags: 0, extra_edges_and_group: 0 };
+
+type Region = [Vertex; REGION_VERTICES];
+
+/// Seekable source of a region-sharded file, e.g. a file or a downloaded file in memory
+pub trait RegionSource: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> RegionSource for T {}
+
+/// Position of the gzip compressed vertices and edges of a region in the file, regions without walkable tiles or
+/// edges have empty blocks
+#[derive(Clone, Copy, Default)]
+struct RegionEntry {
+    offset: u64,
+    vertices_len: u32,
+    edges_len: u32,
+}
+
+impl RegionEntry {
+    fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.vertices_len.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.edges_len.to_le_bytes());
+        bytes
+    }
+
+    fn parse(bytes: &[u8; ENTRY_SIZE]) -> RegionEntry {
+        RegionEntry {
+            offset: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            vertices_len: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            edges_len: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+/// Region id like the game's, `x / 64 << 8 | y / 64`, of the region at `index` in the order regions are stored.
+/// Regions of all planes share their id.
+fn region_id(index: usize) -> u16 {
+    let x = index as u32 % HORIZONTAL_REGIONS;
+    let y = index as u32 / HORIZONTAL_REGIONS % VERTICAL_REGIONS;
+    (x << 8 | y) as u16
+}
+
+/// Index of the region containing the vertex and the offset of the vertex within it, regions are stored plane by
+/// plane, row by row from the south-west corner
+fn locate(vertex_index: usize) -> (usize, usize) {
+    let x = vertex_index as u32 % WIDTH;
+    let y = vertex_index as u32 / WIDTH;
+    let region = (y / REGION_SIZE) * HORIZONTAL_REGIONS + x / REGION_SIZE;
+    (region as usize, ((y % REGION_SIZE) * REGION_SIZE + x % REGION_SIZE) as usize)
+}
+
+fn vertex_index(region: usize, offset: usize) -> usize {
+    let x = region as u32 % HORIZONTAL_REGIONS * REGION_SIZE + offset as u32 % REGION_SIZE;
+    let y = region as u32 / HORIZONTAL_REGIONS * REGION_SIZE + offset as u32 / REGION_SIZE;
+    (y * WIDTH + x) as usize
+}
+
+/// Ids of all regions of the map, e.g. to open a whole region-sharded file with [NavGrid::load_regions]
+pub fn all_region_ids() -> Vec<u16> {
+    (0..(HORIZONTAL_REGIONS * VERTICAL_REGIONS) as usize).map(region_id).collect()
+}
+
+/// Vertices of a NavGrid opened with [NavGrid::load_regions]. Each selected region is read from the file the first
+/// time one of its vertices is accessed and kept until the NavGrid is dropped, vertices of other regions are blocked.
+pub struct RegionVertices {
+    source: Mutex<Box<dyn RegionSource>>,
+    /// Entries of the selected regions, the entries of other regions are empty
+    entries: Vec<RegionEntry>,
+    /// Regions read so far, null if not read yet. Regions are never replaced or freed before the vertices are dropped.
+    regions: Vec<AtomicPtr<Region>>,
+}
+
+impl RegionVertices {
+    pub fn get(&self, index: usize) -> Option<&Vertex> {
+        if index >= (WIDTH * HEIGHT * PLANES) as usize {
+            return None;
+        }
+        let (region, offset) = locate(index);
+        if self.entries[region].vertices_len == 0 {
+            return Some(&BLOCKED);
+        }
+        let mut loaded = self.regions[region].load(Ordering::Acquire);
+        if loaded.is_null() {
+            // The blocks were checked to lie within the file when it was opened, so this only fails for corrupt blocks
+            // or I/O errors, which shouldn't fail every search reaching the region
+            let vertices = self.read_vertices(region).unwrap_or_else(|e| {
+                eprintln!("Error reading region {}, treating it as blocked: {}", region_id(region), e);
+                Box::new([BLOCKED; REGION_VERTICES])
+            });
+            let vertices = Box::into_raw(vertices);
+            loaded = match self.regions[region].compare_exchange(null_mut(), vertices, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => vertices,
+                // Another thread read the region first
+                Err(existing) => {
+                    drop(unsafe { Box::from_raw(vertices) });
+                    existing
+                }
+            };
+        }
+        // The region is only freed once the vertices are dropped, which can't happen while they are borrowed
+        Some(unsafe { &(*loaded)[offset] })
+    }
+
+    /// Number of regions read from the file so far
+    pub fn loaded_regions(&self) -> usize {
+        self.regions.iter().filter(|region| !region.load(Ordering::Relaxed).is_null()).count()
+    }
+
+    fn read_vertices(&self, region: usize) -> Result<Box<Region>, NavGridError> {
+        let entry = self.entries[region];
+        let mut source = self.source.lock().unwrap_or_else(|e| e.into_inner());
+        source.seek(SeekFrom::Start(entry.offset))?;
+        let mut decoder = GzDecoder::new((&mut **source).take(entry.vertices_len as u64));
+        let mut vertices = Box::new([BLOCKED; REGION_VERTICES]);
+        for vertex in vertices.iter_mut() {
+            let mut buf = [0; 2];
+            decoder.read_exact(&mut buf).map_err(|_| NavGridError::Truncated)?;
+            vertex.flags = buf[0];
+            vertex.extra_edges_and_group = buf[1];
+        }
+        Ok(vertices)
+    }
+}
+
+impl Drop for RegionVertices {
+    fn drop(&mut self) {
+        for region in &mut self.regions {
+            let loaded = *region.get_mut();
+            if !loaded.is_null() {
+                drop(unsafe { Box::from_raw(loaded) });
+            }
+        }
+    }
+}
+
+impl NavGrid {
+    /// Opens a NavGrid file written by [save_nav_grid_regions], limited to the regions with the given ids, which
+    /// include all planes of each region. Only the edges starting in the regions are decoded up front, their vertices
+    /// are read when a search first reaches them. Tiles outside the regions are blocked, and edges, teleports and
+    /// points of interest leading outside them are dropped.
+    /// The file must not be modified while the NavGrid is open.
+    pub fn load_regions(path: impl AsRef<Path>, region_ids: &[u16]) -> Result<NavGrid, NavGridError> {
+        NavGrid::read_regions(BufReader::new(File::open(path)?), region_ids)
+    }
+
+    /// Like [NavGrid::load_regions], but reads the file from any seekable source, e.g. a download kept in memory
+    pub fn read_regions(mut source: impl RegionSource + 'static, region_ids: &[u16]) -> Result<NavGrid, NavGridError> {
+        let mut bytes = [0; HEADER_SIZE];
+        source.read_exact(&mut bytes).map_err(|_| NavGridError::NotANavGrid)?;
+        let header = Header::parse(&bytes, REGIONS_MAGIC)?;
+
+        let mut reader = CrcReader::new(&mut source);
+        let mut len = [0; 4];
+        reader.read_exact(&mut len).map_err(|_| NavGridError::Truncated)?;
+        let mut globals = vec![0; u32::from_le_bytes(len) as usize];
+        reader.read_exact(&mut globals).map_err(|_| NavGridError::Truncated)?;
+        let mut table = vec![0; FILE_REGIONS * ENTRY_SIZE];
+        reader.read_exact(&mut table).map_err(|_| NavGridError::Truncated)?;
+        let checksum = reader.crc().sum();
+        if checksum != header.checksum {
+            return Err(NavGridError::Checksum { expected: header.checksum, actual: checksum });
+        }
+
+        let selected: HashSet<u16> = region_ids.iter().copied().collect();
+        let entries: Vec<RegionEntry> = table.chunks_exact(ENTRY_SIZE).take(REGIONS).enumerate()
+            .map(|(index, bytes)| match selected.contains(&region_id(index)) {
+                true => RegionEntry::parse(bytes.try_into().unwrap()),
+                false => RegionEntry::default(),
+            })
+            .collect();
+        // Regions are read when a search first reaches them, so their blocks are checked up front
+        let file_len = source.seek(SeekFrom::End(0))?;
+        let blocks_start = (HEADER_SIZE + 4 + globals.len() + table.len()) as u64;
+        let outside = |entry: &RegionEntry| entry.offset < blocks_start || entry.offset.saturating_add(entry.vertices_len as u64 + entry.edges_len as u64) > file_len;
+        if entries.iter().any(|entry| entry.vertices_len as u64 + entry.edges_len as u64 != 0 && outside(entry)) {
+            return Err(NavGridError::Truncated);
+        }
+        let contains = |coordinate: &Coordinate| coordinate.validate() && entries[locate(coordinate.index() as usize).0].vertices_len != 0;
+
+        let mut globals = globals.as_slice();
+        let mut teleports: Vec<Edge> = ciborium::de::from_reader(&mut globals)?;
+        teleports.retain(|edge| contains(&edge.destination));
+        let mut points_of_interest: Vec<crate::PointOfInterest> = ciborium::de::from_reader(&mut globals)?;
+        points_of_interest.retain(|poi| contains(&poi.position));
+
+        let mut edges = MultiMap::new();
+        for entry in entries.iter().filter(|entry| entry.edges_len != 0) {
+            source.seek(SeekFrom::Start(entry.offset + entry.vertices_len as u64))?;
+            let decoder = GzDecoder::new((&mut source).take(entry.edges_len as u64));
+            let region_edges: Vec<(u32, Vec<Edge>)> = ciborium::de::from_reader(BufReader::new(decoder))?;
+            for (index, mut region_edges) in region_edges {
+                region_edges.retain(|edge| contains(&edge.destination));
+                edges.insert_many(index, region_edges);
+            }
+        }
+
+        let regions = (0..REGIONS).map(|_| AtomicPtr::new(null_mut())).collect();
+        let vertices = RegionVertices { source: Mutex::new(Box::new(source)), entries, regions };
+        Ok(NavGrid::from_parts(Vertices::Regions(vertices), edges, teleports, points_of_interest))
+    }
+}
+
+/// Writes a region-sharded NavGrid file, which can be opened limited to some regions with [NavGrid::load_regions].
+/// It consists of a [Header] starting with [REGIONS_MAGIC], followed by the length of the CBOR encoded teleports and
+/// points of interest, the teleports and points of interest, and a table of the offset and the lengths of the blocks
+/// of each region of all planes. Each region has a gzip compressed block of two bytes per vertex (flags, extra edges
+/// and group), row by row from its south-west corner, followed by a gzip compressed block of the CBOR encoded edges
+/// starting in it. Blocks of regions without walkable tiles or edges are empty.
+pub fn save_nav_grid_regions(nav_grid: &NavGrid, path: impl AsRef<Path>) -> Result<(), ciborium::ser::Error<std::io::Error>> {
+    let mut globals = Vec::new();
+    ciborium::ser::into_writer(&nav_grid.teleports, &mut globals)?;
+    ciborium::ser::into_writer(&nav_grid.points_of_interest, &mut globals)?;
+    let mut region_edges: BTreeMap<usize, Vec<(u32, &Vec<Edge>)>> = BTreeMap::new();
+    for (index, edges) in nav_grid.edges.iter_all() {
+        region_edges.entry(locate(*index as usize).0).or_default().push((*index, edges));
+    }
+
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut offset = (HEADER_SIZE + 4 + globals.len() + FILE_REGIONS * ENTRY_SIZE) as u64;
+    // The table is only known once the blocks are written
+    file.seek(SeekFrom::Start(offset))?;
+    let mut entries = vec![RegionEntry::default(); FILE_REGIONS];
+    for (region, entry) in entries.iter_mut().enumerate() {
+        entry.offset = offset;
+        let vertices: Vec<Vertex> = (0..REGION_VERTICES)
+            .map(|i| nav_grid.vertices.get(vertex_index(region, i)).copied().unwrap_or_default())
+            .collect();
+        if vertices.iter().any(|vertex| *vertex != BLOCKED) {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            for vertex in &vertices {
+                encoder.write_all(&[vertex.flags, vertex.extra_edges_and_group])?;
+            }
+            let block = encoder.finish()?;
+            file.write_all(&block)?;
+            entry.vertices_len = block.len() as u32;
+        }
+        if let Some(edges) = region_edges.get(&region) {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            ciborium::ser::into_writer(edges, &mut encoder)?;
+            let block = encoder.finish()?;
+            file.write_all(&block)?;
+            entry.edges_len = block.len() as u32;
+        }
+        offset += (entry.vertices_len + entry.edges_len) as u64;
+    }
+
+    file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+    let mut writer = CrcWriter::new(file);
+    writer.write_all(&(globals.len() as u32).to_le_bytes())?;
+    writer.write_all(&globals)?;
+    for entry in &entries {
+        writer.write_all(&entry.to_bytes())?;
+    }
+    let checksum = writer.crc().sum();
+    let mut file = writer.into_inner();
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&Header::new(checksum).to_bytes(REGIONS_MAGIC))?;
+    file.flush()?;
+    Ok(())
+}
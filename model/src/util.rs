@@ -1,4 +1,24 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use flate2::{Compression, CrcReader, CrcWriter};
+#[cfg(feature = "mmap")]
+use flate2::Crc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+#[cfg(feature = "mmap")]
+use memmap2::MmapOptions;
+
+#[cfg(feature = "surface_only")]
+use crate::Coordinate;
+use crate::Diagnostic;
+use crate::NavGrid;
+#[cfg(feature = "mmap")]
+use crate::Vertices;
 use crate::constants::*;
+use crate::regions::{all_region_ids, REGIONS_MAGIC};
 
 type Region<T> = [T; (REGION_SIZE * REGION_SIZE) as usize];
 
@@ -45,9 +65,424 @@ impl<T: Copy> RegionCache<T> {
         Some(&region[((y % REGION_SIZE) * REGION_SIZE + x % REGION_SIZE) as usize])
     }
 
+    /// Restores the default value of all regions, keeping them allocated for reuse.
+    /// Only regions touched since they were allocated have to be cleared.
+    pub fn reset(&mut self) {
+        for region in self.regions.iter_mut().flatten() {
+            region.fill(self.default_value);
+        }
+    }
+
     pub fn mem_usage(&self) -> usize {
         self.regions.iter().map(|v| {
             std::mem::size_of_val(v) + if v.is_some() { std::mem::size_of::<Region<T>>() } else { 0 }
         }).sum()
     }
 }
+
+/// Identifies NavGrid files, followed by the [FORMAT_VERSION] they were written with
+pub const MAGIC: [u8; 4] = *b"ONAV";
+/// Identifies uncompressed NavGrid files that can be memory-mapped
+pub const MAPPED_MAGIC: [u8; 4] = *b"ONVM";
+/// Version of the file format written by [save_nav_grid]
+pub const FORMAT_VERSION: u16 = 1;
+pub(crate) const HEADER_SIZE: usize = 20;
+const CHECKSUM_OFFSET: u64 = 16;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The vertices of memory-mapped files start at this offset, so they are page aligned
+const PAGE_SIZE: usize = 4096;
+
+/// Uncompressed header of a NavGrid file, preceding the gzip compressed grid, the page aligned vertices or the region
+/// table
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct Header {
+    pub version: u16,
+    pub planes: u16,
+    pub width: u32,
+    pub height: u32,
+    /// CRC32 of the uncompressed grid, only of the edges and teleports of memory-mapped files, or of the teleports,
+    /// points of interest and region table of region-sharded files
+    pub checksum: u32,
+}
+
+impl Header {
+    pub(crate) fn new(checksum: u32) -> Header {
+        Header { version: FORMAT_VERSION, planes: GAME_PLANES as u16, width: WIDTH, height: HEIGHT, checksum }
+    }
+
+    pub(crate) fn to_bytes(self, magic: [u8; 4]) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&magic);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.planes.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.width.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Reads a header starting with `magic`, rejecting newer versions and other dimensions
+    pub(crate) fn parse(bytes: &[u8], magic: [u8; 4]) -> Result<Header, NavGridError> {
+        if bytes.len() < HEADER_SIZE || bytes[0..4] != magic {
+            return Err(NavGridError::NotANavGrid);
+        }
+        let header = Header {
+            version: u16::from_le_bytes([bytes[4], bytes[5]]),
+            planes: u16::from_le_bytes([bytes[6], bytes[7]]),
+            width: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            height: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            checksum: u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+        };
+        if header.version > FORMAT_VERSION {
+            return Err(NavGridError::UnsupportedVersion(header.version));
+        }
+        if (header.planes as u32, header.width, header.height) != (GAME_PLANES, WIDTH, HEIGHT) {
+            return Err(NavGridError::Dimensions(header));
+        }
+        Ok(header)
+    }
+}
+
+#[derive(Debug)]
+pub enum NavGridError {
+    Io(std::io::Error),
+    /// The file doesn't start with [MAGIC], [MAPPED_MAGIC], [REGIONS_MAGIC] or the gzip magic of headerless files
+    NotANavGrid,
+    /// The file was written by a newer version of the format
+    UnsupportedVersion(u16),
+    /// The file was written for a map of other dimensions
+    Dimensions(Header),
+    /// The file ended before the whole grid was read
+    Truncated,
+    /// The grid doesn't match the checksum of the header
+    Checksum { expected: u32, actual: u32 },
+    /// The edges or teleports couldn't be decoded
+    Edges(String),
+    /// A file written by [save_nav_grid_mapped] was read by a build without the `mmap` feature or from another source
+    /// than a path
+    Mapped,
+    /// A file written by [save_nav_grid_regions](crate::regions::save_nav_grid_regions) was read from a source that
+    /// can't seek
+    Regions,
+    /// [NavGrid::validate] found [fatal](Diagnostic::is_fatal) diagnostics, searches on the grid could fail
+    Invalid(Vec<Diagnostic>),
+}
+
+impl Display for NavGridError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NavGridError::Io(e) => write!(f, "{}", e),
+            NavGridError::NotANavGrid => write!(f, "not a NavGrid file"),
+            NavGridError::UnsupportedVersion(version) => write!(f, "format version {} is newer than the supported version {}", version, FORMAT_VERSION),
+            NavGridError::Dimensions(header) => write!(f, "grid of {}x{}x{} doesn't match the map of {}x{}x{}", header.width, header.height, header.planes, WIDTH, HEIGHT, GAME_PLANES),
+            NavGridError::Truncated => write!(f, "file is truncated"),
+            NavGridError::Checksum { expected, actual } => write!(f, "checksum {:08x} doesn't match the header checksum {:08x}", actual, expected),
+            NavGridError::Edges(e) => write!(f, "invalid edges: {}", e),
+            NavGridError::Mapped => write!(f, "memory-mapped NavGrid files can only be opened from a path with the mmap feature"),
+            NavGridError::Regions => write!(f, "region-sharded NavGrid files have to be opened with NavGrid::load_regions"),
+            NavGridError::Invalid(diagnostics) => match diagnostics.first() {
+                Some(first) if diagnostics.len() > 1 => write!(f, "invalid grid: {}, and {} more", first, diagnostics.len() - 1),
+                Some(first) => write!(f, "invalid grid: {}", first),
+                None => write!(f, "invalid grid"),
+            },
+        }
+    }
+}
+
+impl std::error::Error for NavGridError {}
+
+impl From<std::io::Error> for NavGridError {
+    fn from(e: std::io::Error) -> NavGridError {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            NavGridError::Truncated
+        } else {
+            NavGridError::Io(e)
+        }
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for NavGridError {
+    fn from(e: ciborium::de::Error<std::io::Error>) -> NavGridError {
+        match e {
+            ciborium::de::Error::Io(e) => e.into(),
+            e => NavGridError::Edges(format!("{:?}", e)),
+        }
+    }
+}
+
+/// Reads a NavGrid file written by [save_nav_grid], or opens one written by [save_nav_grid_mapped] with [NavGrid::open_mmap]
+/// or one written by [save_nav_grid_regions](crate::regions::save_nav_grid_regions) with all regions selected.
+/// Headerless files written before [FORMAT_VERSION] 1 are still read, without an integrity check.
+/// Saving them again upgrades them to the current format.
+pub fn load_nav_grid(path: impl AsRef<Path>) -> Result<NavGrid, NavGridError> {
+    let mut file = BufReader::new(File::open(&path)?);
+    let start = file.fill_buf()?;
+    if start.starts_with(&REGIONS_MAGIC) {
+        return NavGrid::read_regions(file, &all_region_ids());
+    }
+    if start.starts_with(&MAPPED_MAGIC) {
+        #[cfg(feature = "mmap")]
+        return NavGrid::open_mmap(path);
+    }
+    read_nav_grid(file)
+}
+
+/// Reads the [Header] of a NavGrid file of any format, e.g. to tell which grid a file derived from it was built from.
+/// Headerless files written before [FORMAT_VERSION] 1 have none.
+pub fn read_header(path: impl AsRef<Path>) -> Result<Option<Header>, NavGridError> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE);
+    File::open(path)?.take(HEADER_SIZE as u64).read_to_end(&mut bytes)?;
+    if bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(None);
+    }
+    match [MAGIC, MAPPED_MAGIC, REGIONS_MAGIC].into_iter().find(|magic| bytes.starts_with(magic)) {
+        Some(magic) => Header::parse(&bytes, magic).map(Some),
+        None => Err(NavGridError::NotANavGrid),
+    }
+}
+
+/// Reads a NavGrid written by [save_nav_grid] from memory or any other source than a file, e.g. a download.
+/// Files written by [save_nav_grid_mapped] can only be opened from a path with [NavGrid::open_mmap].
+pub fn read_nav_grid(mut reader: impl BufRead) -> Result<NavGrid, NavGridError> {
+    let start = reader.fill_buf()?;
+    if start.starts_with(&GZIP_MAGIC) {
+        return read_grid(BufReader::new(GzDecoder::new(reader)));
+    }
+    if start.starts_with(&MAPPED_MAGIC) {
+        return Err(NavGridError::Mapped);
+    }
+    if start.starts_with(&REGIONS_MAGIC) {
+        return Err(NavGridError::Regions);
+    }
+    let mut bytes = [0; HEADER_SIZE];
+    reader.read_exact(&mut bytes).map_err(|_| NavGridError::NotANavGrid)?;
+    let header = Header::parse(&bytes, MAGIC)?;
+    let mut reader = BufReader::new(CrcReader::new(GzDecoder::new(reader)));
+    let nav_grid = read_grid(&mut reader)?;
+    // Anything left after the points of interest is part of the checksum too
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+    let actual = reader.get_ref().crc().sum();
+    if actual != header.checksum {
+        return Err(NavGridError::Checksum { expected: header.checksum, actual });
+    }
+    Ok(nav_grid)
+}
+
+fn read_grid(mut reader: impl BufRead) -> Result<NavGrid, NavGridError> {
+    let mut nav_grid = NavGrid::new();
+    for vertex in &mut nav_grid.vertices {
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf)?;
+        vertex.flags = buf[0];
+        vertex.extra_edges_and_group = buf[1];
+    }
+    #[cfg(feature = "surface_only")]
+    {
+        let upper_planes = (GAME_PLANES - PLANES) * WIDTH * HEIGHT * 2;
+        std::io::copy(&mut (&mut reader).take(upper_planes as u64), &mut std::io::sink())?;
+    }
+    nav_grid.edges = ciborium::de::from_reader(&mut reader)?;
+    nav_grid.teleports = ciborium::de::from_reader(&mut reader)?;
+    // Files written before points of interest were added end after the teleports
+    if !reader.fill_buf()?.is_empty() {
+        nav_grid.points_of_interest = ciborium::de::from_reader(&mut reader)?;
+    }
+    #[cfg(feature = "surface_only")]
+    retain_surface(&mut nav_grid);
+    Ok(nav_grid)
+}
+
+/// Cross-plane edges can't be represented in a single plane grid
+#[cfg(feature = "surface_only")]
+fn retain_surface(nav_grid: &mut NavGrid) {
+    nav_grid.edges.retain(|source, edge| Coordinate::from_index(*source).validate() && edge.destination.validate());
+    nav_grid.teleports.retain(|edge| edge.destination.validate());
+    nav_grid.points_of_interest.retain(|poi| poi.position.validate());
+    for (index, vertex) in nav_grid.vertices.iter_mut().enumerate() {
+        if vertex.has_extra_edges() && !nav_grid.edges.contains_key(&(index as u32)) {
+            vertex.set_extra_edges(false);
+        }
+    }
+}
+
+/// Writes a NavGrid file consisting of a [Header] followed by the gzip compressed grid of
+/// two bytes per vertex (flags, extra edges and group), followed by the CBOR encoded edges, teleports and points of
+/// interest.
+/// Grids limited to the surface or built by [NavGrid::builder] are padded with empty vertices, so the file can be read
+/// by any build.
+pub fn save_nav_grid(nav_grid: &NavGrid, path: impl AsRef<Path>) -> Result<(), ciborium::ser::Error<std::io::Error>> {
+    let mut file = File::create(path)?;
+    // The checksum is only known once the grid is written
+    file.write_all(&Header::new(0).to_bytes(MAGIC))?;
+    let encoder = GzEncoder::new(file, Compression::default());
+    let mut writer = BufWriter::new(CrcWriter::new(encoder));
+    for vertex in &nav_grid.vertices {
+        writer.write_all(&[vertex.flags, vertex.extra_edges_and_group])?;
+    }
+    let padding = (GAME_PLANES * WIDTH * HEIGHT) as usize - nav_grid.vertices.len();
+    std::io::copy(&mut std::io::repeat(0).take(padding as u64 * 2), &mut writer)?;
+    ciborium::ser::into_writer(&nav_grid.edges, &mut writer)?;
+    ciborium::ser::into_writer(&nav_grid.teleports, &mut writer)?;
+    ciborium::ser::into_writer(&nav_grid.points_of_interest, &mut writer)?;
+    let writer = writer.into_inner().map_err(|e| e.into_error())?;
+    let checksum = writer.crc().sum();
+    let mut file = writer.into_inner().finish()?;
+    file.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+    file.write_all(&checksum.to_le_bytes())?;
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
+impl NavGrid {
+    /// Opens a NavGrid file written by [save_nav_grid_mapped] without reading its vertices, which are paged in
+    /// from the file as they are accessed and shared with other processes mapping the same file.
+    /// Only the edges, teleports and points of interest are decoded and checked against the header checksum.
+    /// The file must not be modified while the NavGrid is open.
+    pub fn open_mmap(path: impl AsRef<Path>) -> Result<NavGrid, NavGridError> {
+        let file = File::open(path)?;
+        let map = unsafe { MmapOptions::new().map_copy(&file)? };
+        let header = Header::parse(&map, MAPPED_MAGIC)?;
+        let edges_offset = PAGE_SIZE + (GAME_PLANES * WIDTH * HEIGHT * 2) as usize;
+        if map.len() < edges_offset {
+            return Err(NavGridError::Truncated);
+        }
+        let mut crc = Crc::new();
+        crc.update(&map[edges_offset..]);
+        if crc.sum() != header.checksum {
+            return Err(NavGridError::Checksum { expected: header.checksum, actual: crc.sum() });
+        }
+        let mut reader = &map[edges_offset..];
+        let edges = ciborium::de::from_reader(&mut reader)?;
+        let teleports = ciborium::de::from_reader(&mut reader)?;
+        let points_of_interest = if reader.is_empty() { Vec::new() } else { ciborium::de::from_reader(&mut reader)? };
+        // Planes are stored after each other, so the surface is at the start of the vertices
+        let vertices = Vertices::Mapped { map, offset: PAGE_SIZE, len: (WIDTH * HEIGHT * PLANES) as usize };
+        #[allow(unused_mut)]
+        let mut nav_grid = NavGrid::from_parts(vertices, edges, teleports, points_of_interest);
+        #[cfg(feature = "surface_only")]
+        retain_surface(&mut nav_grid);
+        Ok(nav_grid)
+    }
+}
+
+/// Writes an uncompressed NavGrid file for [NavGrid::open_mmap], consisting of a [Header] padded to a page,
+/// followed by two bytes per vertex and the CBOR encoded edges, teleports and points of interest.
+/// Grids limited to the surface or built by [NavGrid::builder] are padded with empty vertices, so the file can be read
+/// by any build.
+pub fn save_nav_grid_mapped(nav_grid: &NavGrid, path: impl AsRef<Path>) -> Result<(), ciborium::ser::Error<std::io::Error>> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&Header::new(0).to_bytes(MAPPED_MAGIC))?;
+    std::io::copy(&mut std::io::repeat(0).take((PAGE_SIZE - HEADER_SIZE) as u64), &mut writer)?;
+    for vertex in &nav_grid.vertices {
+        writer.write_all(&[vertex.flags, vertex.extra_edges_and_group])?;
+    }
+    let padding = (GAME_PLANES * WIDTH * HEIGHT) as usize - nav_grid.vertices.len();
+    std::io::copy(&mut std::io::repeat(0).take(padding as u64 * 2), &mut writer)?;
+    let mut writer = CrcWriter::new(writer);
+    ciborium::ser::into_writer(&nav_grid.edges, &mut writer)?;
+    ciborium::ser::into_writer(&nav_grid.teleports, &mut writer)?;
+    ciborium::ser::into_writer(&nav_grid.points_of_interest, &mut writer)?;
+    let checksum = writer.crc().sum();
+    let mut file = writer.into_inner().into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+    file.write_all(&checksum.to_le_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Area, Coordinate, Edge, PoiKind, PointOfInterest, Vertex};
+    use crate::definitions::{EdgeDefinition, PlaneOffset, Regex, RequirementDefinition};
+    use crate::regions::save_nav_grid_regions;
+
+    fn c(x: u16, y: u16) -> Coordinate {
+        Coordinate { x, y, plane: 0 }
+    }
+
+    /// Small grid using every part of the format: flags, groups, edges with requirements, teleports and points of interest
+    fn sample_grid() -> NavGrid {
+        let door = Edge {
+            destination: c(14, 10),
+            cost: 2,
+            definition: EdgeDefinition::Door { id: 1530, name: Some("Door".to_string()), position: c(13, 10), action: Regex::new("Open").unwrap() },
+            requirements: vec![RequirementDefinition::Item { item: Regex::new("Key").unwrap(), quantity: 1, consumed: false }],
+            instance_plane: Some(PlaneOffset::Varbit { index: 4 }),
+            arrival: None,
+        };
+        let teleport = Edge {
+            destination: c(15, 11),
+            cost: 10,
+            definition: EdgeDefinition::SpellTeleport { spell: "Varrock Teleport".to_string() },
+            requirements: vec![RequirementDefinition::Skill { skill: "Magic".to_string(), level: 25 }],
+            instance_plane: None,
+            arrival: Some(Area { min: c(14, 10), max: c(16, 12) }),
+        };
+        let bank = PointOfInterest { kind: PoiKind::Bank, position: c(16, 12), name: Some("Bank".to_string()) };
+        let mut nav_grid = NavGrid::builder()
+            .walkable_area(&Area { min: c(10, 10), max: c(12, 12) })
+            .walkable_area(&Area { min: c(14, 10), max: c(16, 12) })
+            .edge(c(12, 10), door)
+            .teleport(teleport)
+            .point_of_interest(bank)
+            .build();
+        nav_grid.vertices[c(15, 11).index() as usize].set_group(2);
+        nav_grid
+    }
+
+    fn assert_same_grid(loaded: &NavGrid, saved: &NavGrid) {
+        assert!(loaded.vertices.iter().take(saved.vertices.len()).eq(saved.vertices.iter()));
+        assert!(loaded.vertices.iter().skip(saved.vertices.len()).all(|vertex| *vertex == Vertex::default()));
+        assert_eq!(loaded.edges, saved.edges);
+        assert_eq!(loaded.teleports, saved.teleports);
+        let points = |nav_grid: &NavGrid| nav_grid.points_of_interest.iter().map(|poi| (poi.kind, poi.position, poi.name.clone())).collect::<Vec<_>>();
+        assert_eq!(points(loaded), points(saved));
+    }
+
+    #[test]
+    #[ignore = "decodes a grid of the whole map, run in release builds"]
+    fn saved_grids_load_unchanged() {
+        let nav_grid = sample_grid();
+        let path = std::env::temp_dir().join(format!("navgrid-{}.bin", std::process::id()));
+        save_nav_grid(&nav_grid, &path).unwrap();
+        let loaded = load_nav_grid(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_same_grid(&loaded.unwrap(), &nav_grid);
+    }
+
+    #[test]
+    #[ignore = "writes a grid of the whole map, run in release builds"]
+    #[cfg(feature = "mmap")]
+    fn saved_mapped_grids_open_unchanged() {
+        let nav_grid = sample_grid();
+        let path = std::env::temp_dir().join(format!("navgrid-{}.mapped.bin", std::process::id()));
+        save_nav_grid_mapped(&nav_grid, &path).unwrap();
+        let loaded = NavGrid::open_mmap(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_same_grid(&loaded.unwrap(), &nav_grid);
+    }
+
+    #[test]
+    #[ignore = "writes a grid of the whole map, run in release builds"]
+    fn saved_region_grids_load_unchanged() {
+        let nav_grid = sample_grid();
+        let path = std::env::temp_dir().join(format!("navgrid-{}.regions.bin", std::process::id()));
+        save_nav_grid_regions(&nav_grid, &path).unwrap();
+        let loaded = NavGrid::load_regions(&path, &all_region_ids());
+        std::fs::remove_file(&path).unwrap();
+        assert_same_grid(&loaded.unwrap(), &nav_grid);
+    }
+
+    #[test]
+    #[ignore = "writes a grid of the whole map, run in release builds"]
+    fn truncated_region_grids_are_rejected() {
+        let path = std::env::temp_dir().join(format!("navgrid-{}.truncated.regions.bin", std::process::id()));
+        save_nav_grid_regions(&sample_grid(), &path).unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 1).unwrap();
+        let loaded = NavGrid::load_regions(&path, &all_region_ids());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(NavGridError::Truncated)));
+    }
+}
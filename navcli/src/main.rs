@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand};
+use expect_exit::{Expected, ExpectedWithError};
+use serde::de::DeserializeOwned;
+
+use model::{Coordinate, NavGrid, PoiKind};
+use model::definitions::{EdgeDefinition, GameState};
+use pathfinder::{SearchOptions, StepKind};
+
+#[derive(Parser)]
+struct Options {
+    /// Path to NavGrid file
+    #[clap(short, long, global = true)]
+    navgrid: Option<PathBuf>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Searches the path between two coordinates and prints its steps
+    Path {
+        /// Start of the path, as x,y,plane
+        #[clap(long, parse(try_from_str = parse_coordinate))]
+        start: Coordinate,
+        /// End of the path, as x,y,plane
+        #[clap(long, parse(try_from_str = parse_coordinate))]
+        end: Coordinate,
+        /// YAML or JSON file with the game state the path is searched with, the default game state if absent
+        #[clap(long)]
+        state: Option<PathBuf>,
+        /// YAML or JSON file with the search options, like the `options` of the webservice's `/path`
+        #[clap(long)]
+        options: Option<PathBuf>,
+        /// Merge straight lines of walking steps into a single step
+        #[clap(long)]
+        simplify: bool,
+        /// Print the path as JSON, like the webservice's `/path` response
+        #[clap(long)]
+        json: bool,
+    },
+    /// Prints statistics of the grid, e.g. walkable tiles, group sizes and edge counts
+    Info {
+        /// Print the size of every group instead of the largest ones
+        #[clap(long)]
+        groups: bool,
+    },
+    /// Checks the grid for edges leading nowhere, group labels disagreeing with the moves between tiles and other
+    /// inconsistencies. Exits with status 1 if errors are found.
+    Validate {
+        /// Also exit with status 1 if only warnings are found
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Searches paths between random walkable tiles of the same group and prints the search times
+    Bench {
+        /// Number of paths searched
+        #[clap(long, default_value = "100")]
+        count: usize,
+        /// Seed of the random tiles, so runs on the same grid search the same paths
+        #[clap(long, default_value = "1")]
+        seed: u64,
+        /// YAML or JSON file with the game state the paths are searched with, the default game state if absent
+        #[clap(long)]
+        state: Option<PathBuf>,
+    },
+}
+
+/// Parses a coordinate given as `x,y,plane`
+fn parse_coordinate(s: &str) -> Result<Coordinate, String> {
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    match parts[..] {
+        [x, y, plane] => {
+            let coordinate = Coordinate {
+                x: x.parse().map_err(|_| format!("Invalid x: {}", x))?,
+                y: y.parse().map_err(|_| format!("Invalid y: {}", y))?,
+                plane: plane.parse().map_err(|_| format!("Invalid plane: {}", plane))?,
+            };
+            if coordinate.validate() { Ok(coordinate) } else { Err(format!("{} is out of bounds", coordinate)) }
+        }
+        _ => Err("Expected x,y,plane".to_string()),
+    }
+}
+
+/// Reads a YAML or JSON file, or returns the default value if no file is passed
+fn read_or_default<T: DeserializeOwned + Default>(path: Option<&Path>, name: &str) -> T {
+    match path {
+        Some(path) => {
+            let file = File::open(path).or_exit_e_(&format!("Error opening {} file", name));
+            serde_yaml::from_reader(BufReader::new(file)).or_exit_e_(&format!("Error parsing {} file", name))
+        }
+        None => T::default(),
+    }
+}
+
+fn find_path(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions, simplify: bool, json: bool) {
+    let result = pathfinder::find_path(nav_grid, start, end, game_state, options).unwrap_or_else(|_| {
+        eprintln!("Search budget exceeded");
+        std::process::exit(1);
+    });
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result).or_exit_e_("Error serializing path"));
+        return;
+    }
+    let mut result = result.or_exit_(&format!("No path found from {} to {}", start, end));
+    if simplify {
+        result.path.steps = pathfinder::simplify(std::mem::take(&mut result.path.steps));
+    }
+    for step in &result.path.steps {
+        let definition = match step.kind {
+            StepKind::Walk => String::new(),
+            _ => serde_json::to_string(&step.definition).or_exit_e_("Error serializing step"),
+        };
+        println!("{:<8} {} -> {} cost {} {}", format!("{:?}", step.kind), step.from, step.to, step.cost, definition);
+    }
+    println!("Cost {}, {} steps, {} tiles walked, {} teleports, {} visited in {:.2}ms",
+             result.path.cost, result.path.steps.len(), result.tiles_walked, result.teleports_used, result.visited, result.duration.as_secs_f64() * 1000f64);
+}
+
+/// Name of the kind of edge, as the `type` of its definition
+fn edge_type(definition: &EdgeDefinition) -> &'static str {
+    match definition {
+        EdgeDefinition::Step { .. } => "Step",
+        EdgeDefinition::Door { .. } => "Door",
+        EdgeDefinition::GameObject { .. } => "GameObject",
+        EdgeDefinition::Npc { .. } => "Npc",
+        EdgeDefinition::SpellTeleport { .. } => "SpellTeleport",
+        EdgeDefinition::ItemTeleport { .. } => "ItemTeleport",
+    }
+}
+
+fn print_info(nav_grid: &NavGrid, all_groups: bool) {
+    let mut walkable_by_plane: BTreeMap<u8, usize> = BTreeMap::new();
+    let mut group_sizes: BTreeMap<u8, usize> = BTreeMap::new();
+    for (index, vertex) in nav_grid.vertices.iter().enumerate().filter(|(_, vertex)| vertex.flags != 0) {
+        *walkable_by_plane.entry(Coordinate::from_index(index as u32).plane).or_default() += 1;
+        *group_sizes.entry(vertex.get_group()).or_default() += 1;
+    }
+    println!("Vertices: {}", nav_grid.vertices.len());
+    println!("Walkable tiles: {}", walkable_by_plane.values().sum::<usize>());
+    for (plane, walkable) in &walkable_by_plane {
+        println!("  plane {}: {}", plane, walkable);
+    }
+
+    println!("Groups: {}", group_sizes.len());
+    let mut groups: Vec<(u8, usize)> = group_sizes.into_iter().collect();
+    groups.sort_by_key(|(group, size)| (std::cmp::Reverse(*size), *group));
+    for (group, size) in groups.iter().take(if all_groups { usize::MAX } else { 10 }) {
+        println!("  group {:>3}: {} tiles", group, size);
+    }
+    if !all_groups && groups.len() > 10 {
+        println!("  ... {} more, pass --groups to list all", groups.len() - 10);
+    }
+
+    let mut edges_by_type: BTreeMap<&str, usize> = BTreeMap::new();
+    nav_grid.edges.iter_all().flat_map(|(_, edges)| edges).for_each(|edge| *edges_by_type.entry(edge_type(&edge.definition)).or_default() += 1);
+    println!("Edges: {} from {} tiles", edges_by_type.values().sum::<usize>(), nav_grid.edges.len());
+    for (kind, count) in &edges_by_type {
+        println!("  {}: {}", kind, count);
+    }
+    let mut teleports_by_type: BTreeMap<&str, usize> = BTreeMap::new();
+    nav_grid.teleports.iter().for_each(|teleport| *teleports_by_type.entry(edge_type(&teleport.definition)).or_default() += 1);
+    println!("Teleports: {}", nav_grid.teleports.len());
+    for (kind, count) in &teleports_by_type {
+        println!("  {}: {}", kind, count);
+    }
+
+    println!("Points of interest: {}", nav_grid.points_of_interest.len());
+    for kind in PoiKind::ALL {
+        let count = nav_grid.points_of_interest(kind).count();
+        if count > 0 {
+            println!("  {}: {}", kind.tag(), count);
+        }
+    }
+
+    let selection = nav_grid.data_selection();
+    println!("Game state data: {} varps, {} varbits, {} item patterns, {} skills, {} quests, {} diaries",
+             selection.varps.len(), selection.varbits.len(), selection.items.len(), selection.skills.len(), selection.quests.len(), selection.diaries.len());
+}
+
+/// Prints the diagnostics of the grid and returns the number of errors and warnings
+fn validate(nav_grid: &NavGrid) -> (usize, usize) {
+    let diagnostics = nav_grid.validate();
+    for diagnostic in &diagnostics {
+        println!("{} {}", if diagnostic.is_fatal() { "error:" } else { "warning:" }, diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.is_fatal()).count();
+    (errors, diagnostics.len() - errors)
+}
+
+/// xorshift64*, enough to pick tiles reproducibly without a dependency
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545F4914F6CDD1D) % bound as u64) as usize
+    }
+}
+
+fn bench(nav_grid: &NavGrid, count: usize, seed: u64, game_state: &GameState) {
+    let mut groups: BTreeMap<u8, Vec<u32>> = BTreeMap::new();
+    let mut walkable = Vec::new();
+    for (index, vertex) in nav_grid.vertices.iter().enumerate().filter(|(_, vertex)| vertex.flags != 0) {
+        groups.entry(vertex.get_group()).or_default().push(index as u32);
+        walkable.push(index as u32);
+    }
+    if walkable.is_empty() {
+        eprintln!("The grid has no walkable tiles");
+        std::process::exit(1);
+    }
+    let mut random = Random(seed.max(1));
+    let mut durations = Vec::with_capacity(count);
+    let mut visited = 0;
+    let mut found = 0;
+    let options = SearchOptions::default();
+    for _ in 0..count {
+        let start = walkable[random.below(walkable.len())];
+        let group = &groups[&nav_grid.vertices[start as usize].get_group()];
+        let end = group[random.below(group.len())];
+        let begin = Instant::now();
+        let (expanded, _, path) = pathfinder::astar_with_options(nav_grid, &Coordinate::from_index(start), &Coordinate::from_index(end), game_state, &options);
+        durations.push(Instant::now() - begin);
+        visited += expanded;
+        found += matches!(path, Ok(Some(_))) as usize;
+    }
+    durations.sort_unstable();
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000f64;
+    let percentile = |p: usize| millis(durations[(durations.len() - 1) * p / 100]);
+    println!("{} searches, {} paths found, {} vertices visited on average", count, found, visited / count.max(1));
+    if !durations.is_empty() {
+        println!("min {:.2}ms, median {:.2}ms, p95 {:.2}ms, max {:.2}ms, total {:.2}ms",
+                 percentile(0), percentile(50), percentile(95), percentile(100), millis(durations.iter().sum()));
+    }
+}
+
+fn main() {
+    let options = Options::parse();
+    let navgrid = options.navgrid.or_exit_("Pass the NavGrid file as --navgrid");
+    let nav_grid = model::util::load_nav_grid(&navgrid).or_exit_e_("Error loading NavGrid");
+
+    match options.command {
+        Command::Path { start, end, state, options, simplify, json } => {
+            let game_state: GameState = read_or_default(state.as_deref(), "game state");
+            let options: SearchOptions = read_or_default(options.as_deref(), "search options");
+            find_path(&nav_grid, &start, &end, &game_state, &options, simplify, json);
+        }
+        Command::Info { groups } => print_info(&nav_grid, groups),
+        Command::Validate { strict } => {
+            let (errors, warnings) = validate(&nav_grid);
+            if errors + warnings == 0 {
+                println!("No problems found");
+                return;
+            }
+            println!("{} errors and {} warnings found", errors, warnings);
+            if errors > 0 || strict {
+                std::process::exit(1);
+            }
+        }
+        Command::Bench { count, seed, state } => {
+            let game_state: GameState = read_or_default(state.as_deref(), "game state");
+            bench(&nav_grid, count, seed, &game_state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinates_are_parsed_from_x_y_and_plane() {
+        assert_eq!(parse_coordinate("3222, 3218,0"), Ok(Coordinate { x: 3222, y: 3218, plane: 0 }));
+        assert_eq!(parse_coordinate("3222,3218"), Err("Expected x,y,plane".to_string()));
+        assert_eq!(parse_coordinate("3222,north,0"), Err("Invalid y: north".to_string()));
+        assert!(parse_coordinate("3222,3218,9").is_err());
+    }
+}
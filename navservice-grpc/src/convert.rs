@@ -0,0 +1,161 @@
+use tonic::Status;
+
+use model::{Area, Coordinate};
+use model::definitions::{DiaryTier, EdgeDefinition, GameState, QuestState, Regex, UnknownPolicy};
+use pathfinder::{PathConstraints, PathResult, SearchOptions, Step, StepKind};
+
+use crate::proto;
+
+pub fn coordinate(coordinate: Option<proto::Coordinate>) -> Result<Coordinate, Status> {
+    let coordinate = coordinate.ok_or_else(|| Status::invalid_argument("Missing coordinate"))?;
+    let out_of_bounds = || Status::invalid_argument("Coordinate out of bounds");
+    let coordinate = Coordinate {
+        x: u16::try_from(coordinate.x).map_err(|_| out_of_bounds())?,
+        y: u16::try_from(coordinate.y).map_err(|_| out_of_bounds())?,
+        plane: u8::try_from(coordinate.plane).map_err(|_| out_of_bounds())?,
+    };
+    if coordinate.validate() {
+        Ok(coordinate)
+    } else {
+        Err(out_of_bounds())
+    }
+}
+
+fn area(area: proto::Area) -> Result<Area, Status> {
+    Ok(Area { min: coordinate(area.min)?, max: coordinate(area.max)? })
+}
+
+pub fn game_state(game_state: Option<proto::GameState>) -> Result<GameState, Status> {
+    let game_state = game_state.unwrap_or_default();
+    let skills = game_state.skills.into_iter()
+        .map(|(skill, level)| match u8::try_from(level) {
+            Ok(level) if (1..=99).contains(&level) => Ok((skill.to_uppercase(), level)),
+            _ => Err(Status::invalid_argument(format!("{} level {} is not between 1 and 99", skill, level))),
+        })
+        .collect::<Result<_, _>>()?;
+    let quests = game_state.quests.into_iter()
+        .map(|(quest, state)| Ok((quest, quest_state(state)?)))
+        .collect::<Result<_, Status>>()?;
+    let diaries = game_state.diaries.into_iter()
+        .map(|(region, tier)| Ok((region, diary_tier(tier)?)))
+        .collect::<Result<_, Status>>()?;
+    let unknown_defaults_to = match proto::UnknownPolicy::from_i32(game_state.unknown_defaults_to) {
+        Some(proto::UnknownPolicy::Met) => UnknownPolicy::Met,
+        Some(proto::UnknownPolicy::NotMet) => UnknownPolicy::NotMet,
+        None => return Err(Status::invalid_argument("Unknown policy")),
+    };
+    Ok(GameState {
+        member: game_state.member,
+        skills,
+        items: game_state.items,
+        varps: game_state.varps,
+        varbits: game_state.varbits,
+        quests,
+        diaries,
+        run_energy: u8::try_from(game_state.run_energy).map_err(|_| Status::invalid_argument("Run energy out of range"))?,
+        weight: game_state.weight,
+        unknown_defaults_to,
+    })
+}
+
+fn quest_state(state: i32) -> Result<QuestState, Status> {
+    match proto::QuestState::from_i32(state) {
+        Some(proto::QuestState::NotStarted) => Ok(QuestState::NotStarted),
+        Some(proto::QuestState::InProgress) => Ok(QuestState::InProgress),
+        Some(proto::QuestState::Finished) => Ok(QuestState::Finished),
+        None => Err(Status::invalid_argument("Unknown quest state")),
+    }
+}
+
+fn diary_tier(tier: i32) -> Result<DiaryTier, Status> {
+    match proto::DiaryTier::from_i32(tier) {
+        Some(proto::DiaryTier::Easy) => Ok(DiaryTier::Easy),
+        Some(proto::DiaryTier::Medium) => Ok(DiaryTier::Medium),
+        Some(proto::DiaryTier::Hard) => Ok(DiaryTier::Hard),
+        Some(proto::DiaryTier::Elite) => Ok(DiaryTier::Elite),
+        None => Err(Status::invalid_argument("Unknown diary tier")),
+    }
+}
+
+pub fn search_options(options: Option<proto::SearchOptions>) -> Result<SearchOptions, Status> {
+    let options = options.unwrap_or_default();
+    let defaults = SearchOptions::default();
+    Ok(SearchOptions {
+        strict_consumables: options.strict_consumables,
+        heuristic_weight: options.heuristic_weight.unwrap_or(defaults.heuristic_weight),
+        blocked: options.blocked.into_iter().map(|c| coordinate(Some(c))).collect::<Result<_, _>>()?,
+        avoid: options.avoid.into_iter().map(area).collect::<Result<_, _>>()?,
+        disabled: options.disabled.into_iter().map(edge_definition).collect::<Result<_, _>>()?,
+        max_visited: options.max_visited,
+        max_memory: None,
+        max_millis: None,
+        max_cost: None,
+        annotate_requirements: false,
+        prune_teleports: options.prune_teleports.unwrap_or(defaults.prune_teleports),
+        constraints: PathConstraints {
+            avoid_wilderness: options.avoid_wilderness,
+            max_wilderness_level: options.max_wilderness_level.map(|level| u8::try_from(level).unwrap_or(u8::MAX)),
+        },
+        ignore_requirements: options.ignore_requirements,
+        cancelled: None,
+    })
+}
+
+fn regex(pattern: &str) -> Result<Regex, Status> {
+    Regex::new(pattern).map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+fn edge_definition(definition: proto::EdgeDefinition) -> Result<EdgeDefinition, Status> {
+    use proto::edge_definition::Definition;
+    Ok(match definition.definition.ok_or_else(|| Status::invalid_argument("Missing edge definition"))? {
+        Definition::Step(step) => EdgeDefinition::Step { position: coordinate(step.position)? },
+        Definition::Door(door) => EdgeDefinition::Door { id: door.id, name: door.name, position: coordinate(door.position)?, action: regex(&door.action)? },
+        Definition::GameObject(object) => EdgeDefinition::GameObject { id: object.id, name: object.name, position: coordinate(object.position)?, action: regex(&object.action)? },
+        Definition::Npc(npc) => EdgeDefinition::Npc { id: npc.id, name: npc.name, position: coordinate(npc.position)?, action: regex(&npc.action)? },
+        Definition::SpellTeleport(teleport) => EdgeDefinition::SpellTeleport { spell: teleport.spell },
+        Definition::ItemTeleport(teleport) => EdgeDefinition::ItemTeleport { item: regex(&teleport.item)?, action: regex(&teleport.action)? },
+    })
+}
+
+fn to_coordinate(coordinate: &Coordinate) -> proto::Coordinate {
+    proto::Coordinate { x: coordinate.x as u32, y: coordinate.y as u32, plane: coordinate.plane as u32 }
+}
+
+fn to_edge_definition(definition: &EdgeDefinition) -> proto::EdgeDefinition {
+    use proto::edge_definition::{Definition, GameObject, ItemTeleport, SpellTeleport};
+    let definition = match definition {
+        EdgeDefinition::Step { position } => Definition::Step(proto::edge_definition::Step { position: Some(to_coordinate(position)) }),
+        EdgeDefinition::Door { id, name, position, action } => Definition::Door(GameObject { id: *id, position: Some(to_coordinate(position)), action: action.to_string(), name: name.clone() }),
+        EdgeDefinition::GameObject { id, name, position, action } => Definition::GameObject(GameObject { id: *id, position: Some(to_coordinate(position)), action: action.to_string(), name: name.clone() }),
+        EdgeDefinition::Npc { id, name, position, action } => Definition::Npc(GameObject { id: *id, position: Some(to_coordinate(position)), action: action.to_string(), name: name.clone() }),
+        EdgeDefinition::SpellTeleport { spell } => Definition::SpellTeleport(SpellTeleport { spell: spell.clone() }),
+        EdgeDefinition::ItemTeleport { item, action } => Definition::ItemTeleport(ItemTeleport { item: item.to_string(), action: action.to_string() }),
+    };
+    proto::EdgeDefinition { definition: Some(definition) }
+}
+
+fn to_step(step: &Step) -> proto::Step {
+    let kind = match step.kind {
+        StepKind::Walk => proto::StepKind::Walk,
+        StepKind::Edge => proto::StepKind::Edge,
+        StepKind::Teleport => proto::StepKind::Teleport,
+    };
+    proto::Step {
+        definition: Some(to_edge_definition(&step.definition)),
+        kind: kind as i32,
+        from: Some(to_coordinate(&step.from)),
+        to: Some(to_coordinate(&step.to)),
+        cost: step.cost,
+        arrival: step.arrival.as_ref().map(|area| proto::Area { min: Some(to_coordinate(&area.min)), max: Some(to_coordinate(&area.max)) }),
+    }
+}
+
+pub fn to_path(path: &PathResult) -> proto::Path {
+    proto::Path {
+        steps: path.path.steps.iter().map(to_step).collect(),
+        cost: path.path.cost,
+        tiles_walked: path.tiles_walked,
+        teleports_used: path.teleports_used,
+    }
+}
+
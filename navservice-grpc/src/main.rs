@@ -0,0 +1,151 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use clap::Parser;
+use expect_exit::ExpectedWithError;
+use tonic::{Request, Response, Status};
+use tonic::transport::Server;
+
+use model::{Area, Coordinate, Diagnostic, NavGrid};
+use pathfinder::{Aborted, PathResult, SearchOptions};
+
+use proto::nav_service_server::{NavService, NavServiceServer};
+
+mod convert;
+
+mod proto {
+    tonic::include_proto!("osrsnav.v1");
+}
+
+#[derive(Parser)]
+struct Options {
+    /// Path to NavGrid file
+    #[clap(short, long)]
+    navgrid: PathBuf,
+    /// Address to listen on
+    #[clap(short, long, default_value = "0.0.0.0:50051")]
+    address: SocketAddr,
+    /// Abort searches after expanding this many vertices
+    #[clap(long)]
+    max_visited: Option<u32>,
+}
+
+struct NavServer {
+    nav_grid: Arc<NavGrid>,
+    max_visited: Option<u32>,
+}
+
+impl NavServer {
+    /// Converts the requested search options, capping the visited vertices at the configured budget
+    fn options(&self, options: Option<proto::SearchOptions>) -> Result<SearchOptions, Status> {
+        let mut options = convert::search_options(options)?;
+        options.max_visited = match (options.max_visited, self.max_visited) {
+            (Some(requested), Some(max_visited)) => Some(requested.min(max_visited)),
+            (requested, max_visited) => requested.or(max_visited),
+        };
+        Ok(options)
+    }
+
+    /// Runs a search on the blocking thread pool, as searches can take long enough to stall the runtime
+    async fn search<T, F>(&self, search: F) -> Result<T, Status> where T: Send + 'static, F: FnOnce(&NavGrid) -> T + Send + 'static {
+        let nav_grid = self.nav_grid.clone();
+        tokio::task::spawn_blocking(move || search(&nav_grid)).await.map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+fn validate(start: &Coordinate, ends: &[Coordinate], options: &SearchOptions) -> Result<(), Status> {
+    if !options.avoid.iter().all(Area::validate) {
+        Err(Status::invalid_argument("Avoided area out of bounds"))
+    } else if options.avoid.iter().any(|area| area.contains(start) || ends.iter().any(|end| area.contains(end))) {
+        Err(Status::invalid_argument("Start or end inside of an avoided area"))
+    } else if !options.constraints.permits(start) || !ends.iter().all(|end| options.constraints.permits(end)) {
+        Err(Status::invalid_argument("Start or end violates the path constraints"))
+    } else {
+        Ok(())
+    }
+}
+
+fn aborted(aborted: Aborted) -> Status {
+    match aborted {
+        Aborted::BudgetExceeded => Status::resource_exhausted("Search budget exceeded"),
+        Aborted::Cancelled => Status::cancelled("Search cancelled"),
+    }
+}
+
+#[tonic::async_trait]
+impl NavService for NavServer {
+    async fn find_path(&self, request: Request<proto::PathRequest>) -> Result<Response<proto::PathResponse>, Status> {
+        let request = request.into_inner();
+        let start = convert::coordinate(request.start)?;
+        let end = convert::coordinate(request.end)?;
+        let game_state = convert::game_state(request.game_state)?;
+        let options = self.options(request.options)?;
+        validate(&start, &[end], &options)?;
+        let begin = Instant::now();
+        let (visited, _, path) = self.search(move |nav_grid| pathfinder::astar_with_options(nav_grid, &start, &end, &game_state, &options)).await?;
+        let duration = Instant::now() - begin;
+        let path = path.map_err(aborted)?.map(|path| PathResult::new(path, visited, duration));
+        Ok(Response::new(proto::PathResponse {
+            path: path.as_ref().map(convert::to_path),
+            visited: visited as u64,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+        }))
+    }
+
+    async fn find_nearest(&self, request: Request<proto::NearestRequest>) -> Result<Response<proto::NearestResponse>, Status> {
+        let request = request.into_inner();
+        let start = convert::coordinate(request.start)?;
+        let ends = request.ends.into_iter().map(|end| convert::coordinate(Some(end))).collect::<Result<Vec<_>, _>>()?;
+        let game_state = convert::game_state(request.game_state)?;
+        let options = self.options(request.options)?;
+        validate(&start, &[], &options)?;
+        let begin = Instant::now();
+        let (visited, _, reached) = self.search(move |nav_grid| pathfinder::dijkstra_multi_with_options(nav_grid, &start, &ends, &game_state, &options)).await?;
+        let duration = Instant::now() - begin;
+        let (end, path) = match reached.map_err(aborted)? {
+            Some((end, path)) => (end as u32, Some(PathResult::new(path, visited, duration))),
+            None => (0, None),
+        };
+        Ok(Response::new(proto::NearestResponse {
+            path: path.as_ref().map(convert::to_path),
+            end,
+            visited: visited as u64,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+        }))
+    }
+
+    async fn reachability(&self, request: Request<proto::ReachabilityRequest>) -> Result<Response<proto::ReachabilityResponse>, Status> {
+        let request = request.into_inner();
+        let start = convert::coordinate(request.start)?;
+        let end = convert::coordinate(request.end)?;
+        let game_state = convert::game_state(request.game_state)?;
+        let options = self.options(request.options)?;
+        validate(&start, &[end], &options)?;
+        let reachable = self.search(move |nav_grid| pathfinder::is_reachable_with_options(nav_grid, &start, &end, &game_state, &options)).await?;
+        Ok(Response::new(proto::ReachabilityResponse { reachable: reachable.map_err(aborted)? }))
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let options = Options::parse();
+    let nav_grid = model::util::load_nav_grid(&options.navgrid).or_exit_e_("Error loading NavGrid");
+    // Searches on a grid with fatal diagnostics can panic, so it isn't served at all
+    let diagnostics = nav_grid.validate();
+    for diagnostic in &diagnostics {
+        eprintln!("{} {}", if diagnostic.is_fatal() { "error:" } else { "warning:" }, diagnostic);
+    }
+    if diagnostics.iter().any(Diagnostic::is_fatal) {
+        eprintln!("Error loading NavGrid: {} has fatal diagnostics", options.navgrid.display());
+        std::process::exit(1);
+    }
+    let server = NavServer { nav_grid: Arc::new(nav_grid), max_visited: options.max_visited };
+    println!("Listening on {}", options.address);
+    Server::builder()
+        .add_service(NavServiceServer::new(server))
+        .serve(options.address)
+        .await
+        .or_exit_e_("Error serving requests");
+}
@@ -0,0 +1,101 @@
+use std::io::Cursor;
+
+use wasm_bindgen::prelude::*;
+
+use model::{Coordinate, NavGrid};
+use model::definitions::GameState;
+use pathfinder::SearchOptions;
+
+/// NavGrid decoded once and searched any number of times
+#[wasm_bindgen]
+pub struct Grid {
+    nav_grid: NavGrid,
+}
+
+#[wasm_bindgen]
+impl Grid {
+    /// Decodes a NavGrid file written by `save_nav_grid`
+    #[wasm_bindgen(constructor)]
+    pub fn new(grid_bytes: &[u8]) -> Result<Grid, JsError> {
+        let nav_grid = model::util::read_nav_grid(grid_bytes).map_err(|e| JsError::new(&format!("Error loading NavGrid: {}", e)))?;
+        Ok(Grid { nav_grid })
+    }
+
+    /// Opens a region-sharded NavGrid file limited to the regions with the given ids, see `NavGrid::load_regions`.
+    /// Only the file is kept in memory, the vertices of each region are decoded when a search first reaches it.
+    #[wasm_bindgen(js_name = fromRegions)]
+    pub fn from_regions(grid_bytes: Vec<u8>, region_ids: Vec<u16>) -> Result<Grid, JsError> {
+        let nav_grid = NavGrid::read_regions(Cursor::new(grid_bytes), &region_ids).map_err(|e| JsError::new(&format!("Error loading NavGrid: {}", e)))?;
+        Ok(Grid { nav_grid })
+    }
+
+    /// Searches a path like `POST /path` of the webservice. Start and end are coordinate JSON objects, the game
+    /// state and the optional search options use the same JSON as the webservice.
+    /// Returns the JSON of the path, or `null` if the end can't be reached.
+    #[wasm_bindgen(js_name = findPath)]
+    pub fn find_path(&self, start: &str, end: &str, state_json: &str, options_json: Option<String>) -> Result<String, JsError> {
+        let start: Coordinate = parse("start", start)?;
+        let end: Coordinate = parse("end", end)?;
+        let game_state: GameState = parse("game state", state_json)?;
+        let mut options: SearchOptions = match options_json {
+            Some(options) => parse("options", &options)?,
+            None => SearchOptions::default(),
+        };
+        if !start.validate() || !end.validate() {
+            return Err(JsError::new("Start and end must lie within the map"));
+        }
+        // There is no clock to measure time against in wasm32-unknown-unknown
+        options.max_millis = None;
+        let (_, _, path) = pathfinder::astar_with_options(&self.nav_grid, &start, &end, &game_state, &options);
+        let path = path.map_err(|_| JsError::new("Search budget exceeded"))?;
+        serde_json::to_string(&path).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+/// Collects a NavGrid file downloaded in chunks, e.g. from the chunks of a `fetch` response stream, so the download
+/// doesn't have to be buffered in JavaScript first
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct GridLoader {
+    bytes: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl GridLoader {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GridLoader {
+        GridLoader::default()
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend_from_slice(chunk);
+    }
+
+    /// Number of bytes received so far
+    #[wasm_bindgen(getter)]
+    pub fn received(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Decodes the received file, consuming the loader
+    pub fn finish(self) -> Result<Grid, JsError> {
+        Grid::new(&self.bytes)
+    }
+
+    /// Opens the received region-sharded file limited to the regions with the given ids, consuming the loader
+    #[wasm_bindgen(js_name = finishRegions)]
+    pub fn finish_regions(self, region_ids: Vec<u16>) -> Result<Grid, JsError> {
+        Grid::from_regions(self.bytes, region_ids)
+    }
+}
+
+/// Decodes `grid_bytes` and searches a single path, see [Grid::find_path]. Decoding the grid takes far longer than
+/// most searches, so [Grid] should be used for more than one search.
+#[wasm_bindgen(js_name = findPath)]
+pub fn find_path(grid_bytes: &[u8], start: &str, end: &str, state_json: &str) -> Result<String, JsError> {
+    Grid::new(grid_bytes)?.find_path(start, end, state_json, None)
+}
+
+fn parse<'a, T: serde::Deserialize<'a>>(name: &str, json: &'a str) -> Result<T, JsError> {
+    serde_json::from_str(json).map_err(|e| JsError::new(&format!("Invalid {}: {}", name, e)))
+}
@@ -0,0 +1,82 @@
+//! Compares evaluating the requirements of every gated edge on its own, with item quantities memoized, and by the ids of
+//! the distinct requirement lists, on a field where every tile has gated edges.
+//! Run with `cargo bench -p pathfinder --bench requirements`
+
+use std::time::{Duration, Instant};
+
+use model::{Coordinate, Edge, NavGrid};
+use model::definitions::{Compare, EdgeDefinition, GameState, QuestState, Regex, RequirementDefinition, RequirementEvaluator};
+use pathfinder::dijkstra;
+
+const SIZE: u16 = 200;
+const RUNS: u32 = 20;
+
+fn c(x: u16, y: u16) -> Coordinate {
+    Coordinate { x, y, plane: 0 }
+}
+
+/// Requirements like those of shortcuts and toll gates, with 60 distinct variants
+fn requirements(variant: u16) -> Vec<RequirementDefinition> {
+    let level = 1 + (variant % 30) as u8 * 3;
+    vec![
+        RequirementDefinition::Skill { skill: "AGILITY".to_string(), level },
+        RequirementDefinition::Item { item: Regex::new("^Coins$").unwrap(), quantity: 10, consumed: true },
+        RequirementDefinition::Any(vec![
+            RequirementDefinition::Quest { name: "Dragon Slayer I".to_string(), state: QuestState::Finished },
+            RequirementDefinition::Varbit { index: 4, value: (variant % 4) as i32, compare: Compare::GE },
+        ]),
+    ]
+}
+
+/// Field with a gated shortcut from every tile two tiles to the east
+fn gated_field() -> NavGrid {
+    let lists: Vec<Vec<RequirementDefinition>> = (0..60).map(requirements).collect();
+    let action = Regex::new("Climb-over").unwrap();
+    let mut builder = NavGrid::builder();
+    for x in 0..SIZE {
+        for y in 0..SIZE {
+            builder = builder.walkable(c(x, y));
+            if x + 2 < SIZE {
+                builder = builder.edge(c(x, y), Edge {
+                    destination: c(x + 2, y),
+                    cost: 1,
+                    definition: EdgeDefinition::GameObject { id: 1, name: None, position: c(x + 1, y), action: action.clone() },
+                    requirements: lists[((x + y) % 60) as usize].clone(),
+                    instance_plane: None,
+                    arrival: None,
+                });
+            }
+        }
+    }
+    builder.build()
+}
+
+/// Prints the average time of the runs, and the count the last one returned
+fn time(name: &str, counted: &str, mut run: impl FnMut() -> usize) {
+    let mut count = 0;
+    let begin = Instant::now();
+    for _ in 0..RUNS {
+        count = run();
+    }
+    let elapsed: Duration = begin.elapsed() / RUNS;
+    println!("{:<28} {:>8.2}ms, {} {}", name, elapsed.as_secs_f64() * 1000f64, count, counted);
+}
+
+fn main() {
+    let nav_grid = gated_field();
+    let game_state = GameState::builder().skill("Agility", 60).item("Coins", 100).varbit(4, 2).build();
+    let edges: Vec<(u32, usize, &Edge)> = nav_grid.edges.iter_all()
+        .flat_map(|(source, edges)| edges.iter().enumerate().map(move |(position, edge)| (*source, position, edge)))
+        .collect();
+    println!("{} gated edges, {} distinct requirement lists", edges.len(), nav_grid.requirement_ids().count() - 1);
+    time("each requirement", "edges met", || edges.iter().filter(|(_, _, edge)| edge.requirements.iter().all(|req| req.is_met(&game_state))).count());
+    time("memoized item quantities", "edges met", || {
+        let mut requirements = RequirementEvaluator::new(&game_state);
+        edges.iter().filter(|(_, _, edge)| requirements.all_met(&edge.requirements)).count()
+    });
+    time("requirement ids", "edges met", || {
+        let mut requirements = RequirementEvaluator::new(&game_state).requirement_ids(nav_grid.requirement_ids());
+        edges.iter().filter(|(source, position, edge)| requirements.edge_met(*source, *position, &edge.requirements)).count()
+    });
+    time("dijkstra across the field", "vertices expanded", || dijkstra(&nav_grid, &c(0, 0), &c(SIZE - 1, SIZE - 1), &game_state).0);
+}
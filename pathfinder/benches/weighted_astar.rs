@@ -0,0 +1,50 @@
+//! Compares the vertices expanded and the time taken by exact and weighted A* on a field with a wall between the start
+//! and the end, run with `cargo bench -p pathfinder --bench weighted_astar`
+
+use std::time::Instant;
+
+use model::{Coordinate, NavGrid};
+use model::definitions::GameState;
+use pathfinder::weighted_astar;
+
+const SIZE: u16 = 400;
+const RUNS: u32 = 20;
+
+fn c(x: u16, y: u16) -> Coordinate {
+    Coordinate { x, y, plane: 0 }
+}
+
+/// Field with a wall across its middle, which the path has to go around
+fn walled_field() -> NavGrid {
+    let wall = |x: u16, y: u16| x == SIZE / 2 && (150..250).contains(&y);
+    let mut builder = NavGrid::builder();
+    for x in 0..SIZE {
+        for y in 0..SIZE {
+            if !wall(x, y) {
+                builder = builder.walkable(c(x, y));
+            }
+        }
+    }
+    builder.build()
+}
+
+fn main() {
+    let nav_grid = walled_field();
+    let game_state = GameState::default();
+    let (start, end) = (c(2, 100), c(SIZE - 3, 300));
+    let mut exact_cost = None;
+    println!("{:>8} {:>10} {:>8} {:>10}", "weight", "expanded", "cost", "time");
+    for weight in [1.0, 1.2, 1.5, 2.0] {
+        let begin = Instant::now();
+        let mut result = None;
+        for _ in 0..RUNS {
+            result = Some(weighted_astar(&nav_grid, &start, &end, &game_state, weight));
+        }
+        let elapsed = begin.elapsed() / RUNS;
+        let (count, _, path) = result.unwrap();
+        let cost = path.expect("The end is reachable").cost;
+        let exact_cost = *exact_cost.get_or_insert(cost);
+        assert!(cost as f32 <= exact_cost as f32 * weight, "Cost {} exceeds {} times the optimum {}", cost, weight, exact_cost);
+        println!("{:>8.1} {:>10} {:>8} {:>8.2}ms", weight, count, cost, elapsed.as_secs_f64() * 1000f64);
+    }
+}
@@ -0,0 +1,608 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+
+use rayon::prelude::*;
+
+use model::{Coordinate, Edge, NavGrid};
+use model::constants::*;
+use model::definitions::{EdgeDefinition, GameState, RequirementEvaluator};
+
+use crate::{Aborted, available_teleports, can_move, can_teleport_from, dijkstra_with_options, Path, SearchOptions, Step, StepKind};
+
+/// Identifies contraction hierarchy files, followed by the [FORMAT_VERSION] they were written with
+pub const MAGIC: [u8; 4] = *b"ONCH";
+/// Version of the file format written by [ContractionHierarchy::save]. Version 2 added the checksum of the NavGrid.
+pub const FORMAT_VERSION: u16 = 2;
+/// Absent node, arc or edge
+const NONE: u32 = u32::MAX;
+/// Nodes settled by a witness search before it gives up, adding a shortcut that may not be needed
+const WITNESS_LIMIT: usize = 500;
+
+/// Directed arc of the hierarchy, either walking to an adjacent tile, taking an edge or a shortcut of two arcs
+#[derive(Clone, Copy, Debug)]
+struct Arc {
+    /// Node at the other end of the arc
+    node: u32,
+    cost: u32,
+    /// Node bypassed by a shortcut, [NONE] for arcs of the NavGrid
+    middle: u32,
+    /// Position of the edge taken in the edges of the source vertex, [NONE] for walking
+    edge: u32,
+}
+
+/// Contraction hierarchy of a NavGrid, answering cross-map queries by searching a few thousand nodes instead of
+/// millions of tiles. Preprocessing contracts the tiles in order of importance, adding shortcuts that preserve the
+/// costs between the remaining ones. Queries search upwards in the hierarchy from both ends until the searches meet.
+///
+/// The hierarchy only contains walking and edges without requirements, teleports are added to each query. Searches
+/// it doesn't [cover](ContractionHierarchy::covers), because the game state meets the requirements of other edges or
+/// the options add obstacles, have to fall back to a regular search.
+pub struct ContractionHierarchy {
+    /// Checksum in the [Header](model::util::Header) of the NavGrid file it was built from, 0 for headerless files,
+    /// along with the vertices and edges of the NavGrid, to reject loading it for another NavGrid
+    checksum: u32,
+    vertex_count: u64,
+    edge_count: u64,
+    /// Vertex index of every node, ascending
+    nodes: Vec<u32>,
+    /// Arcs of each node leading to higher ranked nodes, from `up[up_offsets[node]]` to `up[up_offsets[node + 1]]`
+    up_offsets: Vec<u32>,
+    up: Vec<Arc>,
+    /// Arcs leading to each node from higher ranked nodes, with `node` being the source
+    down_offsets: Vec<u32>,
+    down: Vec<Arc>,
+}
+
+/// Edges that can always be taken, independent of the game state
+fn is_static(edge: &Edge) -> bool {
+    edge.requirements.is_empty() && edge.instance_plane.is_none()
+}
+
+/// Arcs of all nodes while contracting, incoming arcs carry the source in `node`
+struct Graph {
+    outgoing: Vec<Vec<Arc>>,
+    incoming: Vec<Vec<Arc>>,
+}
+
+impl Graph {
+    /// Adds the arc unless there is a cheaper one between the nodes already, which it replaces otherwise
+    fn add_arc(&mut self, from: u32, to: u32, cost: u32, middle: u32, edge: u32) {
+        if from == to {
+            return;
+        }
+        let outgoing = Arc { node: to, cost, middle, edge };
+        let incoming = Arc { node: from, cost, middle, edge };
+        match self.outgoing[from as usize].iter().position(|arc| arc.node == to) {
+            Some(i) if self.outgoing[from as usize][i].cost <= cost => {}
+            Some(i) => {
+                self.outgoing[from as usize][i] = outgoing;
+                let j = self.incoming[to as usize].iter().position(|arc| arc.node == from).expect("Arcs are added in both directions");
+                self.incoming[to as usize][j] = incoming;
+            }
+            None => {
+                self.outgoing[from as usize].push(outgoing);
+                self.incoming[to as usize].push(incoming);
+            }
+        }
+    }
+
+    /// Shortcuts `(from, to, cost)` needed to preserve the costs between the neighbors of the node once it is contracted
+    fn shortcuts(&self, node: u32, contracted: &[bool], witness: &mut WitnessSearch) -> Vec<(u32, u32, u32)> {
+        let mut shortcuts = Vec::new();
+        let targets: Vec<&Arc> = self.outgoing[node as usize].iter().filter(|arc| !contracted[arc.node as usize]).collect();
+        let max_target_cost = match targets.iter().map(|arc| arc.cost).max() {
+            Some(cost) => cost,
+            None => return shortcuts,
+        };
+        for incoming in self.incoming[node as usize].iter().filter(|arc| !contracted[arc.node as usize]) {
+            witness.run(self, incoming.node, node, incoming.cost.saturating_add(max_target_cost), contracted);
+            for target in &targets {
+                let cost = incoming.cost.saturating_add(target.cost);
+                if target.node != incoming.node && witness.cost(target.node) > cost {
+                    shortcuts.push((incoming.node, target.node, cost));
+                }
+            }
+        }
+        shortcuts
+    }
+
+    /// Contraction order, nodes adding fewer shortcuts than arcs they remove and whose neighbors were contracted less
+    /// often go first, which keeps the hierarchy sparse and balanced
+    fn priority(&self, node: u32, contracted: &[bool], deleted: &[u32], witness: &mut WitnessSearch) -> i64 {
+        let shortcuts = self.shortcuts(node, contracted, witness).len() as i64;
+        let degree = self.outgoing[node as usize].iter().chain(&self.incoming[node as usize]).filter(|arc| !contracted[arc.node as usize]).count() as i64;
+        shortcuts - degree + deleted[node as usize] as i64
+    }
+}
+
+/// Bounded search for paths between neighbors of a node that avoid it, which make shortcuts over it unnecessary
+#[derive(Default)]
+struct WitnessSearch {
+    costs: HashMap<u32, u32>,
+    queue: BinaryHeap<Reverse<(u32, u32)>>,
+}
+
+impl WitnessSearch {
+    fn run(&mut self, graph: &Graph, source: u32, skipped: u32, max_cost: u32, contracted: &[bool]) {
+        self.costs.clear();
+        self.queue.clear();
+        self.costs.insert(source, 0);
+        self.queue.push(Reverse((0, source)));
+        let mut settled = 0;
+        while let Some(Reverse((cost, node))) = self.queue.pop() {
+            if cost > self.cost(node) {
+                continue;
+            }
+            if cost > max_cost || settled == WITNESS_LIMIT {
+                break;
+            }
+            settled += 1;
+            for arc in &graph.outgoing[node as usize] {
+                if arc.node == skipped || contracted[arc.node as usize] {
+                    continue;
+                }
+                let next = cost.saturating_add(arc.cost);
+                let known = self.costs.entry(arc.node).or_insert(u32::MAX);
+                if next < *known {
+                    *known = next;
+                    self.queue.push(Reverse((next, arc.node)));
+                }
+            }
+        }
+    }
+
+    fn cost(&self, node: u32) -> u32 {
+        self.costs.get(&node).copied().unwrap_or(u32::MAX)
+    }
+}
+
+/// Cost of a node reached by a query and the node and arc it was reached from.
+/// Nodes seeded by a teleport have no previous node and carry the position of the teleport instead of an arc.
+#[derive(Clone, Copy)]
+struct Label {
+    cost: u32,
+    prev: u32,
+    arc: u32,
+}
+
+/// One direction of a bidirectional query
+#[derive(Default)]
+struct QuerySearch {
+    labels: HashMap<u32, Label>,
+    queue: BinaryHeap<Reverse<(u32, u32)>>,
+}
+
+impl QuerySearch {
+    fn push(&mut self, node: u32, label: Label) {
+        if label.cost < self.labels.get(&node).map_or(u32::MAX, |known| known.cost) {
+            self.labels.insert(node, label);
+            self.queue.push(Reverse((label.cost, node)));
+        }
+    }
+
+    fn min_cost(&self) -> u32 {
+        self.queue.peek().map_or(u32::MAX, |Reverse((cost, _))| *cost)
+    }
+
+    /// Settles the cheapest node, relaxing its arcs and updating the best meeting node with the other direction.
+    /// Returns false for outdated queue entries.
+    fn settle(&mut self, offsets: &[u32], arcs: &[Arc], other: &QuerySearch, best: &mut (u32, u32)) -> bool {
+        let Reverse((cost, node)) = self.queue.pop().expect("Only called with a non-empty queue");
+        if cost > self.labels[&node].cost {
+            return false;
+        }
+        if let Some(other) = other.labels.get(&node) {
+            if cost.saturating_add(other.cost) < best.0 {
+                *best = (cost.saturating_add(other.cost), node);
+            }
+        }
+        for i in offsets[node as usize]..offsets[node as usize + 1] {
+            let arc = &arcs[i as usize];
+            self.push(arc.node, Label { cost: cost.saturating_add(arc.cost), prev: node, arc: i });
+        }
+        true
+    }
+
+    fn mem_usage(&self) -> usize {
+        self.labels.capacity() * std::mem::size_of::<(u32, Label)>() + self.queue.capacity() * std::mem::size_of::<Reverse<(u32, u32)>>()
+    }
+}
+
+impl ContractionHierarchy {
+    /// Contracts the walkable tiles of the NavGrid and the edges without requirements between them.
+    /// Takes long and a lot of memory on the full map, so it is meant to run once after generating the NavGrid.
+    /// `checksum` is the one in the header of the NavGrid file, see [load](ContractionHierarchy::load).
+    pub fn build(nav_grid: &NavGrid, checksum: u32) -> ContractionHierarchy {
+        let mut nodes: Vec<u32> = (0..nav_grid.vertices.len() as u32).into_par_iter()
+            .filter(|index| nav_grid.vertices[*index as usize].flags != 0)
+            .collect();
+        for (index, edges) in nav_grid.edges.iter_all() {
+            nodes.push(*index);
+            nodes.extend(edges.iter().map(|edge| edge.destination.index()));
+        }
+        nodes.extend(nav_grid.teleports.iter().map(|teleport| teleport.destination.index()));
+        nodes.sort_unstable();
+        nodes.dedup();
+        let node_of = |index: u32| nodes.binary_search(&index).ok().map(|node| node as u32);
+
+        let mut graph = Graph { outgoing: vec![Vec::new(); nodes.len()], incoming: vec![Vec::new(); nodes.len()] };
+        for (node, index) in nodes.iter().enumerate() {
+            for (flag, dx, dy) in &DIRECTIONS {
+                if can_move(nav_grid, *index, *flag, *dx, *dy) {
+                    if let Some(adj) = node_of((*index as i32 + (WIDTH as i32 * *dy) + *dx) as u32) {
+                        graph.add_arc(node as u32, adj, 1, NONE, NONE);
+                    }
+                }
+            }
+            if nav_grid.vertices[*index as usize].has_extra_edges() {
+                for (position, edge) in nav_grid.edges.get_vec(index).into_iter().flatten().enumerate() {
+                    if is_static(edge) {
+                        if let Some(destination) = node_of(edge.destination.index()) {
+                            graph.add_arc(node as u32, destination, edge.cost, NONE, position as u32);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut contracted = vec![false; nodes.len()];
+        let mut deleted = vec![0; nodes.len()];
+        let mut ranks = vec![0; nodes.len()];
+        let mut queue: BinaryHeap<Reverse<(i64, u32)>> = (0..nodes.len() as u32).into_par_iter()
+            .map_init(WitnessSearch::default, |witness, node| Reverse((graph.priority(node, &contracted, &deleted, witness), node)))
+            .collect::<Vec<_>>()
+            .into();
+        let mut witness = WitnessSearch::default();
+        let mut rank = 0;
+        while let Some(Reverse((_, node))) = queue.pop() {
+            // Priorities change as neighbors are contracted, they are only updated once the node comes up
+            let priority = graph.priority(node, &contracted, &deleted, &mut witness);
+            if queue.peek().map_or(false, |Reverse((next, _))| priority > *next) {
+                queue.push(Reverse((priority, node)));
+                continue;
+            }
+            for (from, to, cost) in graph.shortcuts(node, &contracted, &mut witness) {
+                graph.add_arc(from, to, cost, node, NONE);
+            }
+            contracted[node as usize] = true;
+            ranks[node as usize] = rank;
+            rank += 1;
+            for arc in graph.outgoing[node as usize].iter().chain(&graph.incoming[node as usize]) {
+                if !contracted[arc.node as usize] {
+                    deleted[arc.node as usize] += 1;
+                }
+            }
+        }
+
+        let mut up = vec![Vec::new(); nodes.len()];
+        let mut down = vec![Vec::new(); nodes.len()];
+        for (from, arcs) in graph.outgoing.into_iter().enumerate() {
+            for arc in arcs {
+                if ranks[from] < ranks[arc.node as usize] {
+                    up[from].push(arc);
+                } else {
+                    down[arc.node as usize].push(Arc { node: from as u32, ..arc });
+                }
+            }
+        }
+        let (up_offsets, up) = flatten(up);
+        let (down_offsets, down) = flatten(down);
+        ContractionHierarchy {
+            checksum,
+            vertex_count: nav_grid.vertices.len() as u64,
+            edge_count: nav_grid.iter_edges().count() as u64,
+            nodes,
+            up_offsets,
+            up,
+            down_offsets,
+            down,
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Number of arcs, including shortcuts
+    pub fn arc_count(&self) -> usize {
+        self.up.len() + self.down.len()
+    }
+
+    fn node(&self, coordinate: &Coordinate) -> Option<u32> {
+        self.nodes.binary_search(&coordinate.index()).ok().map(|node| node as u32)
+    }
+
+    /// Whether [query](ContractionHierarchy::query) finds the same cost as a regular search. Edges missing from the
+    /// hierarchy could lead to cheaper paths if the game state meets their requirements, and obstacles of the options
+    /// aren't part of it. Past the teleports cast from the start, paths never leave the group of the end, so only edges
+    /// in that group are considered. Most of the map is a single group though, so a game state meeting the
+    /// requirements of any edge on it usually isn't covered.
+    pub fn covers(&self, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> bool {
+        if !options.blocked.is_empty() || !options.avoid.is_empty() || !options.disabled.is_empty() || options.strict_consumables || options.constraints.wilderness_cap().is_some() {
+            return false;
+        }
+        // Teleports are only cast from the start, paths starting deep in the Wilderness have to walk out of it first
+        if self.node(start).is_none() || self.node(end).is_none() || !can_teleport_from(start.index()) {
+            return false;
+        }
+        let target_group = nav_grid.vertices[end.index() as usize].get_group();
+        let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids()).ignore_requirements(options.ignore_requirements);
+        let mut gated = nav_grid.edges.iter_all()
+            .filter(|(source, _)| nav_grid.vertices.get(**source as usize).map_or(false, |v| v.get_group() == target_group))
+            .flat_map(|(source, edges)| edges.iter().enumerate().map(move |(position, edge)| (*source, position, edge)))
+            .filter(|(_, _, edge)| !is_static(edge));
+        !gated.any(|(source, position, edge)| requirements.edge_met(source, position, &edge.requirements))
+    }
+
+    /// Cheapest path from start to end within the hierarchy, only equal to the path of a regular search if the
+    /// hierarchy [covers](ContractionHierarchy::covers) the search
+    pub fn query(&self, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+        let (source, target) = match (self.node(start), self.node(end)) {
+            (Some(source), Some(target)) => (source, target),
+            _ => return (0, 0, Ok(None)),
+        };
+        let mut forward = QuerySearch::default();
+        let mut backward = QuerySearch::default();
+        forward.push(source, Label { cost: 0, prev: NONE, arc: NONE });
+        let teleports: Vec<(u32, &Edge)> = available_teleports(nav_grid, game_state, options.ignore_requirements).collect();
+        for (position, (index, teleport)) in teleports.iter().enumerate() {
+            if let Ok(node) = self.nodes.binary_search(index) {
+                forward.push(node as u32, Label { cost: teleport.cost, prev: NONE, arc: position as u32 });
+            }
+        }
+        backward.push(target, Label { cost: 0, prev: NONE, arc: NONE });
+
+        let mut best = (u32::MAX, NONE);
+        let budget = options.budget();
+        let mut count = 0;
+        loop {
+            let (forward_min, backward_min) = (forward.min_cost(), backward.min_cost());
+            if forward_min.min(backward_min) >= best.0 {
+                break;
+            }
+            if let Err(aborted) = budget.check(count, forward_min.min(backward_min), || forward.mem_usage() + backward.mem_usage()) {
+                return (count, forward.mem_usage() + backward.mem_usage(), Err(aborted));
+            }
+            let settled = if forward_min <= backward_min {
+                forward.settle(&self.up_offsets, &self.up, &backward, &mut best)
+            } else {
+                backward.settle(&self.down_offsets, &self.down, &forward, &mut best)
+            };
+            if settled {
+                count += 1;
+            }
+        }
+        let mem_usage = forward.mem_usage() + backward.mem_usage();
+        if best.1 == NONE {
+            return (count, mem_usage, Ok(None));
+        }
+
+        let mut arcs = Vec::new();
+        let mut node = best.1;
+        let teleport = loop {
+            let label = forward.labels[&node];
+            if label.prev == NONE {
+                break Some(label.arc).filter(|arc| *arc != NONE).map(|position| teleports[position as usize]);
+            }
+            arcs.push((label.prev, node, self.up[label.arc as usize]));
+            node = label.prev;
+        };
+        arcs.reverse();
+        let mut node = best.1;
+        loop {
+            let label = backward.labels[&node];
+            if label.prev == NONE {
+                break;
+            }
+            arcs.push((node, label.prev, self.down[label.arc as usize]));
+            node = label.prev;
+        }
+
+        let mut steps = Vec::new();
+        if let Some((index, teleport)) = teleport {
+            let requirements = if options.annotate_requirements { teleport.requirements.clone() } else { Vec::new() };
+            steps.push(Step { definition: teleport.definition.clone(), kind: StepKind::Teleport, from: *start, to: Coordinate::from_index(index), cost: teleport.cost, requirements, arrival: teleport.arrival });
+        }
+        let mut unpacked = Vec::new();
+        for (from, to, arc) in arcs {
+            self.unpack(from, to, &arc, &mut unpacked);
+        }
+        for (from, to, edge) in unpacked {
+            let (from, to) = (self.nodes[from as usize], self.nodes[to as usize]);
+            steps.push(if edge == NONE {
+                Step { definition: EdgeDefinition::Step { position: Coordinate::from_index(to) }, kind: StepKind::Walk, from: Coordinate::from_index(from), to: Coordinate::from_index(to), cost: 1, requirements: Vec::new(), arrival: None }
+            } else {
+                let edge = &nav_grid.edges.get_vec(&from).expect("Hierarchy matches the NavGrid")[edge as usize];
+                Step { definition: edge.definition.clone(), kind: StepKind::Edge, from: Coordinate::from_index(from), to: edge.destination, cost: edge.cost, requirements: Vec::new(), arrival: edge.arrival }
+            });
+        }
+        (count, mem_usage, Ok(Some(Path { steps, cost: best.0 })))
+    }
+
+    /// Replaces shortcuts by the arcs of the NavGrid they consist of, as `(from, to, edge)`
+    fn unpack(&self, from: u32, to: u32, arc: &Arc, unpacked: &mut Vec<(u32, u32, u32)>) {
+        if arc.middle == NONE {
+            unpacked.push((from, to, arc.edge));
+            return;
+        }
+        // The bypassed node was contracted before both ends, so the arc to it leads down and the arc from it up
+        let middle = arc.middle as usize;
+        let first = self.down[self.down_offsets[middle] as usize..self.down_offsets[middle + 1] as usize].iter()
+            .filter(|a| a.node == from)
+            .min_by_key(|a| a.cost)
+            .expect("Shortcuts consist of existing arcs");
+        let second = self.up[self.up_offsets[middle] as usize..self.up_offsets[middle + 1] as usize].iter()
+            .filter(|a| a.node == to)
+            .min_by_key(|a| a.cost)
+            .expect("Shortcuts consist of existing arcs");
+        self.unpack(from, arc.middle, first, unpacked);
+        self.unpack(arc.middle, to, second, unpacked);
+    }
+
+    /// Cheapest path from start to end, using the hierarchy if it covers the search and [dijkstra_with_options] otherwise
+    pub fn find_path(&self, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+        if self.covers(nav_grid, start, end, game_state, options) {
+            self.query(nav_grid, start, end, game_state, options)
+        } else {
+            dijkstra_with_options(nav_grid, start, end, game_state, options)
+        }
+    }
+
+    /// Writes the hierarchy as [MAGIC] and [FORMAT_VERSION], followed by the checksum of its NavGrid and its arrays of
+    /// little endian integers
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.checksum.to_le_bytes())?;
+        writer.write_all(&self.vertex_count.to_le_bytes())?;
+        writer.write_all(&self.edge_count.to_le_bytes())?;
+        write_u32s(&mut writer, &self.nodes)?;
+        write_u32s(&mut writer, &self.up_offsets)?;
+        write_arcs(&mut writer, &self.up)?;
+        write_u32s(&mut writer, &self.down_offsets)?;
+        write_arcs(&mut writer, &self.down)?;
+        writer.flush()
+    }
+
+    /// Reads a hierarchy written by [save](ContractionHierarchy::save), which has to be built from the NavGrid. Grids
+    /// of the same size can differ in their tiles, so the hierarchy is only loaded if it was built with the `checksum`
+    /// in the header of the NavGrid file.
+    pub fn load(path: impl AsRef<std::path::Path>, nav_grid: &NavGrid, checksum: u32) -> io::Result<ContractionHierarchy> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Not a contraction hierarchy"));
+        }
+        let version = u16::from_le_bytes(read_bytes(&mut reader)?);
+        if version > FORMAT_VERSION {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("Unsupported contraction hierarchy version {}", version)));
+        }
+        if version < 2 {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Contraction hierarchy doesn't record the checksum of its NavGrid, it has to be rebuilt"));
+        }
+        let file_checksum = u32::from_le_bytes(read_bytes(&mut reader)?);
+        let vertex_count = u64::from_le_bytes(read_bytes(&mut reader)?);
+        let edge_count = u64::from_le_bytes(read_bytes(&mut reader)?);
+        if file_checksum != checksum || vertex_count != nav_grid.vertices.len() as u64 || edge_count != nav_grid.iter_edges().count() as u64 {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Contraction hierarchy was built from another NavGrid"));
+        }
+        Ok(ContractionHierarchy {
+            checksum,
+            vertex_count,
+            edge_count,
+            nodes: read_u32s(&mut reader)?,
+            up_offsets: read_u32s(&mut reader)?,
+            up: read_arcs(&mut reader)?,
+            down_offsets: read_u32s(&mut reader)?,
+            down: read_arcs(&mut reader)?,
+        })
+    }
+}
+
+/// Concatenates the arcs of all nodes, returning the offsets of the arcs of each node and one past the last arc
+fn flatten(arcs: Vec<Vec<Arc>>) -> (Vec<u32>, Vec<Arc>) {
+    let mut offsets = Vec::with_capacity(arcs.len() + 1);
+    let mut flattened = Vec::with_capacity(arcs.iter().map(Vec::len).sum());
+    for node_arcs in arcs {
+        offsets.push(flattened.len() as u32);
+        flattened.extend(node_arcs);
+    }
+    offsets.push(flattened.len() as u32);
+    (offsets, flattened)
+}
+
+fn write_u32s(writer: &mut impl Write, values: &[u32]) -> io::Result<()> {
+    writer.write_all(&(values.len() as u64).to_le_bytes())?;
+    values.iter().try_for_each(|value| writer.write_all(&value.to_le_bytes()))
+}
+
+fn write_arcs(writer: &mut impl Write, arcs: &[Arc]) -> io::Result<()> {
+    writer.write_all(&(arcs.len() as u64).to_le_bytes())?;
+    arcs.iter().try_for_each(|arc| {
+        [arc.node, arc.cost, arc.middle, arc.edge].iter().try_for_each(|value| writer.write_all(&value.to_le_bytes()))
+    })
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32s(reader: &mut impl Read) -> io::Result<Vec<u32>> {
+    let len = u64::from_le_bytes(read_bytes(reader)?);
+    (0..len).map(|_| Ok(u32::from_le_bytes(read_bytes(reader)?))).collect()
+}
+
+fn read_arcs(reader: &mut impl Read) -> io::Result<Vec<Arc>> {
+    let len = u64::from_le_bytes(read_bytes(reader)?);
+    (0..len).map(|_| {
+        let [node, cost, middle, edge] = [(); 4].map(|_| read_bytes(reader).map(u32::from_le_bytes));
+        Ok(Arc { node: node?, cost: cost?, middle: middle?, edge: edge? })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use model::Area;
+    use model::definitions::{Regex, RequirementDefinition};
+
+    use crate::{dijkstra, par_label_groups};
+
+    use super::*;
+
+    fn c(x: u16, y: u16) -> Coordinate {
+        Coordinate { x, y, plane: 0 }
+    }
+
+    /// Shortcut between the corners of a room requiring Agility level 50
+    fn shortcut(source: Coordinate, destination: Coordinate) -> Edge {
+        Edge {
+            destination,
+            cost: 2,
+            definition: EdgeDefinition::GameObject { id: 1, name: None, position: source, action: Regex::new("Climb").unwrap() },
+            requirements: vec![RequirementDefinition::Skill { skill: "AGILITY".to_string(), level: 50 }],
+            instance_plane: None,
+            arrival: None,
+        }
+    }
+
+    /// Two rooms that aren't connected, the eastern one has a shortcut
+    fn rooms() -> NavGrid {
+        let mut nav_grid = NavGrid::builder()
+            .walkable_area(&Area { min: c(0, 0), max: c(9, 9) })
+            .walkable_area(&Area { min: c(20, 0), max: c(29, 9) })
+            .edge(c(20, 0), shortcut(c(20, 0), c(29, 9)))
+            .build();
+        par_label_groups(&mut nav_grid, |_| true);
+        nav_grid
+    }
+
+    #[test]
+    fn hierarchies_load_only_for_the_checksum_they_were_built_with() {
+        let nav_grid = rooms();
+        let path = std::env::temp_dir().join(format!("osrs-nav-ch-{}.ch", std::process::id()));
+        ContractionHierarchy::build(&nav_grid, 0x1234).save(&path).unwrap();
+        assert!(ContractionHierarchy::load(&path, &nav_grid, 0x1234).is_ok());
+        let error = ContractionHierarchy::load(&path, &nav_grid, 0x4321).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn gated_edges_outside_the_group_of_the_end_are_covered() {
+        let nav_grid = rooms();
+        let hierarchy = ContractionHierarchy::build(&nav_grid, 0);
+        let agile = GameState::builder().skill("Agility", 50).build();
+        let options = SearchOptions::default();
+        assert!(hierarchy.covers(&nav_grid, &c(0, 0), &c(9, 9), &agile, &options));
+        assert!(!hierarchy.covers(&nav_grid, &c(20, 0), &c(29, 9), &agile, &options));
+        assert!(hierarchy.covers(&nav_grid, &c(20, 0), &c(29, 9), &GameState::default(), &options));
+        let (_, _, path) = hierarchy.query(&nav_grid, &c(0, 0), &c(9, 9), &agile, &options);
+        assert_eq!(path.unwrap().map(|path| path.cost), dijkstra(&nav_grid, &c(0, 0), &c(9, 9), &agile).2.map(|path| path.cost));
+    }
+}
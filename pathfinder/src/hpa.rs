@@ -0,0 +1,311 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use rayon::prelude::*;
+
+use model::{Coordinate, NavGrid};
+use model::constants::*;
+use model::definitions::{EdgeDefinition, GameState, RequirementEvaluator};
+
+use crate::{Aborted, available_teleports, can_move, can_teleport_from, Path, SearchOptions, Step, StepKind};
+
+/// Width and height of the chunks the map is partitioned into, which are the map regions
+pub const CHUNK_SIZE: u16 = REGION_SIZE as u16;
+/// Absent edge of an arc that walks
+const NONE: u32 = u32::MAX;
+
+/// Arc of the region graph, either walking within a chunk, stepping over a chunk border or taking an edge
+#[derive(Clone, Copy)]
+struct Arc {
+    node: u32,
+    cost: u32,
+    /// Position of the edge taken in the edges of the source vertex, [NONE] for walking
+    edge: u32,
+}
+
+/// Abstract graph for hierarchical pathfinding (HPA*). The map is partitioned into chunks of [CHUNK_SIZE] tiles, each
+/// passable stretch of a chunk border gets one entrance in its middle, connected to the entrances of the same chunk by
+/// the cost of walking between them within the chunk. Sources and destinations of edges and teleports are entrances
+/// as well, connected by the edge.
+///
+/// Searching the graph only expands a few entrances per chunk, but the paths found are approximate: they cross chunk
+/// borders at entrances only, and ignore [SearchOptions] other than the requirements and the budget.
+pub struct RegionGraph {
+    /// Vertex index of every entrance, ascending
+    entrances: Vec<u32>,
+    /// Arcs of each entrance, from `arcs[offsets[node]]` to `arcs[offsets[node + 1]]`
+    offsets: Vec<u32>,
+    arcs: Vec<Arc>,
+}
+
+fn chunk(index: u32) -> (u16, u16, u8) {
+    let coordinate = Coordinate::from_index(index);
+    (coordinate.x / CHUNK_SIZE, coordinate.y / CHUNK_SIZE, coordinate.plane)
+}
+
+/// Cost of walking from (or with `reverse`, to) the start to every tile of its chunk it is connected to within the
+/// chunk, and the previous (or next) tile on the way
+fn local_search(nav_grid: &NavGrid, start: u32, reverse: bool) -> HashMap<u32, (u32, u32)> {
+    let start_chunk = chunk(start);
+    let mut reached = HashMap::new();
+    let mut queue = VecDeque::new();
+    reached.insert(start, (0, start));
+    queue.push_back(start);
+    while let Some(index) = queue.pop_front() {
+        let cost = reached[&index].0;
+        for (flag, dx, dy) in &DIRECTIONS {
+            let offset = (WIDTH as i32 * *dy) + *dx;
+            let adj = if reverse { index as i64 - offset as i64 } else { index as i64 + offset as i64 };
+            if adj < 0 || adj >= nav_grid.vertices.len() as i64 || chunk(adj as u32) != start_chunk || reached.contains_key(&(adj as u32)) {
+                continue;
+            }
+            let (from, adj) = if reverse { (adj as u32, adj as u32) } else { (index, adj as u32) };
+            if can_move(nav_grid, from, *flag, *dx, *dy) {
+                reached.insert(adj, (cost + 1, index));
+                queue.push_back(adj);
+            }
+        }
+    }
+    reached
+}
+
+/// Walking steps from the start of a [local_search] to the tile, or from the tile to the start of a reverse search
+fn local_steps(reached: &HashMap<u32, (u32, u32)>, mut index: u32, reverse: bool) -> Vec<Step> {
+    let mut steps = Vec::new();
+    loop {
+        let prev = reached[&index].1;
+        if prev == index {
+            break;
+        }
+        let (from, to) = if reverse { (index, prev) } else { (prev, index) };
+        steps.push(Step { definition: EdgeDefinition::Step { position: Coordinate::from_index(to) }, kind: StepKind::Walk, from: Coordinate::from_index(from), to: Coordinate::from_index(to), cost: 1, requirements: Vec::new(), arrival: None });
+        index = prev;
+    }
+    if !reverse {
+        steps.reverse();
+    }
+    steps
+}
+
+/// Middle tiles of the passable stretches of the border between the chunk at `origin` and its neighbor in direction
+/// `(dx, dy)`, with the tile across the border
+fn border_entrances(nav_grid: &NavGrid, origin: Coordinate, dx: i32, dy: i32, entrances: &mut Vec<(u32, u32)>) {
+    let (forward, backward) = match (dx, dy) {
+        (1, 0) => (FLAG_E, FLAG_W),
+        _ => (FLAG_N, FLAG_S),
+    };
+    let offset = (WIDTH as i32 * dy) + dx;
+    let mut stretch = Vec::new();
+    for i in 0..=CHUNK_SIZE {
+        let crossing = (i < CHUNK_SIZE).then(|| {
+            let (x, y) = if dx == 1 { (origin.x + CHUNK_SIZE - 1, origin.y + i) } else { (origin.x + i, origin.y + CHUNK_SIZE - 1) };
+            let inside = Coordinate { x, y, plane: origin.plane };
+            let outside = Coordinate { x: x + dx as u16, y: y + dy as u16, plane: origin.plane };
+            (inside.validate() && outside.validate()).then(|| (inside.index(), (inside.index() as i32 + offset) as u32))
+        }).flatten().filter(|(inside, outside)| {
+            can_move(nav_grid, *inside, forward, dx, dy) || can_move(nav_grid, *outside, backward, -dx, -dy)
+        });
+        match crossing {
+            Some(crossing) => stretch.push(crossing),
+            None if !stretch.is_empty() => {
+                entrances.push(stretch[stretch.len() / 2]);
+                stretch.clear();
+            }
+            None => {}
+        }
+    }
+}
+
+impl RegionGraph {
+    /// Finds the entrances of all chunks and the walking costs between them, in parallel per chunk
+    pub fn build(nav_grid: &NavGrid) -> RegionGraph {
+        let chunks: Vec<Coordinate> = (0..PLANES as u8)
+            .flat_map(|plane| (0..WIDTH as u16 / CHUNK_SIZE).flat_map(move |x| (0..HEIGHT as u16 / CHUNK_SIZE).map(move |y| Coordinate { x: x * CHUNK_SIZE, y: y * CHUNK_SIZE, plane })))
+            .collect();
+        let crossings: Vec<(u32, u32)> = chunks.par_iter()
+            .flat_map_iter(|origin| {
+                let mut crossings = Vec::new();
+                border_entrances(nav_grid, *origin, 1, 0, &mut crossings);
+                border_entrances(nav_grid, *origin, 0, 1, &mut crossings);
+                crossings
+            })
+            .collect();
+
+        let mut entrances: Vec<u32> = crossings.iter().flat_map(|(inside, outside)| [*inside, *outside]).collect();
+        for (index, edges) in nav_grid.edges.iter_all() {
+            entrances.push(*index);
+            entrances.extend(edges.iter().filter(|edge| edge.instance_plane.is_none()).map(|edge| edge.destination.index()));
+        }
+        entrances.extend(nav_grid.teleports.iter().map(|teleport| teleport.destination.index()));
+        entrances.sort_unstable();
+        entrances.dedup();
+        let node_of = |index: u32| entrances.binary_search(&index).ok().map(|node| node as u32);
+        let mut chunk_entrances: HashMap<(u16, u16, u8), Vec<u32>> = HashMap::new();
+        for (node, index) in entrances.iter().enumerate() {
+            chunk_entrances.entry(chunk(*index)).or_default().push(node as u32);
+        }
+
+        let mut arcs: Vec<Vec<Arc>> = entrances.par_iter()
+            .map(|index| {
+                let reached = local_search(nav_grid, *index, false);
+                let mut arcs: Vec<Arc> = chunk_entrances[&chunk(*index)].iter()
+                    .filter(|node| entrances[**node as usize] != *index)
+                    .filter_map(|node| reached.get(&entrances[*node as usize]).map(|(cost, _)| Arc { node: *node, cost: *cost, edge: NONE }))
+                    .collect();
+                if nav_grid.vertices.get(*index as usize).map_or(false, |vertex| vertex.has_extra_edges()) {
+                    for (position, edge) in nav_grid.edges.get_vec(index).into_iter().flatten().enumerate() {
+                        if let (None, Some(node)) = (&edge.instance_plane, node_of(edge.destination.index())) {
+                            arcs.push(Arc { node, cost: edge.cost, edge: position as u32 });
+                        }
+                    }
+                }
+                arcs
+            })
+            .collect();
+        for (inside, outside) in crossings {
+            let (inside_node, outside_node) = (node_of(inside).unwrap(), node_of(outside).unwrap());
+            let (dx, dy) = if chunk(inside).0 == chunk(outside).0 { (0, 1) } else { (1, 0) };
+            let (forward, backward) = if dx == 1 { (FLAG_E, FLAG_W) } else { (FLAG_N, FLAG_S) };
+            if can_move(nav_grid, inside, forward, dx, dy) {
+                arcs[inside_node as usize].push(Arc { node: outside_node, cost: 1, edge: NONE });
+            }
+            if can_move(nav_grid, outside, backward, -dx, -dy) {
+                arcs[outside_node as usize].push(Arc { node: inside_node, cost: 1, edge: NONE });
+            }
+        }
+
+        let mut offsets = Vec::with_capacity(entrances.len() + 1);
+        let mut flattened = Vec::with_capacity(arcs.iter().map(Vec::len).sum());
+        for node_arcs in arcs {
+            offsets.push(flattened.len() as u32);
+            flattened.extend(node_arcs);
+        }
+        offsets.push(flattened.len() as u32);
+        RegionGraph { entrances, offsets, arcs: flattened }
+    }
+
+    pub fn entrance_count(&self) -> usize {
+        self.entrances.len()
+    }
+
+    pub fn arc_count(&self) -> usize {
+        self.arcs.len()
+    }
+}
+
+/// Where the search reached an entrance from
+#[derive(Clone, Copy)]
+enum Origin {
+    /// Walking within the chunk of the start
+    Start,
+    /// Teleport at this position of the available teleports
+    Teleport(usize),
+    /// Arc at this position, leaving the entrance
+    Arc(u32, u32),
+}
+
+/// Approximate path from start to end, searching the [RegionGraph] and refining the arcs taken into steps.
+/// Returns the number of entrances expanded and the memory used like the other searches.
+pub fn find_path(nav_grid: &NavGrid, graph: &RegionGraph, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    let (start_index, end_index) = (start.index(), end.index());
+    if start_index == end_index {
+        return (0, 0, Ok(Some(Path { steps: Vec::new(), cost: 0 })));
+    }
+    let from_start = local_search(nav_grid, start_index, false);
+    let to_end = local_search(nav_grid, end_index, true);
+    let mut best = (from_start.get(&end_index).map_or(u32::MAX, |(cost, _)| *cost), NONE);
+
+    let mut labels: HashMap<u32, (u32, Origin)> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    let push = |labels: &mut HashMap<u32, (u32, Origin)>, queue: &mut BinaryHeap<Reverse<(u32, u32)>>, node: u32, cost: u32, origin: Origin| {
+        if cost < labels.get(&node).map_or(u32::MAX, |(known, _)| *known) {
+            labels.insert(node, (cost, origin));
+            queue.push(Reverse((cost, node)));
+        }
+    };
+    for (index, (cost, _)) in &from_start {
+        if let Ok(node) = graph.entrances.binary_search(index) {
+            push(&mut labels, &mut queue, node as u32, *cost, Origin::Start);
+        }
+    }
+    // Teleports are only cast from the start, paths starting deep in the Wilderness walk out of it
+    let teleports: Vec<_> = available_teleports(nav_grid, game_state, options.ignore_requirements).filter(|_| can_teleport_from(start_index)).collect();
+    for (position, (index, teleport)) in teleports.iter().enumerate() {
+        if let Ok(node) = graph.entrances.binary_search(index) {
+            push(&mut labels, &mut queue, node as u32, teleport.cost, Origin::Teleport(position));
+        }
+    }
+
+    let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids()).ignore_requirements(options.ignore_requirements);
+    let budget = options.budget();
+    let mut count = 0;
+    let mem_usage = |labels: &HashMap<u32, (u32, Origin)>, queue: &BinaryHeap<Reverse<(u32, u32)>>| {
+        labels.capacity() * std::mem::size_of::<(u32, (u32, Origin))>() + queue.capacity() * std::mem::size_of::<Reverse<(u32, u32)>>()
+    };
+    while let Some(Reverse((cost, node))) = queue.pop() {
+        if cost >= best.0 {
+            break;
+        }
+        if cost > labels[&node].0 {
+            continue;
+        }
+        if let Err(aborted) = budget.check(count, cost, || mem_usage(&labels, &queue)) {
+            return (count, mem_usage(&labels, &queue), Err(aborted));
+        }
+        count += 1;
+        let index = graph.entrances[node as usize];
+        if let Some((remaining, _)) = to_end.get(&index) {
+            if cost + remaining < best.0 {
+                best = (cost + remaining, node);
+            }
+        }
+        for position in graph.offsets[node as usize]..graph.offsets[node as usize + 1] {
+            let arc = &graph.arcs[position as usize];
+            if arc.edge != NONE {
+                let edge = &nav_grid.edges.get_vec(&index).expect("Region graph matches the NavGrid")[arc.edge as usize];
+                if !requirements.edge_met(index, arc.edge as usize, &edge.requirements) {
+                    continue;
+                }
+            }
+            push(&mut labels, &mut queue, arc.node, cost + arc.cost, Origin::Arc(node, position));
+        }
+    }
+    let mem_usage = mem_usage(&labels, &queue);
+    if best.0 == u32::MAX {
+        return (count, mem_usage, Ok(None));
+    }
+    if best.1 == NONE {
+        return (count, mem_usage, Ok(Some(Path { steps: local_steps(&from_start, end_index, false), cost: best.0 })));
+    }
+
+    let mut taken = Vec::new();
+    let mut node = best.1;
+    let mut steps = loop {
+        match labels[&node].1 {
+            Origin::Start => break local_steps(&from_start, graph.entrances[node as usize], false),
+            Origin::Teleport(position) => {
+                let (index, teleport) = teleports[position];
+                let requirements = if options.annotate_requirements { teleport.requirements.clone() } else { Vec::new() };
+                break vec![Step { definition: teleport.definition.clone(), kind: StepKind::Teleport, from: *start, to: Coordinate::from_index(index), cost: teleport.cost, requirements, arrival: teleport.arrival }];
+            }
+            Origin::Arc(prev, position) => {
+                taken.push((prev, node, &graph.arcs[position as usize]));
+                node = prev;
+            }
+        }
+    };
+    for (from, to, arc) in taken.into_iter().rev() {
+        let (from, to) = (graph.entrances[from as usize], graph.entrances[to as usize]);
+        if arc.edge != NONE {
+            let edge = &nav_grid.edges.get_vec(&from).expect("Region graph matches the NavGrid")[arc.edge as usize];
+            let requirements = if options.annotate_requirements { edge.requirements.clone() } else { Vec::new() };
+            steps.push(Step { definition: edge.definition.clone(), kind: StepKind::Edge, from: Coordinate::from_index(from), to: edge.destination, cost: edge.cost, requirements, arrival: edge.arrival });
+        } else if chunk(from) == chunk(to) {
+            steps.extend(local_steps(&local_search(nav_grid, from, false), to, false));
+        } else {
+            steps.push(Step { definition: EdgeDefinition::Step { position: Coordinate::from_index(to) }, kind: StepKind::Walk, from: Coordinate::from_index(from), to: Coordinate::from_index(to), cost: 1, requirements: Vec::new(), arrival: None });
+        }
+    }
+    steps.extend(local_steps(&to_end, graph.entrances[best.1 as usize], true));
+    (count, mem_usage, Ok(Some(Path { steps, cost: best.0 })))
+}
@@ -1,15 +1,456 @@
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 
-use model::{Coordinate, Edge, NavGrid};
+use model::{Area, Coordinate, Edge, NavGrid, PoiKind, PointOfInterest};
 use model::constants::*;
-use model::definitions::{EdgeDefinition, GameState};
+use model::definitions::{EdgeDefinition, GameState, Regex, RequirementDefinition, RequirementEvaluator, UnknownPolicy};
 use model::util::RegionCache;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub mod ch;
+pub mod hpa;
+pub mod plan;
+
+/// Element of a generated path, carrying the tiles the player is on before and after taking it
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Step {
+    #[serde(flatten)]
+    pub definition: EdgeDefinition,
+    pub kind: StepKind,
+    pub from: Coordinate,
+    pub to: Coordinate,
+    pub cost: u32,
+    /// Requirements of the edge taken, only filled in if [SearchOptions::annotate_requirements] is set
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<RequirementDefinition>,
+    /// Area the player may end up anywhere in instead of `to`, see [Edge::arrival]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrival: Option<Area>,
+}
+
+/// How a step is taken, which determines how a client has to execute it
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum StepKind {
+    /// Walking to an adjacent tile
+    Walk,
+    /// Interacting with the world at `from`, like opening a door or climbing a ladder
+    Edge,
+    /// Teleporting to `to` from wherever the player is
+    Teleport,
+}
+
+impl Step {
+    pub fn is_walk(&self) -> bool {
+        self.kind == StepKind::Walk
+    }
+
+    fn direction(&self) -> (i32, i32) {
+        (self.to.x as i32 - self.from.x as i32, self.to.y as i32 - self.from.y as i32)
+    }
+}
+
+/// Merges consecutive walking steps in the same direction into a single step to the end of the straight line.
+/// Any other step ends the line and is kept as is.
+pub fn simplify(path: Vec<Step>) -> Vec<Step> {
+    let mut simplified: Vec<Step> = Vec::with_capacity(path.len());
+    let mut direction = None;
+    for step in path {
+        if let Some(last) = simplified.last_mut() {
+            if step.is_walk() && last.is_walk() && direction == Some(step.direction()) {
+                last.definition = step.definition;
+                last.to = step.to;
+                last.cost += step.cost;
+                continue;
+            }
+        }
+        direction = Some(step.direction());
+        simplified.push(step);
+    }
+    simplified
+}
+
+/// Merges consecutive walking steps into a single step to the farthest tile the player walks to directly,
+/// which is moving diagonally towards it and then straight, without being stopped by a wall.
+/// Collapses far more steps than [simplify], since the merged steps don't have to be in a line.
+/// Obstacles of [SearchOptions] aren't known to the NavGrid and might be walked through.
+pub fn compress_path(nav_grid: &NavGrid, path: &[Step]) -> Vec<Step> {
+    let mut compressed: Vec<Step> = Vec::with_capacity(path.len());
+    let mut i = 0;
+    while i < path.len() {
+        let mut step = path[i].clone();
+        i += 1;
+        if step.is_walk() {
+            while i < path.len() && path[i].is_walk() && walks_directly(nav_grid, &step.from, &path[i].to) {
+                step.definition = path[i].definition.clone();
+                step.to = path[i].to;
+                step.cost += path[i].cost;
+                i += 1;
+            }
+        }
+        compressed.push(step);
+    }
+    compressed
+}
+
+/// Whether walking from `from` towards `to`, diagonally while both axes differ and straight afterwards, reaches it
+fn walks_directly(nav_grid: &NavGrid, from: &Coordinate, to: &Coordinate) -> bool {
+    if from.plane != to.plane {
+        return false;
+    }
+    let mut index = from.index();
+    let end_index = to.index();
+    while index != end_index {
+        let dx = ((end_index % WIDTH) as i32 - (index % WIDTH) as i32).signum();
+        let dy = ((end_index / WIDTH) as i32 - (index / WIDTH) as i32).signum();
+        let flag = DIRECTIONS.iter().find(|(_, x, y)| *x == dx && *y == dy).map_or(0, |(flag, _, _)| *flag);
+        if !can_move(nav_grid, index, flag, dx, dy) {
+            return false;
+        }
+        index = (index as i32 + WIDTH as i32 * dy + dx) as u32;
+    }
+    true
+}
+
+/// Searches return the same path for the same NavGrid and request. Among paths of equal cost, walking on in the direction
+/// of the previous step is preferred over turning, so paths walk in straight lines where they can. Remaining ties go to
+/// the move found first, directions in the order of [DIRECTIONS], then edges in the order they are stored at their
+/// source and teleports in the order of [NavGrid::teleports].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Path {
+    pub steps: Vec<Step>,
+    /// Sum of the costs of all steps
+    pub cost: u32,
+}
+
+/// Path found by a search, along with statistics of the path and the search
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PathResult {
+    #[serde(flatten)]
+    pub path: Path,
+    /// Number of tiles covered by walking steps
+    pub tiles_walked: u32,
+    pub teleports_used: u32,
+    /// Vertices expanded by the search
+    pub visited: usize,
+    #[serde(serialize_with = "serialize_millis", rename = "duration_ms")]
+    #[cfg_attr(feature = "schema", schemars(with = "f64"))]
+    pub duration: Duration,
+}
+
+impl PathResult {
+    pub fn new(path: Path, visited: usize, duration: Duration) -> PathResult {
+        let tiles_walked = path.steps.iter().filter(|step| step.is_walk()).map(|step| chebyshev(&step.from, &step.to)).sum();
+        let teleports_used = path.steps.iter().filter(|step| step.kind == StepKind::Teleport).count() as u32;
+        PathResult { path, tiles_walked, teleports_used, visited, duration }
+    }
+}
+
+fn serialize_millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000f64)
+}
+
+/// Runs [astar_with_options], collecting statistics of the path and the search
+pub fn find_path(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> Result<Option<PathResult>, Aborted> {
+    let begin = Instant::now();
+    let (visited, _, path) = astar_with_options(nav_grid, start, end, game_state, options);
+    let duration = Instant::now() - begin;
+    Ok(path?.map(|path| PathResult::new(path, visited, duration)))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SearchOptions {
+    /// Reject paths that consume more items than present in the game state, e.g. paying a toll twice
+    #[serde(default)]
+    pub strict_consumables: bool,
+    /// Inflates the A* heuristic, trading optimality for speed. Weights below 1 are treated as 1.
+    #[serde(default = "default_heuristic_weight")]
+    pub heuristic_weight: f32,
+    /// Tiles treated as impassable for this search only, e.g. a closed gate or a spawned object
+    #[serde(default)]
+    pub blocked: Vec<Coordinate>,
+    /// Areas the path must not enter, e.g. the Wilderness
+    #[serde(default)]
+    pub avoid: Vec<Area>,
+    /// Edges and teleports that can't be taken for this search only, e.g. a door observed to be locked
+    #[serde(default)]
+    pub disabled: Vec<EdgeDefinition>,
+    /// Gives up after expanding this many vertices
+    #[serde(default)]
+    pub max_visited: Option<u32>,
+    /// Gives up once the search allocated more than this many bytes, checked every [MEMORY_CHECK_INTERVAL] expanded
+    /// vertices
+    #[serde(default)]
+    pub max_memory: Option<usize>,
+    /// Gives up after searching for this many milliseconds, checked every [CANCEL_CHECK_INTERVAL] expanded vertices
+    #[serde(default)]
+    pub max_millis: Option<u64>,
+    /// Gives up once all paths left to explore cost more than this
+    #[serde(default)]
+    pub max_cost: Option<u32>,
+    /// Include the requirements of the edges taken in the steps of the path
+    #[serde(default)]
+    pub annotate_requirements: bool,
+    /// Skip teleports of A* searches that don't get closer to the end than the start, if the end can be walked to.
+    /// Saves seeding the queue with hundreds of teleports, but misses teleports around obstacles the heuristic doesn't see.
+    #[serde(default = "default_prune_teleports")]
+    pub prune_teleports: bool,
+    #[serde(default)]
+    pub constraints: PathConstraints,
+    /// Take edges and teleports regardless of whether the game state meets their requirements
+    #[serde(default)]
+    pub ignore_requirements: bool,
+    /// Gives up once the flag is set, checked every [CANCEL_CHECK_INTERVAL] expanded vertices. Lets another thread abort
+    /// a search whose result is no longer needed, e.g. because the client disconnected.
+    #[serde(skip)]
+    pub cancelled: Option<Arc<AtomicBool>>,
+}
+
+/// Restrictions on the parts of the map a path may lead through, which are checked while expanding
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PathConstraints {
+    /// Never enter the Wilderness
+    #[serde(default)]
+    pub avoid_wilderness: bool,
+    /// Never enter the Wilderness deeper than this level
+    #[serde(default)]
+    pub max_wilderness_level: Option<u8>,
+}
+
+impl PathConstraints {
+    fn wilderness_cap(&self) -> Option<u8> {
+        if self.avoid_wilderness { Some(0) } else { self.max_wilderness_level }
+    }
+
+    /// Whether a path may lead through the coordinate
+    pub fn permits(&self, coordinate: &Coordinate) -> bool {
+        self.wilderness_cap().map_or(true, |cap| coordinate.wilderness_level() <= cap)
+    }
+}
+
+/// Expanded vertices between checks of [SearchOptions::max_memory], since measuring the memory of a search isn't free
+pub const MEMORY_CHECK_INTERVAL: usize = 4096;
+
+/// Expanded vertices between checks of [SearchOptions::cancelled] and [SearchOptions::max_millis]
+pub const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+/// Why a search gave up before finding the end or exhausting all vertices reachable from the start
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Aborted {
+    /// The search expanded [SearchOptions::max_visited] vertices, allocated more than [SearchOptions::max_memory]
+    /// bytes, ran for [SearchOptions::max_millis] or exceeded [SearchOptions::max_cost]
+    BudgetExceeded,
+    /// [SearchOptions::cancelled] was set
+    Cancelled,
+}
+
+impl SearchOptions {
+    fn obstacles(&self) -> Obstacles<'_> {
+        Obstacles {
+            tiles: self.blocked.iter().filter(|coordinate| coordinate.validate()).map(Coordinate::index).collect(),
+            areas: &self.avoid,
+            disabled: &self.disabled,
+            wilderness_cap: self.constraints.wilderness_cap(),
+        }
+    }
+
+    /// Budget of a search starting now
+    fn budget(&self) -> Budget<'_> {
+        Budget { options: self, deadline: self.max_millis.map(|max_millis| Instant::now() + Duration::from_millis(max_millis)) }
+    }
+}
+
+/// Limits of a single search, checked before expanding each vertex
+struct Budget<'a> {
+    options: &'a SearchOptions,
+    deadline: Option<Instant>,
+}
+
+impl Budget<'_> {
+    /// Whether the search has to give up before expanding its next vertex, which the cheapest path left to explore
+    /// reaches for `cost`
+    fn check(&self, count: usize, cost: u32, mem_usage: impl FnOnce() -> usize) -> Result<(), Aborted> {
+        let options = self.options;
+        let check_clock = count % CANCEL_CHECK_INTERVAL == 0;
+        if check_clock && options.cancelled.as_ref().map_or(false, |cancelled| cancelled.load(Ordering::Relaxed)) {
+            return Err(Aborted::Cancelled);
+        }
+        let exceeded = options.max_visited.map_or(false, |max_visited| count >= max_visited as usize) ||
+            options.max_cost.map_or(false, |max_cost| cost > max_cost) ||
+            options.max_memory.map_or(false, |max_memory| count % MEMORY_CHECK_INTERVAL == 0 && mem_usage() > max_memory) ||
+            (check_clock && self.deadline.map_or(false, |deadline| Instant::now() >= deadline));
+        if exceeded { Err(Aborted::BudgetExceeded) } else { Ok(()) }
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            strict_consumables: false,
+            heuristic_weight: default_heuristic_weight(),
+            blocked: Vec::new(),
+            avoid: Vec::new(),
+            disabled: Vec::new(),
+            max_visited: None,
+            max_memory: None,
+            max_millis: None,
+            max_cost: None,
+            annotate_requirements: false,
+            prune_teleports: default_prune_teleports(),
+            constraints: PathConstraints::default(),
+            ignore_requirements: false,
+            cancelled: None,
+        }
+    }
+}
+
+fn default_heuristic_weight() -> f32 {
+    1.0
+}
+
+fn default_prune_teleports() -> bool {
+    true
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+pub enum MovementMode {
+    Walk,
+    Run,
+}
+
+impl MovementMode {
+    pub fn tiles_per_tick(&self) -> u32 {
+        match self {
+            MovementMode::Walk => 1,
+            MovementMode::Run => 2,
+        }
+    }
+}
+
+/// Cost of the moves a search minimizes. Walking costs have to be at least 1 for the A* heuristic to be admissible.
+pub trait CostModel {
+    /// Cost of walking to an adjacent tile, for a path costing `cost` so far.
+    /// Must not decrease with `cost`, since arriving at a tile earlier could be worse otherwise.
+    fn walk_cost(&self, cost: u32) -> u32;
+
+    /// Cost of taking an edge or teleport
+    fn edge_cost(&self, edge: &Edge) -> u32;
+}
+
+/// Every tile costs 1, edges cost what the NavGrid says
+pub struct UniformCost;
+
+impl CostModel for UniformCost {
+    fn walk_cost(&self, _: u32) -> u32 {
+        1
+    }
+
+    fn edge_cost(&self, edge: &Edge) -> u32 {
+        edge.cost
+    }
+}
+
+/// Estimates the game ticks of a path, in half ticks since running covers 2 tiles per tick.
+/// The player runs until the run energy of the game state is drained, which depends on the carried weight,
+/// and walks afterwards. Edge costs are taken as ticks.
+pub struct TickCost {
+    run_tiles: u32,
+}
+
+impl TickCost {
+    pub fn new(game_state: &GameState) -> TickCost {
+        // Energy is 10000 units at 100%, each tick of running drains 67 units and up to 67 more for 64kg
+        let drain_per_tick = 67 + 67 * game_state.weight.clamp(0, 64) as u32 / 64;
+        let run_ticks = game_state.run_energy.min(100) as u32 * 100 / drain_per_tick;
+        TickCost { run_tiles: run_ticks * MovementMode::Run.tiles_per_tick() }
+    }
+}
+
+impl CostModel for TickCost {
+    fn walk_cost(&self, cost: u32) -> u32 {
+        // While running, every half tick so far was spent running a tile, apart from edges
+        if cost < self.run_tiles { 1 } else { 2 }
+    }
+
+    fn edge_cost(&self, edge: &Edge) -> u32 {
+        edge.cost.saturating_mul(2)
+    }
+}
+
+/// [DijkstraCacheState::edge] of vertices that were walked to
+const WALK: u32 = u32::MAX;
+/// Set in [DijkstraCacheState::edge] of vertices that were teleported to
+const TELEPORT: u32 = 1 << 31;
 
 #[derive(Clone, Copy)]
-struct DijkstraCacheState<'a> {
+struct DijkstraCacheState {
     cost: u32,
     prev: u32,
-    edge: Option<&'a Edge>,
+    /// Position of the edge taken in the edges of its source, or with [TELEPORT] set, position of the teleport in the
+    /// teleports of the NavGrid. [WALK] if the vertex was walked to.
+    edge: u32,
+}
+
+const UNVISITED: DijkstraCacheState = DijkstraCacheState { cost: u32::MAX, prev: u32::MAX, edge: WALK };
+
+impl DijkstraCacheState {
+    /// Whether walking between the vertex at `index`, reached with this state, and `adj_index` continues the step it
+    /// was reached with in a straight line. Holds in either direction, so backward searches can use it as well.
+    fn walks_straight(&self, index: u32, adj_index: u32) -> bool {
+        self.edge == WALK && self.prev != u32::MAX && index.wrapping_sub(self.prev) == adj_index.wrapping_sub(index)
+    }
+
+    fn is_teleport(&self) -> bool {
+        self.edge != WALK && self.edge & TELEPORT != 0
+    }
+
+    /// Edge or teleport the vertex was reached with, `source` is `prev` in forward searches and the vertex itself in
+    /// backward searches
+    fn taken_edge<'a>(&self, nav_grid: &'a NavGrid, source: u32) -> Option<&'a Edge> {
+        match self.edge {
+            WALK => None,
+            teleport if teleport & TELEPORT != 0 => Some(&nav_grid.teleports[(teleport & !TELEPORT) as usize]),
+            edge => Some(&nav_grid.edges.get_vec(&source).expect("Edges of the source are unchanged")[edge as usize]),
+        }
+    }
+}
+
+/// Memory of a search that can be reused by subsequent searches, on the same or another NavGrid.
+/// Allocating the cache dominates the duration of short searches, which a reused context avoids.
+pub struct SearchContext {
+    cache: RegionCache<DijkstraCacheState>,
+    /// Queue of A* searches
+    queue: BinaryHeap<Reverse<(u32, u32, u32)>>,
+    /// Queue of Dijkstra searches
+    buckets: BucketRingBuffer<(u32, u32)>,
+}
+
+impl Default for SearchContext {
+    fn default() -> SearchContext {
+        SearchContext { cache: RegionCache::new(UNVISITED), queue: BinaryHeap::new(), buckets: BucketRingBuffer::new(0) }
+    }
+}
+
+impl SearchContext {
+    /// Clears the state of the previous search, keeping the allocated memory
+    pub fn reset(&mut self) {
+        self.cache.reset();
+        self.queue.clear();
+        self.buckets.reset();
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        self.cache.mem_usage() + self.queue.capacity() * std::mem::size_of::<Reverse<(u32, u32, u32)>>() + self.buckets.mem_usage()
+    }
 }
 
 pub struct BucketRingBuffer<T> {
@@ -30,6 +471,10 @@ impl<T: Clone> BucketRingBuffer<T> {
         self.buckets.iter_mut().for_each(Vec::clear);
     }
 
+    pub fn mem_usage(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.capacity() * std::mem::size_of::<T>()).sum::<usize>() + self.buckets.capacity() * std::mem::size_of::<Vec<T>>()
+    }
+
     fn increment(&mut self) {
         self.cursor += 1;
         if self.cursor == self.buckets.len() {
@@ -37,6 +482,7 @@ impl<T: Clone> BucketRingBuffer<T> {
         }
     }
 
+    /// Moves the cursor to the next non-empty bin, which holds the states with the lowest cost
     fn next_bin(&mut self) -> Option<usize> {
         let len = self.buckets.len();
         for i in 0..len {
@@ -45,13 +491,25 @@ impl<T: Clone> BucketRingBuffer<T> {
                 index -= len;
             }
             if !self.buckets[index].is_empty() {
+                self.cursor = index;
                 return Some(index);
             }
         }
         None
     }
 
+    fn pop(&mut self) -> Option<T> {
+        self.buckets[self.cursor].pop()
+    }
+
+    /// Pushes a state with a cost relative to the current bin.
+    /// The ring grows if the cost exceeds its size, since wrapping around would pop the state too early.
     fn push(&mut self, cost: u32, state: T) {
+        if cost as usize >= self.buckets.len() {
+            self.buckets.rotate_left(self.cursor);
+            self.cursor = 0;
+            self.buckets.resize(cost as usize + 1, Vec::new());
+        }
         let len = self.buckets.len();
         let mut index = cost as usize + self.cursor;
         if index >= len {
@@ -61,111 +519,2302 @@ impl<T: Clone> BucketRingBuffer<T> {
     }
 }
 
-pub fn dijkstra(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState) -> (usize, usize, Option<Vec<EdgeDefinition>>) {
-    let start_index = start.index();
-    let end_index = end.index();
-    let target_group = nav_grid.vertices[end_index as usize].get_group();
-    let max_cost = nav_grid.iter_edges().map(|edge| edge.cost).max().unwrap();
-    let mut queue = BucketRingBuffer::new(max_cost); //TODO borrow from pool instead to prevent allocations?
-    let mut cache = RegionCache::new(DijkstraCacheState { cost: u32::MAX, prev: u32::MAX, edge: None });
+pub fn dijkstra(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState) -> (usize, usize, Option<Path>) {
+    let (count, mem_usage, result) = dijkstra_with_options(nav_grid, start, end, game_state, &SearchOptions::default());
+    (count, mem_usage, result.unwrap_or(None))
+}
+
+pub fn dijkstra_with_options(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    dijkstra_with_observer(nav_grid, start, end, game_state, options, |_| {})
+}
+
+/// Like [dijkstra_with_options], additionally calling the observer with the index of every expanded vertex in order
+pub fn dijkstra_with_observer<F>(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions, mut observer: F) -> (usize, usize, Result<Option<Path>, Aborted>) where F: FnMut(u32) {
+    let (count, mem_usage, results) = dijkstra_goals(&mut SearchContext::default(), nav_grid, start, &[end.index()], game_state, options, &UniformCost, false, &mut observer);
+    (count, mem_usage, results.map(|mut results| results.pop().flatten()))
+}
+
+/// Like [dijkstra_with_options], minimizing the costs of the cost model instead of the costs of the NavGrid
+pub fn dijkstra_with_cost(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions, cost_model: &dyn CostModel) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    let (count, mem_usage, results) = dijkstra_goals(&mut SearchContext::default(), nav_grid, start, &[end.index()], game_state, options, cost_model, false, &mut |_| {});
+    (count, mem_usage, results.map(|mut results| results.pop().flatten()))
+}
+
+/// Like [dijkstra_with_options], but reuses the memory of the context instead of allocating it for this search
+pub fn dijkstra_with_context(context: &mut SearchContext, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    let (count, mem_usage, results) = dijkstra_goals(context, nav_grid, start, &[end.index()], game_state, options, &UniformCost, false, &mut |_| {});
+    (count, mem_usage, results.map(|mut results| results.pop().flatten()))
+}
+
+/// Runs [dijkstra_with_options] for every pair of start and end in parallel, returning the results in the same order.
+/// Each rayon worker reuses its own [SearchContext] for the searches it runs.
+pub fn par_dijkstra_many(nav_grid: &NavGrid, queries: &[(Coordinate, Coordinate)], game_state: &GameState, options: &SearchOptions) -> Vec<(usize, usize, Result<Option<Path>, Aborted>)> {
+    queries.par_iter()
+        .map_init(SearchContext::default, |context, (start, end)| dijkstra_with_context(context, nav_grid, start, end, game_state, options))
+        .collect()
+}
+
+/// Costs of the cheapest paths by source and destination, `None` where the destination can't be reached
+pub type CostMatrix = Vec<Vec<Option<u32>>>;
+
+/// Finds the costs from every source to every destination with one search per source settling all destinations, instead
+/// of a search per pair. The searches run in parallel like [par_dijkstra_many]. Destinations outside of the map are
+/// unreachable. Returns the vertices visited by all searches and the memory used by the largest one.
+pub fn cost_matrix(nav_grid: &NavGrid, sources: &[Coordinate], destinations: &[Coordinate], game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<CostMatrix, Aborted>) {
+    let candidates: Vec<usize> = (0..destinations.len()).filter(|i| destinations[*i].validate()).collect();
+    if candidates.is_empty() {
+        return (0, 0, Ok(vec![vec![None; destinations.len()]; sources.len()]));
+    }
+    let goals: Vec<u32> = candidates.iter().map(|i| destinations[*i].index()).collect();
+    let rows: Vec<_> = sources.par_iter()
+        .map_init(SearchContext::default, |context, source| {
+            let (count, mem_usage, paths) = dijkstra_goals(context, nav_grid, source, &goals, game_state, options, &UniformCost, true, &mut |_| {});
+            let row = paths.map(|paths| {
+                let mut row = vec![None; destinations.len()];
+                candidates.iter().zip(paths).for_each(|(i, path)| row[*i] = path.map(|path| path.cost));
+                row
+            });
+            (count, mem_usage, row)
+        })
+        .collect();
+    let count = rows.iter().map(|(count, _, _)| count).sum();
+    let mem_usage = rows.iter().map(|(_, mem_usage, _)| *mem_usage).max().unwrap_or(0);
+    (count, mem_usage, rows.into_iter().map(|(_, _, row)| row).collect())
+}
+
+/// Finds the cheapest path to a walkable tile adjacent to an object, since the object's tile itself can't be stood on.
+/// The tile the path ends on is the `to` of the last step, or `start` if it is already adjacent.
+pub fn dijkstra_adjacent(nav_grid: &NavGrid, start: &Coordinate, object: &Coordinate, game_state: &GameState) -> (usize, usize, Option<Path>) {
+    let object_index = object.index();
+    let goals: Vec<u32> = DIRECTIONS.iter()
+        .map(|(_, dx, dy)| (object_index as i32 + (WIDTH as i32 * *dy) + *dx) as u32)
+        .filter(|index| nav_grid.vertices.get(*index as usize).map_or(false, |v| v.flags != 0))
+        .collect();
+    let (count, mem_usage, result) = dijkstra_multi_target(nav_grid, start, &goals, game_state, &SearchOptions::default());
+    (count, mem_usage, result.unwrap_or(None).map(|(_, path)| path))
+}
+
+/// Whether the end can be reached from the start under the game state.
+///
+/// Walking never leaves a group, so the end is only reachable if the start or the destination of an available teleport
+/// shares its group. That check is cheap, but necessary and not sufficient: edges within a group can be gated by
+/// requirements, so a group may be split into parts that aren't connected for this game state. Unless the groups rule
+/// the end out, this still searches until the end is reached or all options are exhausted.
+pub fn is_reachable(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState) -> bool {
+    groups_linked(nav_grid, start, end, game_state) && astar(nav_grid, start, end, game_state).2.is_some()
+}
+
+/// Like [is_reachable], but searches with the options, giving up once their budget is exceeded
+pub fn is_reachable_with_options(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> Result<bool, Aborted> {
+    if !groups_linked(nav_grid, start, end, game_state) {
+        return Ok(false);
+    }
+    let (_, _, path) = astar_with_options(nav_grid, start, end, game_state, options);
+    path.map(|path| path.is_some())
+}
+
+/// The group check of [is_reachable] on its own: whether the start or the destination of a teleport available under
+/// the game state shares the group of the end. If not, no path exists. If so, a path may still be ruled out by
+/// requirements of edges within the group, which only a search finds out.
+pub fn groups_linked(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState) -> bool {
+    let target_group = nav_grid.vertices[end.index() as usize].get_group();
+    nav_grid.vertices[start.index() as usize].get_group() == target_group
+        || available_teleports(nav_grid, game_state, false).any(|(index, _)| nav_grid.vertices[index as usize].get_group() == target_group)
+}
+
+/// Finds a path visiting all points in order, with one search per leg. The game state may change between legs,
+/// so leg `i` from `points[i]` to `points[i + 1]` is searched with `game_states[i]`, or the last game state if there
+/// are fewer game states than legs. Fails with the index of the first leg that has no path.
+pub fn dijkstra_waypoints(nav_grid: &NavGrid, points: &[Coordinate], game_states: &[GameState]) -> (usize, usize, Result<Path, usize>) {
     let mut count = 0;
-    if nav_grid.vertices[start_index as usize].get_group() == target_group {
-        cache.get_mut(start_index).cost = 0;
-        queue.push(0, (0, start_index));
+    let mut mem_usage = 0;
+    let mut path = Path { steps: Vec::new(), cost: 0 };
+    for (leg, pair) in points.windows(2).enumerate() {
+        let game_state = match game_states.get(leg).or_else(|| game_states.last()) {
+            Some(game_state) => game_state,
+            None => return (count, mem_usage, Err(leg)),
+        };
+        let (leg_count, leg_mem_usage, leg_path) = dijkstra(nav_grid, &pair[0], &pair[1], game_state);
+        count += leg_count;
+        mem_usage = mem_usage.max(leg_mem_usage);
+        match leg_path {
+            Some(leg_path) => {
+                // Legs share the waypoint as `to` and `from` of adjoining steps, so no step is repeated
+                path.steps.extend(leg_path.steps);
+                path.cost += leg_path.cost;
+            }
+            None => return (count, mem_usage, Err(leg)),
+        }
     }
-    for teleport in &nav_grid.teleports {
-        if teleport.requirements.iter().all(|req| req.is_met(game_state)) {
-            let index = teleport.destination.index();
-            if nav_grid.vertices[index as usize].get_group() == target_group {
-                let dest = cache.get_mut(index);
-                if teleport.cost < dest.cost {
-                    dest.cost = teleport.cost;
-                    dest.prev = start_index;
-                    dest.edge = Some(teleport);
-                    queue.push(teleport.cost, (dest.cost, index));
-                }
+    (count, mem_usage, Ok(path))
+}
+
+/// Position of the reached goal among all goals and the path to it
+pub type ReachedGoal = (usize, Path);
+
+/// Finds the cheapest path to whichever of the ends is reached first in a single search, returning its position in `ends`.
+/// Ends outside of the map are skipped.
+pub fn dijkstra_multi(nav_grid: &NavGrid, start: &Coordinate, ends: &[Coordinate], game_state: &GameState) -> (usize, usize, Option<ReachedGoal>) {
+    let (count, mem_usage, result) = dijkstra_multi_with_options(nav_grid, start, ends, game_state, &SearchOptions::default());
+    (count, mem_usage, result.unwrap_or(None))
+}
+
+pub fn dijkstra_multi_with_options(nav_grid: &NavGrid, start: &Coordinate, ends: &[Coordinate], game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<ReachedGoal>, Aborted>) {
+    let candidates: Vec<usize> = (0..ends.len()).filter(|i| ends[*i].validate()).collect();
+    let goals: Vec<u32> = candidates.iter().map(|i| ends[*i].index()).collect();
+    let (count, mem_usage, result) = dijkstra_multi_target(nav_grid, start, &goals, game_state, options);
+    (count, mem_usage, result.map(|reached| reached.map(|(goal, path)| (candidates[goal], path))))
+}
+
+/// Finds the cheapest path to each of the goals in a single search, which is much cheaper than searching for each goal
+/// separately. Unreachable goals and goals outside of the map are `None`.
+pub fn dijkstra_to_all(nav_grid: &NavGrid, start: &Coordinate, goals: &[Coordinate], game_state: &GameState) -> (usize, usize, Vec<Option<Path>>) {
+    let candidates: Vec<usize> = (0..goals.len()).filter(|i| goals[*i].validate()).collect();
+    let indices: Vec<u32> = candidates.iter().map(|i| goals[*i].index()).collect();
+    let (count, mem_usage, results) = dijkstra_goals(&mut SearchContext::default(), nav_grid, start, &indices, game_state, &SearchOptions::default(), &UniformCost, true, &mut |_| {});
+    let mut paths = vec![None; goals.len()];
+    for (goal, path) in candidates.into_iter().zip(results.unwrap_or_default()) {
+        paths[goal] = path;
+    }
+    (count, mem_usage, paths)
+}
+
+/// Bank reached and the path to it
+pub type ReachedBank<'a> = (&'a PointOfInterest, Path);
+
+/// Finds the cheapest path to whichever bank of the NavGrid is reached first
+pub fn nearest_bank<'a>(nav_grid: &'a NavGrid, start: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<ReachedBank<'a>>, Aborted>) {
+    let banks: Vec<&PointOfInterest> = nav_grid.points_of_interest(PoiKind::Bank).collect();
+    let goals: Vec<u32> = banks.iter().map(|bank| bank.position.index()).collect();
+    let (count, mem_usage, result) = dijkstra_multi_target(nav_grid, start, &goals, game_state, options);
+    (count, mem_usage, result.map(|reached| reached.map(|(goal, path)| (banks[goal], path))))
+}
+
+/// Point of interest and the cost of the cheapest path to it
+pub type PoiCost<'a> = (&'a PointOfInterest, u32);
+
+/// Finds the cost of reaching each point of interest of the kind in a single search, sorted by that cost rather than by
+/// distance, since a teleport or shortcut can make a far away point cheaper to reach. Unreachable points are left out.
+pub fn points_of_interest_by_cost<'a>(nav_grid: &'a NavGrid, start: &Coordinate, kind: PoiKind, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Vec<PoiCost<'a>>, Aborted>) {
+    let candidates: Vec<&PointOfInterest> = nav_grid.points_of_interest(kind).filter(|poi| poi.position.validate()).collect();
+    if candidates.is_empty() {
+        return (0, 0, Ok(Vec::new()));
+    }
+    let goals: Vec<u32> = candidates.iter().map(|poi| poi.position.index()).collect();
+    let (count, mem_usage, paths) = dijkstra_goals(&mut SearchContext::default(), nav_grid, start, &goals, game_state, options, &UniformCost, true, &mut |_| {});
+    let result = paths.map(|paths| {
+        let mut costs: Vec<PoiCost> = candidates.into_iter().zip(paths).filter_map(|(poi, path)| Some((poi, path?.cost))).collect();
+        costs.sort_by_key(|(_, cost)| *cost);
+        costs
+    });
+    (count, mem_usage, result)
+}
+
+/// Loop from the start to a bank and on to the target, e.g. from a resource spot to the bank and back
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RoundTrip {
+    pub bank: PointOfInterest,
+    pub to_bank: Path,
+    pub from_bank: Path,
+    /// Cost of both paths
+    pub cost: u32,
+}
+
+/// Finds the cheapest loop from the start via a bank to the target, which is usually the start again. If `via_bank` is
+/// given, the loop passes that bank, otherwise the bank minimizing the cost of the whole loop, which isn't necessarily the
+/// nearest bank to the start.
+///
+/// A single search finds the paths to all banks. Banks are then tried in order of the cost of reaching them, searching
+/// their paths to the target until reaching the next bank costs more than the cheapest loop found so far.
+pub fn round_trip(nav_grid: &NavGrid, start: &Coordinate, target: &Coordinate, via_bank: Option<&Coordinate>, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<RoundTrip>, Aborted>) {
+    let banks: Vec<PointOfInterest> = match via_bank {
+        Some(position) => vec![nav_grid.points_of_interest(PoiKind::Bank).find(|bank| bank.position == *position).cloned()
+            .unwrap_or(PointOfInterest { kind: PoiKind::Bank, position: *position, name: None })],
+        None => nav_grid.points_of_interest(PoiKind::Bank).cloned().collect(),
+    };
+    let goals: Vec<u32> = banks.iter().map(|bank| bank.position.index()).collect();
+    let (mut count, mut mem_usage, to_banks) = dijkstra_goals(&mut SearchContext::default(), nav_grid, start, &goals, game_state, options, &UniformCost, true, &mut |_| {});
+    let mut to_banks: Vec<(PointOfInterest, Path)> = match to_banks {
+        Ok(to_banks) => banks.into_iter().zip(to_banks).filter_map(|(bank, path)| Some((bank, path?))).collect(),
+        Err(aborted) => return (count, mem_usage, Err(aborted)),
+    };
+    to_banks.sort_by_key(|(_, path)| path.cost);
+
+    let mut best: Option<RoundTrip> = None;
+    for (bank, to_bank) in to_banks {
+        if best.as_ref().map_or(false, |best| to_bank.cost >= best.cost) {
+            break;
+        }
+        let (bank_count, bank_mem_usage, from_bank) = dijkstra_with_options(nav_grid, &bank.position, target, game_state, options);
+        count += bank_count;
+        mem_usage = mem_usage.max(bank_mem_usage);
+        match from_bank {
+            Ok(Some(from_bank)) if best.as_ref().map_or(true, |best| to_bank.cost + from_bank.cost < best.cost) => {
+                best = Some(RoundTrip { cost: to_bank.cost + from_bank.cost, bank, to_bank, from_bank });
             }
+            Ok(_) => {}
+            Err(aborted) => return (count, mem_usage, Err(aborted)),
         }
     }
+    (count, mem_usage, Ok(best))
+}
+
+/// Like [astar_with_options], but if the end can't be reached, finds the path to the reachable tile closest to it instead.
+/// Closeness is the Chebyshev distance on the plane of the end, ties are broken by the cost of the path.
+/// Returns whether the end was reached along with the path.
+pub fn closest_reachable(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<(Path, bool), Aborted>) {
+    let (mut count, astar_mem_usage, result) = astar_with_options(nav_grid, start, end, game_state, options);
+    match result {
+        Ok(Some(path)) => return (count, astar_mem_usage, Ok((path, true))),
+        Err(aborted) => return (count, astar_mem_usage, Err(aborted)),
+        Ok(None) => {}
+    }
+
+    let start_index = start.index();
+    let max_cost = nav_grid.iter_edges().map(|edge| edge.cost).max().unwrap_or(1);
+    let mut queue = BucketRingBuffer::new(max_cost);
+    let mut cache = RegionCache::new(UNVISITED);
+    let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids()).ignore_requirements(options.ignore_requirements);
+    let budget = options.budget();
+    let obstacles = options.obstacles();
+    let mut teleports = Teleports::new(nav_grid, game_state, options.ignore_requirements);
+    let mut closest = (chebyshev(start, end), 0, start_index);
+    cache.get_mut(start_index).cost = 0;
+    queue.push(0, (0, start_index));
 
-    while let Some(current) = queue.next_bin() {
-        while let Some((cost, mut index)) = queue.buckets[current].pop() {
+    while queue.next_bin().is_some() {
+        while let Some((cost, index)) = queue.pop() {
+            if cost > cache.get_mut(index).cost {
+                continue;
+            }
+            if let Err(aborted) = budget.check(count, cost, || astar_mem_usage.max(cache.mem_usage())) {
+                return (count, astar_mem_usage.max(cache.mem_usage()), Err(aborted));
+            }
             count += 1;
-            if index == end_index {
-                let mut path = vec![];
-                while index != start_index {
-                    let state = cache.get_mut(index);
-                    if let Some(edge) = state.edge {
-                        path.push(edge.definition.clone());
-                    } else {
-                        path.push(EdgeDefinition::Step { position: Coordinate::from_index(index) });
+            closest = closest.min((chebyshev(&Coordinate::from_index(index), end), cost, index));
+            teleports.cast_from(index, cost, |dest_index, teleport, taken| {
+                if !obstacles.blocks(index, dest_index, Some(teleport)) {
+                    let dest = cache.get_mut(dest_index);
+                    if cost + teleport.cost < dest.cost {
+                        dest.cost = cost + teleport.cost;
+                        dest.prev = index;
+                        dest.edge = taken;
+                        queue.push(teleport.cost, (dest.cost, dest_index));
                     }
-                    index = state.prev;
-                }
-                path.reverse();
-                return (count, cache.mem_usage(), Some(path));
-            }
-            let v = &nav_grid.vertices[index as usize];
-            for (flag, dx, dy) in &DIRECTIONS {
-                if (v.flags & flag) != 0 {
-                    let adj_index = (index as i32 + (WIDTH as i32 * *dy) + *dx) as u32;
-                    let adj = cache.get_mut(adj_index);
-                    if cost + 1 < adj.cost {
-                        adj.cost = cost + 1;
-                        adj.prev = index;
-                        adj.edge = None;
-                        queue.push(1, (adj.cost, adj_index));
+                }
+            });
+            expand(nav_grid, index, &mut requirements, |adj_index, step_cost, edge, taken| {
+                if obstacles.blocks(index, adj_index, edge) {
+                    return;
+                }
+                let adj = cache.get_mut(adj_index);
+                if cost + step_cost < adj.cost {
+                    adj.cost = cost + step_cost;
+                    adj.prev = index;
+                    adj.edge = taken;
+                    queue.push(step_cost, (adj.cost, adj_index));
+                }
+            });
+        }
+        queue.increment();
+    }
+
+    let (_, cost, index) = closest;
+    let path = Path { steps: backtrack(nav_grid, &mut cache, start_index, index, options.annotate_requirements), cost };
+    (count, astar_mem_usage.max(cache.mem_usage()), Ok((path, false)))
+}
+
+/// Cost of the cheapest path from the start to every vertex, found by searching until all reachable vertices are
+/// expanded. Teleports are taken into account, vertices that can't be reached keep a cost of `u32::MAX`.
+pub fn distance_field(nav_grid: &NavGrid, start: &Coordinate, game_state: &GameState) -> RegionCache<u32> {
+    distance_map(nav_grid, start, game_state, u32::MAX)
+}
+
+/// Like [distance_field], but stops searching at `max_cost`. Vertices costing more to reach keep a cost of `u32::MAX`,
+/// which is much cheaper for travel time maps around the start.
+pub fn distance_map(nav_grid: &NavGrid, start: &Coordinate, game_state: &GameState, max_cost: u32) -> RegionCache<u32> {
+    let max_edge_cost = nav_grid.iter_edges().map(|edge| edge.cost).max().unwrap_or(1);
+    let mut queue = BucketRingBuffer::new(max_edge_cost);
+    let mut cache = RegionCache::new(u32::MAX);
+    let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids());
+    let mut teleports = Teleports::new(nav_grid, game_state, false);
+    *cache.get_mut(start.index()) = 0;
+    queue.push(0, (0, start.index()));
+
+    while queue.next_bin().is_some() {
+        while let Some((cost, index)) = queue.pop() {
+            if cost > *cache.get_mut(index) {
+                continue;
+            }
+            teleports.cast_from(index, cost, |dest_index, teleport, _| {
+                let dest = cache.get_mut(dest_index);
+                if cost + teleport.cost <= max_cost && cost + teleport.cost < *dest {
+                    *dest = cost + teleport.cost;
+                    queue.push(teleport.cost, (*dest, dest_index));
+                }
+            });
+            expand(nav_grid, index, &mut requirements, |adj_index, step_cost, _, _| {
+                let adj = cache.get_mut(adj_index);
+                if cost + step_cost <= max_cost && cost + step_cost < *adj {
+                    *adj = cost + step_cost;
+                    queue.push(step_cost, (*adj, adj_index));
+                }
+            });
+        }
+        queue.increment();
+    }
+
+    cache
+}
+
+/// Searches until any of the goal indices is reached, returning the position of the reached goal in `goals`
+fn dijkstra_multi_target(nav_grid: &NavGrid, start: &Coordinate, goals: &[u32], game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<ReachedGoal>, Aborted>) {
+    let (count, mem_usage, results) = dijkstra_goals(&mut SearchContext::default(), nav_grid, start, goals, game_state, options, &UniformCost, false, &mut |_| {});
+    let reached = results.map(|results| results.into_iter().enumerate().find_map(|(goal, result)| result.map(|path| (goal, path))));
+    (count, mem_usage, reached)
+}
+
+/// Searches until the first or, if `settle_all` is set, every goal index is reached.
+/// Goals are settled when popped from the queue, at which point their cost is final.
+#[allow(clippy::too_many_arguments)]
+fn dijkstra_goals(context: &mut SearchContext, nav_grid: &NavGrid, start: &Coordinate, goals: &[u32], game_state: &GameState, options: &SearchOptions, cost_model: &dyn CostModel, settle_all: bool, observer: &mut dyn FnMut(u32)) -> (usize, usize, Result<Vec<Option<Path>>, Aborted>) {
+    let mut results = vec![None; goals.len()];
+    let mut settled = vec![false; goals.len()];
+    let mut unsettled = goals.len();
+    let start_index = start.index();
+    let target_groups: Vec<u8> = goals.iter().map(|index| nav_grid.vertices[*index as usize].get_group()).collect();
+    context.reset();
+    let SearchContext { cache, buckets: queue, .. } = context;
+    let budget = options.budget();
+    let mut count = 0;
+    let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids()).ignore_requirements(options.ignore_requirements);
+    let obstacles = options.obstacles();
+    let mut teleports = Teleports::new(nav_grid, game_state, options.ignore_requirements);
+    let walkable = expand_start(nav_grid, start_index, &target_groups);
+    cache.get_mut(start_index).cost = 0;
+    queue.push(0, (0, start_index));
+
+    while queue.next_bin().is_some() {
+        while let Some((cost, index)) = queue.pop() {
+            // Vertices are pushed again whenever a cheaper path is found, only the cheapest entry is expanded
+            if cost > cache.get_mut(index).cost {
+                continue;
+            }
+            if let Err(aborted) = budget.check(count, cost, || cache.mem_usage()) {
+                return (count, cache.mem_usage(), Err(aborted));
+            }
+            count += 1;
+            observer(index);
+            let mut reached = false;
+            for goal in 0..goals.len() {
+                if goals[goal] == index && !settled[goal] {
+                    settled[goal] = true;
+                    unsettled -= 1;
+                    if !options.strict_consumables || consumables_in_stock(nav_grid, cache, start_index, index, game_state) {
+                        results[goal] = Some(Path { steps: backtrack(nav_grid, cache, start_index, index, options.annotate_requirements), cost });
+                        reached = true;
                     }
                 }
             }
-            if v.has_extra_edges() {
-                for edge in nav_grid.edges.get_vec(&index).unwrap() {
-                    if edge.requirements.iter().all(|req| req.is_met(game_state)) {
-                        let adj = cache.get_mut(edge.destination.index());
-                        if cost + edge.cost < adj.cost {
-                            adj.cost = cost + edge.cost;
-                            adj.prev = index;
-                            adj.edge = Some(edge);
-                            queue.push(edge.cost, (adj.cost, edge.destination.index()));
-                        }
+            if unsettled == 0 || reached && !settle_all {
+                return (count, cache.mem_usage(), Ok(results));
+            }
+            teleports.cast_from(index, cost, |dest_index, teleport, taken| {
+                if target_groups.contains(&nav_grid.vertices[dest_index as usize].get_group()) && !obstacles.blocks(index, dest_index, Some(teleport)) {
+                    let dest = cache.get_mut(dest_index);
+                    let teleport_cost = cost_model.edge_cost(teleport);
+                    if cost + teleport_cost < dest.cost {
+                        dest.cost = cost + teleport_cost;
+                        dest.prev = index;
+                        dest.edge = taken;
+                        queue.push(teleport_cost, (dest.cost, dest_index));
                     }
                 }
+            });
+            if index == start_index && !walkable {
+                continue;
             }
+            let arrival = *cache.get_mut(index);
+            expand(nav_grid, index, &mut requirements, |adj_index, _, edge, taken| {
+                if obstacles.blocks(index, adj_index, edge) {
+                    return;
+                }
+                let step_cost = edge.map_or_else(|| cost_model.walk_cost(cost), |edge| cost_model.edge_cost(edge));
+                let adj = cache.get_mut(adj_index);
+                if cost + step_cost < adj.cost {
+                    adj.cost = cost + step_cost;
+                    adj.prev = index;
+                    adj.edge = taken;
+                    queue.push(step_cost, (adj.cost, adj_index));
+                } else if cost + step_cost == adj.cost && edge.is_none() && arrival.walks_straight(index, adj_index) {
+                    adj.prev = index;
+                    adj.edge = WALK;
+                }
+            });
         }
         queue.increment();
     }
 
-    (count, cache.mem_usage(), None)
+    (count, cache.mem_usage(), Ok(results))
 }
 
-pub fn flood<F>(nav_grid: &NavGrid, start: &Coordinate, mut visit_vertex: F) where F: FnMut(u32) -> bool {
-    let mut queue = VecDeque::new();
-    let mut cache = RegionCache::new(false);
-    queue.push_back(start.index());
-    *cache.get_mut(start.index()) = true;
-    while let Some(index) = queue.pop_front() {
-        let v = &nav_grid.vertices[index as usize];
-        if !visit_vertex(index) {
-            continue;
-        }
-        for (flag, dx, dy) in &DIRECTIONS {
-            if (v.flags & flag) != 0 {
-                let adj_index = (index as i32 + (WIDTH as i32 * *dy) + *dx) as u32;
-                let visited = cache.get_mut(adj_index);
-                if !*visited {
-                    queue.push_back(adj_index);
-                    *visited = true;
-                }
+/// A* search, expanding vertices closer to the end first. Returns the same optimal cost as [dijkstra].
+pub fn astar(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState) -> (usize, usize, Option<Path>) {
+    let (count, mem_usage, result) = astar_with_options(nav_grid, start, end, game_state, &SearchOptions { prune_teleports: false, ..SearchOptions::default() });
+    (count, mem_usage, result.unwrap_or(None))
+}
+
+/// A* search with the heuristic inflated by `heuristic_weight`. Weights above 1 expand far fewer vertices,
+/// but the cost of the returned path is only bounded to `heuristic_weight` times the optimum.
+pub fn weighted_astar(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, heuristic_weight: f32) -> (usize, usize, Option<Path>) {
+    let (count, mem_usage, result) = astar_with_options(nav_grid, start, end, game_state, &SearchOptions { heuristic_weight, ..SearchOptions::default() });
+    (count, mem_usage, result.unwrap_or(None))
+}
+
+pub fn astar_with_options(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    astar_with_context(&mut SearchContext::default(), nav_grid, start, end, game_state, options)
+}
+
+/// Paths for a game state lacking some varps or varbits, assuming requirements on them are all met or all not met
+#[derive(Debug, Serialize)]
+pub struct PathBounds {
+    /// Path if all unknown requirements are met, which is never more expensive than the pessimistic path
+    pub optimistic: Option<Path>,
+    /// Path if no unknown requirement is met, it only depends on values known to the game state
+    pub pessimistic: Option<Path>,
+}
+
+/// Searches the optimistic and pessimistic paths, ignoring the [UnknownPolicy] of the game state.
+/// If both cost the same, the unknown values don't matter for the path.
+pub fn path_bounds(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<PathBounds, Aborted>) {
+    let mut context = SearchContext::default();
+    let mut game_state = game_state.clone();
+    game_state.unknown_defaults_to = UnknownPolicy::Met;
+    let (optimistic_count, _, optimistic) = astar_with_context(&mut context, nav_grid, start, end, &game_state, options);
+    game_state.unknown_defaults_to = UnknownPolicy::NotMet;
+    let (pessimistic_count, mem_usage, pessimistic) = astar_with_context(&mut context, nav_grid, start, end, &game_state, options);
+    let bounds = optimistic.and_then(|optimistic| pessimistic.map(|pessimistic| PathBounds { optimistic, pessimistic }));
+    (optimistic_count + pessimistic_count, mem_usage, bounds)
+}
+
+/// Path that the game state would allow if it met more requirements, e.g. a higher Agility level or a finished quest
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Alternative {
+    #[serde(flatten)]
+    pub path: Path,
+    /// Cost saved over the path the game state allows, absent if it allows none
+    pub savings: Option<u32>,
+    /// Requirements of the steps that the game state doesn't meet
+    pub unmet: Vec<RequirementDefinition>,
+}
+
+/// Searches the path regardless of requirements, and compares it to the `cost` of the path the game state allows.
+/// There is no alternative if it isn't cheaper, or if it doesn't need any requirement the game state doesn't meet.
+pub fn unrestricted_alternative(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions, cost: Option<u32>) -> (usize, usize, Result<Option<Alternative>, Aborted>) {
+    let unrestricted = SearchOptions { ignore_requirements: true, annotate_requirements: true, strict_consumables: false, ..options.clone() };
+    let (count, mem_usage, result) = astar_with_options(nav_grid, start, end, game_state, &unrestricted);
+    let alternative = result.map(|path| path.filter(|path| cost.map_or(true, |cost| path.cost < cost)).and_then(|mut path| {
+        let mut unmet: Vec<RequirementDefinition> = Vec::new();
+        for requirement in path.steps.iter().flat_map(|step| &step.requirements) {
+            if !requirement.is_met(game_state) && !unmet.contains(requirement) {
+                unmet.push(requirement.clone());
             }
         }
-        if v.has_extra_edges() {
-            for edge in nav_grid.edges.get_vec(&index).unwrap() {
-                let visited = cache.get_mut(edge.destination.index());
-                if !*visited {
-                    queue.push_back(edge.destination.index());
-                    *visited = true;
-                }
-            }
+        if unmet.is_empty() {
+            return None;
+        }
+        if !options.annotate_requirements {
+            path.steps.iter_mut().for_each(|step| step.requirements.clear());
+        }
+        Some(Alternative { savings: cost.map(|cost| cost - path.cost), path, unmet })
+    }));
+    (count, mem_usage, alternative)
+}
+
+/// Paths of the same search under two game states, and the edges only one of them takes
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Comparison {
+    pub a: Option<Path>,
+    pub b: Option<Path>,
+    /// Cost saved by taking route b over route a
+    pub savings: Option<i64>,
+    /// Edges only used by route a
+    pub only_a: Vec<Step>,
+    /// Edges only used by route b
+    pub only_b: Vec<Step>,
+}
+
+/// Searches the path under both game states, e.g. before and after finishing a quest, and compares the routes.
+pub fn compare(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, state_a: &GameState, state_b: &GameState, options: &SearchOptions) -> (usize, usize, Result<Comparison, Aborted>) {
+    let (count_a, mem_usage_a, a) = astar_with_options(nav_grid, start, end, state_a, options);
+    let a = match a {
+        Ok(a) => a,
+        Err(aborted) => return (count_a, mem_usage_a, Err(aborted)),
+    };
+    let (count_b, mem_usage_b, b) = astar_with_options(nav_grid, start, end, state_b, options);
+    let (count, mem_usage) = (count_a + count_b, mem_usage_a.max(mem_usage_b));
+    let b = match b {
+        Ok(b) => b,
+        Err(aborted) => return (count, mem_usage, Err(aborted)),
+    };
+    let savings = a.as_ref().zip(b.as_ref()).map(|(a, b)| a.cost as i64 - b.cost as i64);
+    let exclusive_edges = |path: &Option<Path>, other: &Option<Path>| -> Vec<Step> {
+        let edges = path.iter().flat_map(|p| &p.steps).filter(|e| !e.is_walk());
+        edges.filter(|e| !other.iter().flat_map(|p| &p.steps).any(|o| o.definition == e.definition)).cloned().collect()
+    };
+    let only_a = exclusive_edges(&a, &b);
+    let only_b = exclusive_edges(&b, &a);
+    (count, mem_usage, Ok(Comparison { a, b, savings, only_a, only_b }))
+}
+
+/// Like [astar_with_options], but reuses the memory of the context instead of allocating it for this search
+pub fn astar_with_context(context: &mut SearchContext, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    astar_with_cost(context, nav_grid, start, end, game_state, options, &UniformCost)
+}
+
+/// Result of a search that degrades gracefully when running out of budget, see [astar_with_fallback]
+#[derive(Clone, Debug)]
+pub enum SearchOutcome {
+    Found(Path),
+    /// The search exhausted all vertices reachable from the start without reaching the end
+    Unreachable,
+    /// The search ran out of budget before reaching the end, see [Aborted::BudgetExceeded]. The partial path leads to
+    /// the expanded tile closest to the end, which is `reached`, or is empty if no tile on the plane of the end was
+    /// expanded.
+    Budget { best_partial_path: Path, reached: Coordinate },
+    /// The search was cancelled, see [SearchOptions::cancelled]
+    Cancelled,
+}
+
+/// Like [astar_with_context], but if the budget of the options runs out, returns the path to the expanded tile closest
+/// to the end instead of nothing. Closeness is the Chebyshev distance like in [closest_reachable]. A* expands towards
+/// the end, so the partial path usually heads the right way, although it may lead into a dead end.
+pub fn astar_with_fallback(context: &mut SearchContext, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, SearchOutcome) {
+    let heuristic = Heuristic::new(nav_grid, end, game_state, &UniformCost);
+    let start_index = start.index();
+    let mut closest = (chebyshev(start, end), start_index);
+    let (count, mem_usage, result) = astar_restricted(context, nav_grid, start, end, game_state, options, &Restrictions::default(), &|coordinate| heuristic.estimate(coordinate), &UniformCost, &mut |index| {
+        // The search starts over if it pruned teleports it turned out to need, which expands the start again
+        let distance = chebyshev(&Coordinate::from_index(index), end);
+        if index == start_index || distance < closest.0 {
+            closest = (distance, index);
+        }
+    });
+    let outcome = match result {
+        Ok(Some(path)) => SearchOutcome::Found(path),
+        Ok(None) => SearchOutcome::Unreachable,
+        Err(Aborted::Cancelled) => SearchOutcome::Cancelled,
+        Err(Aborted::BudgetExceeded) => {
+            let (_, index) = closest;
+            let cost = context.cache.get_mut(index).cost;
+            // The closest tile is unvisited if the search gave up after starting over, but before expanding the start
+            if index == start_index || cost == u32::MAX {
+                SearchOutcome::Budget { best_partial_path: Path { steps: Vec::new(), cost: 0 }, reached: *start }
+            } else {
+                let steps = backtrack(nav_grid, &mut context.cache, start_index, index, options.annotate_requirements);
+                SearchOutcome::Budget { best_partial_path: Path { steps, cost }, reached: Coordinate::from_index(index) }
+            }
+        }
+    };
+    (count, mem_usage, outcome)
+}
+
+/// Like [astar_with_context], minimizing the costs of the cost model instead of the costs of the NavGrid
+pub fn astar_with_cost(context: &mut SearchContext, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions, cost_model: &dyn CostModel) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    let heuristic = Heuristic::new(nav_grid, end, game_state, cost_model);
+    astar_restricted(context, nav_grid, start, end, game_state, options, &Restrictions::default(), &|coordinate| heuristic.estimate(coordinate), cost_model, &mut |_| {})
+}
+
+/// A* search guided by a custom estimate of the cost from a coordinate to the end, e.g. one using precomputed
+/// distances between landmarks. The path is only optimal if the estimate never exceeds the actual cost,
+/// which also has to hold for coordinates on other planes than the end.
+pub fn astar_with_heuristic<H>(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions, heuristic: H) -> (usize, usize, Result<Option<Path>, Aborted>) where H: Fn(&Coordinate) -> u32 {
+    astar_restricted(&mut SearchContext::default(), nav_grid, start, end, game_state, options, &Restrictions::default(), &heuristic, &UniformCost, &mut |_| {})
+}
+
+/// Finds up to `k` loopless paths from the start to the end in order of their cost using Yen's algorithm.
+/// Every path differs from all cheaper ones in at least one step, the first one is the path found by [astar].
+pub fn k_shortest(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, k: usize) -> (usize, usize, Vec<Path>) {
+    let options = SearchOptions { prune_teleports: false, ..SearchOptions::default() };
+    let heuristic = Heuristic::new(nav_grid, end, game_state, &UniformCost);
+    let mut context = SearchContext::default();
+    let (mut count, mut mem_usage, first) = astar(nav_grid, start, end, game_state);
+    let mut paths: Vec<Path> = first.into_iter().collect();
+    // Only the cheapest k - paths.len() candidates can still be chosen
+    let mut candidates: Vec<Path> = Vec::new();
+    while paths.len() < k {
+        let previous = &paths[paths.len() - 1];
+        for i in 0..previous.steps.len() {
+            let root = &previous.steps[..i];
+            let spur = previous.steps[i].from;
+            let mut restrictions = Restrictions::default();
+            restrictions.vertices.extend(root.iter().map(|step| step.from.index()));
+            for path in paths.iter().filter(|path| path.steps.len() > i && same_steps(&path.steps[..i], root)) {
+                let step = &path.steps[i];
+                restrictions.moves.push((step.from.index(), step.to.index(), &step.definition));
+            }
+            let (spur_count, spur_mem_usage, spur_path) = astar_restricted(&mut context, nav_grid, &spur, end, game_state, &options, &restrictions, &|coordinate| heuristic.estimate(coordinate), &UniformCost, &mut |_| {});
+            count += spur_count;
+            mem_usage = mem_usage.max(spur_mem_usage);
+            if let Ok(Some(spur_path)) = spur_path {
+                let mut steps = root.to_vec();
+                steps.extend(spur_path.steps);
+                let candidate = Path { cost: root.iter().map(|step| step.cost).sum::<u32>() + spur_path.cost, steps };
+                if !candidates.iter().chain(&paths).any(|path| same_steps(&path.steps, &candidate.steps)) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        candidates.sort_by_key(|path| path.cost);
+        candidates.truncate(k - paths.len());
+        if candidates.is_empty() {
+            break;
+        }
+        paths.push(candidates.remove(0));
+    }
+    (count, mem_usage, paths)
+}
+
+fn same_steps(a: &[Step], b: &[Step]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.from == b.from && a.to == b.to && a.definition == b.definition)
+}
+
+/// Vertices and moves excluded from a search, to find alternatives to previously found paths
+#[derive(Default)]
+struct Restrictions<'a> {
+    vertices: HashSet<u32>,
+    /// Moves from a vertex to another by taking the edge with the definition
+    moves: Vec<(u32, u32, &'a EdgeDefinition)>,
+}
+
+impl Restrictions<'_> {
+    fn forbids(&self, index: u32, adj_index: u32, edge: Option<&Edge>) -> bool {
+        self.vertices.contains(&adj_index) || self.moves.iter().any(|(from, to, definition)| {
+            *from == index && *to == adj_index && match edge {
+                Some(edge) => edge.definition == **definition,
+                None => matches!(definition, EdgeDefinition::Step { .. }),
+            }
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn astar_restricted(context: &mut SearchContext, nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions, restrictions: &Restrictions, heuristic: &dyn Fn(&Coordinate) -> u32, cost_model: &dyn CostModel, observer: &mut dyn FnMut(u32)) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    let start_index = start.index();
+    let end_index = end.index();
+    let target_group = nav_grid.vertices[end_index as usize].get_group();
+    let weight = options.heuristic_weight.max(1.0);
+    let mut teleports = Teleports::new(nav_grid, game_state, options.ignore_requirements);
+    // A path starting deep in the Wilderness may walk out of it and teleport next to the end, which the heuristic
+    // doesn't see from the start
+    let via_teleport = if can_teleport_from(start_index) {
+        u32::MAX
+    } else {
+        teleports.available.iter().map(|(index, _, teleport)| cost_model.edge_cost(teleport).saturating_add(heuristic(&Coordinate::from_index(*index)))).min().unwrap_or(u32::MAX)
+    };
+    let estimate = |index: u32| (heuristic(&Coordinate::from_index(index)).min(via_teleport) as f32 * weight) as u32;
+    context.reset();
+    let SearchContext { cache, queue, .. } = context;
+    let budget = options.budget();
+    let mut count = 0;
+    let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids()).ignore_requirements(options.ignore_requirements);
+    let obstacles = options.obstacles();
+    let walkable = nav_grid.vertices[start_index as usize].get_group() == target_group;
+    let walk_from_start = expand_start(nav_grid, start_index, &[target_group]);
+    cache.get_mut(start_index).cost = 0;
+    queue.push(Reverse((estimate(start_index), 0, start_index)));
+    let mut pruned = false;
+
+    while let Some(Reverse((_, cost, index))) = queue.pop() {
+        if cost > cache.get_mut(index).cost {
+            continue;
+        }
+        if let Err(aborted) = budget.check(count, cost, || cache.mem_usage()) {
+            return (count, cache.mem_usage(), Err(aborted));
+        }
+        count += 1;
+        observer(index);
+        if index == end_index {
+            if options.strict_consumables && !consumables_in_stock(nav_grid, cache, start_index, index, game_state) {
+                break;
+            }
+            let path = Path { steps: backtrack(nav_grid, cache, start_index, index, options.annotate_requirements), cost };
+            return (count, cache.mem_usage(), Ok(Some(path)));
+        }
+        teleports.cast_from(index, cost, |dest_index, teleport, taken| {
+            let teleport_cost = cost_model.edge_cost(teleport);
+            if options.prune_teleports && walkable && teleport_cost.saturating_add(heuristic(&Coordinate::from_index(dest_index))) >= heuristic(&Coordinate::from_index(index)) {
+                pruned = true;
+                return;
+            }
+            if nav_grid.vertices[dest_index as usize].get_group() == target_group && !obstacles.blocks(index, dest_index, Some(teleport)) && !restrictions.forbids(index, dest_index, Some(teleport)) {
+                let dest = cache.get_mut(dest_index);
+                if cost + teleport_cost < dest.cost {
+                    dest.cost = cost + teleport_cost;
+                    dest.prev = index;
+                    dest.edge = taken;
+                    queue.push(Reverse((dest.cost.saturating_add(estimate(dest_index)), dest.cost, dest_index)));
+                }
+            }
+        });
+        if index == start_index && !walk_from_start {
+            continue;
+        }
+        let arrival = *cache.get_mut(index);
+        expand(nav_grid, index, &mut requirements, |adj_index, _, edge, taken| {
+            if obstacles.blocks(index, adj_index, edge) || restrictions.forbids(index, adj_index, edge) {
+                return;
+            }
+            let step_cost = edge.map_or_else(|| cost_model.walk_cost(cost), |edge| cost_model.edge_cost(edge));
+            let adj = cache.get_mut(adj_index);
+            if cost + step_cost < adj.cost {
+                adj.cost = cost + step_cost;
+                adj.prev = index;
+                adj.edge = taken;
+                queue.push(Reverse((adj.cost.saturating_add(estimate(adj_index)), adj.cost, adj_index)));
+            } else if cost + step_cost == adj.cost && edge.is_none() && arrival.walks_straight(index, adj_index) {
+                adj.prev = index;
+                adj.edge = WALK;
+            }
+        });
+    }
+
+    // Walking may be blocked by edges the group doesn't know about, so a pruned teleport could have been the only way
+    if pruned {
+        let (retry_count, mem_usage, result) = astar_restricted(context, nav_grid, start, end, game_state, &SearchOptions { prune_teleports: false, ..options.clone() }, restrictions, heuristic, cost_model, observer);
+        return (count + retry_count, mem_usage, result);
+    }
+    (count, cache.mem_usage(), Ok(None))
+}
+
+/// Dijkstra search expanding from both the start and the end, until the frontiers meet.
+/// Visits far fewer vertices for point to point queries, while still returning an optimal path.
+/// Teleports can't be traversed in reverse, so they only seed the search from the start.
+pub fn bidirectional(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState) -> (usize, usize, Option<Path>) {
+    let (count, mem_usage, result) = bidirectional_with_options(nav_grid, start, end, game_state, &SearchOptions::default());
+    (count, mem_usage, result.unwrap_or(None))
+}
+
+/// Bidirectional search respecting obstacles, the search budget and requirement annotation of the options.
+/// Consumables are only known to be in stock once both halves of the path are joined,
+/// so `strict_consumables` falls back to a regular search. So do starts deeper in the Wilderness than
+/// [TELEPORT_WILDERNESS_LEVEL], since teleports cast along the way would have to be traversed backwards to meet.
+/// `heuristic_weight` has no effect.
+pub fn bidirectional_with_options(nav_grid: &NavGrid, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<Path>, Aborted>) {
+    if options.strict_consumables || !can_teleport_from(start.index()) {
+        return dijkstra_with_options(nav_grid, start, end, game_state, options);
+    }
+    let start_index = start.index();
+    let end_index = end.index();
+    let target_group = nav_grid.vertices[end_index as usize].get_group();
+    let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids()).ignore_requirements(options.ignore_requirements);
+    let mut reverse_edges: HashMap<u32, Vec<(u32, u32, &Edge)>> = HashMap::new();
+    // Edges of vertices that aren't flagged to have any are never taken forwards, so they mustn't be taken backwards
+    for (source, edges) in nav_grid.edges.iter_all().filter(|(source, _)| nav_grid.vertices.get(**source as usize).map_or(false, |v| v.has_extra_edges())) {
+        for (position, edge) in edges.iter().enumerate() {
+            if requirements.edge_met(*source, position, &edge.requirements) {
+                if let Some(destination) = edge.resolve_destination(game_state) {
+                    reverse_edges.entry(destination.index()).or_default().push((*source, position as u32, edge));
+                }
+            }
+        }
+    }
+    // The edge map iterates in no particular order, sources are sorted to keep the backward search deterministic
+    reverse_edges.values_mut().for_each(|edges| edges.sort_by_key(|(source, _, _)| *source));
+    let mut forward = RegionCache::new(UNVISITED);
+    let mut backward = RegionCache::new(UNVISITED);
+    let mut forward_queue = BinaryHeap::new();
+    let mut backward_queue = BinaryHeap::new();
+    let budget = options.budget();
+    let mut count = 0;
+    let obstacles = options.obstacles();
+    let mut teleports = Teleports::new(nav_grid, game_state, options.ignore_requirements);
+    let walkable = expand_start(nav_grid, start_index, &[target_group]);
+    forward.get_mut(start_index).cost = 0;
+    forward_queue.push(Reverse((0, start_index)));
+    backward.get_mut(end_index).cost = 0;
+    backward_queue.push(Reverse((0, end_index)));
+    let mut best = u32::MAX;
+    let mut meeting = end_index;
+
+    loop {
+        let top_forward = forward_queue.peek().map_or(u32::MAX, |Reverse((cost, _))| *cost);
+        let top_backward = backward_queue.peek().map_or(u32::MAX, |Reverse((cost, _))| *cost);
+        if top_forward == u32::MAX && top_backward == u32::MAX || top_forward.saturating_add(top_backward) >= best {
+            break;
+        }
+        if top_forward <= top_backward {
+            let Reverse((cost, index)) = forward_queue.pop().unwrap();
+            if cost > forward.get_mut(index).cost {
+                continue;
+            }
+            if let Err(aborted) = budget.check(count, cost, || forward.mem_usage() + backward.mem_usage()) {
+                return (count, forward.mem_usage() + backward.mem_usage(), Err(aborted));
+            }
+            count += 1;
+            teleports.cast_from(index, cost, |dest_index, teleport, taken| {
+                if nav_grid.vertices[dest_index as usize].get_group() == target_group && !obstacles.blocks(index, dest_index, Some(teleport)) {
+                    let dest = forward.get_mut(dest_index);
+                    if cost + teleport.cost < dest.cost {
+                        dest.cost = cost + teleport.cost;
+                        dest.prev = index;
+                        dest.edge = taken;
+                        forward_queue.push(Reverse((dest.cost, dest_index)));
+                        let remaining = backward.get_mut(dest_index).cost;
+                        if remaining != u32::MAX && cost + teleport.cost + remaining < best {
+                            best = cost + teleport.cost + remaining;
+                            meeting = dest_index;
+                        }
+                    }
+                }
+            });
+            if index == start_index && !walkable {
+                continue;
+            }
+            let arrival = *forward.get_mut(index);
+            expand(nav_grid, index, &mut requirements, |adj_index, step_cost, edge, taken| {
+                if obstacles.blocks(index, adj_index, edge) {
+                    return;
+                }
+                let adj = forward.get_mut(adj_index);
+                if cost + step_cost == adj.cost && edge.is_none() && arrival.walks_straight(index, adj_index) {
+                    adj.prev = index;
+                    adj.edge = WALK;
+                } else if cost + step_cost < adj.cost {
+                    adj.cost = cost + step_cost;
+                    adj.prev = index;
+                    adj.edge = taken;
+                    forward_queue.push(Reverse((adj.cost, adj_index)));
+                    let remaining = backward.get_mut(adj_index).cost;
+                    if remaining != u32::MAX && cost + step_cost + remaining < best {
+                        best = cost + step_cost + remaining;
+                        meeting = adj_index;
+                    }
+                }
+            });
+        } else {
+            let Reverse((cost, index)) = backward_queue.pop().unwrap();
+            if cost > backward.get_mut(index).cost {
+                continue;
+            }
+            if let Err(aborted) = budget.check(count, cost, || forward.mem_usage() + backward.mem_usage()) {
+                return (count, forward.mem_usage() + backward.mem_usage(), Err(aborted));
+            }
+            count += 1;
+            let arrival = *backward.get_mut(index);
+            expand_reverse(nav_grid, &reverse_edges, index, |adj_index, step_cost, edge, taken| {
+                if obstacles.contains(adj_index) || obstacles.blocks(adj_index, index, edge) {
+                    return;
+                }
+                let adj = backward.get_mut(adj_index);
+                if cost + step_cost == adj.cost && edge.is_none() && arrival.walks_straight(index, adj_index) {
+                    adj.prev = index;
+                    adj.edge = WALK;
+                } else if cost + step_cost < adj.cost {
+                    adj.cost = cost + step_cost;
+                    adj.prev = index;
+                    adj.edge = taken;
+                    backward_queue.push(Reverse((adj.cost, adj_index)));
+                    let travelled = forward.get_mut(adj_index).cost;
+                    if travelled != u32::MAX && cost + step_cost + travelled < best {
+                        best = cost + step_cost + travelled;
+                        meeting = adj_index;
+                    }
+                }
+            });
+        }
+    }
+
+    let mem_usage = forward.mem_usage() + backward.mem_usage();
+    if best == u32::MAX {
+        return (count, mem_usage, Ok(None));
+    }
+    let mut steps = backtrack(nav_grid, &mut forward, start_index, meeting, options.annotate_requirements);
+    let mut index = meeting;
+    while index != end_index {
+        let state = *backward.get_mut(index);
+        // Backward searches reach the source of an edge from its destination
+        let edge = state.taken_edge(nav_grid, index);
+        let (definition, kind) = if let Some(edge) = edge {
+            (edge.definition.clone(), StepKind::Edge)
+        } else {
+            (EdgeDefinition::Step { position: Coordinate::from_index(state.prev) }, StepKind::Walk)
+        };
+        let cost = state.cost - backward.get_mut(state.prev).cost;
+        let requirements = match edge {
+            Some(edge) if options.annotate_requirements => edge.requirements.clone(),
+            _ => Vec::new(),
+        };
+        let arrival = edge.and_then(|edge| edge.arrival);
+        steps.push(Step { definition, kind, from: Coordinate::from_index(index), to: Coordinate::from_index(state.prev), cost, requirements, arrival });
+        index = state.prev;
+    }
+    (count, mem_usage, Ok(Some(Path { steps, cost: best })))
+}
+
+/// Chebyshev distance to the goal, which is a lower bound for walking since every step costs 1.
+/// Edges can be shortcuts though, leading far away or to other planes for little cost (e.g. dungeon entrances),
+/// so the estimate is capped by the cheapest way of reaching the goal from the exit of any shortcut.
+/// The result is consistent, so A* never has to expand a vertex twice.
+struct Heuristic {
+    goal: Coordinate,
+    via_shortcut: u32,
+}
+
+impl Heuristic {
+    fn new(nav_grid: &NavGrid, goal: &Coordinate, game_state: &GameState, cost_model: &dyn CostModel) -> Heuristic {
+        let via_shortcut = nav_grid.edges.iter_all()
+            .flat_map(|(source, edges)| edges.iter().map(move |edge| (Coordinate::from_index(*source), edge)))
+            .filter_map(|(source, edge)| {
+                let destination = edge.resolve_destination(game_state)?;
+                let cost = cost_model.edge_cost(edge);
+                if chebyshev(&source, &destination) > cost {
+                    Some(cost.saturating_add(chebyshev(&destination, goal)))
+                } else {
+                    None
+                }
+            })
+            .min()
+            .unwrap_or(u32::MAX);
+        Heuristic { goal: *goal, via_shortcut }
+    }
+
+    fn estimate(&self, coordinate: &Coordinate) -> u32 {
+        chebyshev(coordinate, &self.goal).min(self.via_shortcut)
+    }
+}
+
+/// Chebyshev distance between two coordinates, or `u32::MAX` if they are on different planes
+fn chebyshev(a: &Coordinate, b: &Coordinate) -> u32 {
+    if a.plane != b.plane {
+        return u32::MAX;
+    }
+    let dx = (a.x as i32 - b.x as i32).unsigned_abs();
+    let dy = (a.y as i32 - b.y as i32).unsigned_abs();
+    dx.max(dy)
+}
+
+/// See [NavGrid::can_move], which [NavGrid::validate] checks the groups with
+#[inline]
+fn can_move(nav_grid: &NavGrid, index: u32, flag: u8, dx: i32, dy: i32) -> bool {
+    nav_grid.can_move(index, flag, dx, dy)
+}
+
+/// Tiles and areas treated as impassable for a single search
+struct Obstacles<'a> {
+    tiles: HashSet<u32>,
+    areas: &'a [Area],
+    disabled: &'a [EdgeDefinition],
+    /// Deepest Wilderness level that may be entered
+    wilderness_cap: Option<u8>,
+}
+
+impl Obstacles<'_> {
+    fn contains(&self, index: u32) -> bool {
+        self.tiles.contains(&index)
+            || self.areas.iter().any(|area| area.contains(&Coordinate::from_index(index)))
+            || self.wilderness_cap.map_or(false, |cap| Coordinate::from_index(index).wilderness_level() > cap)
+    }
+
+    /// Whether moving from `index` to `adj_index` is prevented by the obstacles, or taking the edge is disabled.
+    /// Walking diagonally is also prevented by blocking either of the tiles next to both, like a wall would.
+    fn blocks(&self, index: u32, adj_index: u32, edge: Option<&Edge>) -> bool {
+        if self.tiles.is_empty() && self.areas.is_empty() && self.disabled.is_empty() && self.wilderness_cap.is_none() {
+            return false;
+        }
+        if self.contains(adj_index) {
+            return true;
+        }
+        match edge {
+            Some(edge) => self.disabled.contains(&edge.definition),
+            None => {
+                let dx = (adj_index % WIDTH) as i32 - (index % WIDTH) as i32;
+                let dy = (adj_index / WIDTH) as i32 - (index / WIDTH) as i32;
+                dx != 0 && dy != 0 && (self.contains((index as i32 + dx) as u32) || self.contains((index as i32 + WIDTH as i32 * dy) as u32))
+            }
+        }
+    }
+}
+
+/// Calls `relax` with the index, cost and edge of every vertex that can be moved to from the vertex at `index`, and
+/// what to store as [DijkstraCacheState::edge] of it
+fn expand<'a: 'g, 'g, F>(nav_grid: &'a NavGrid, index: u32, requirements: &mut RequirementEvaluator<'g>, mut relax: F) where F: FnMut(u32, u32, Option<&'a Edge>, u32) {
+    let v = &nav_grid.vertices[index as usize];
+    for (flag, dx, dy) in &DIRECTIONS {
+        if can_move(nav_grid, index, *flag, *dx, *dy) {
+            relax((index as i32 + (WIDTH as i32 * *dy) + *dx) as u32, 1, None, WALK);
+        }
+    }
+    if v.has_extra_edges() {
+        for (position, edge) in nav_grid.edges.get_vec(&index).into_iter().flatten().enumerate() {
+            if requirements.edge_met(index, position, &edge.requirements) {
+                if let Some(destination) = edge.resolve_destination(requirements.game_state()) {
+                    relax(destination.index(), edge.cost, Some(edge), position as u32);
+                }
+            }
+        }
+    }
+}
+
+/// Calls `relax` with the index, cost and edge of every vertex that can move to the vertex at `index`, and what to
+/// store as [DijkstraCacheState::edge] of it
+fn expand_reverse<'a, F>(nav_grid: &'a NavGrid, reverse_edges: &HashMap<u32, Vec<(u32, u32, &'a Edge)>>, index: u32, mut relax: F) where F: FnMut(u32, u32, Option<&'a Edge>, u32) {
+    for (flag, dx, dy) in &DIRECTIONS {
+        let adj_index = index as i64 - (WIDTH as i64 * *dy as i64) - *dx as i64;
+        if usize::try_from(adj_index).map_or(false, |i| i < nav_grid.vertices.len()) && can_move(nav_grid, adj_index as u32, *flag, *dx, *dy) {
+            relax(adj_index as u32, 1, None, WALK);
+        }
+    }
+    for (source, position, edge) in reverse_edges.get(&index).into_iter().flatten() {
+        relax(*source, edge.cost, Some(*edge), *position);
+    }
+}
+
+/// Deepest Wilderness level teleports can be cast from. Jewellery working up to level 30 is treated the same.
+pub const TELEPORT_WILDERNESS_LEVEL: u8 = 20;
+
+/// Whether teleports can be cast on the vertex at `index`
+fn can_teleport_from(index: u32) -> bool {
+    Coordinate::from_index(index).wilderness_level() <= TELEPORT_WILDERNESS_LEVEL
+}
+
+/// Whether a search walks from the vertex at `start_index`. Walking from the start is pointless if it can't reach any
+/// of `target_groups` and teleports can be cast right away.
+fn expand_start(nav_grid: &NavGrid, start_index: u32, target_groups: &[u8]) -> bool {
+    target_groups.contains(&nav_grid.vertices[start_index as usize].get_group()) || !can_teleport_from(start_index)
+}
+
+/// Teleports available to a search, cast from the cheapest vertex expanded so far that teleporting is possible from.
+/// That is the start, unless it is deeper in the Wilderness than [TELEPORT_WILDERNESS_LEVEL] and the path has to walk
+/// out of it first.
+struct Teleports<'a> {
+    /// Destination index, [DijkstraCacheState::edge] and edge of every available teleport
+    available: Vec<(u32, u32, &'a Edge)>,
+    /// Cost of the vertex the teleports were last cast from
+    origin_cost: u32,
+}
+
+impl<'a> Teleports<'a> {
+    fn new(nav_grid: &'a NavGrid, game_state: &GameState, ignore_requirements: bool) -> Teleports<'a> {
+        let available = enumerate_available_teleports(nav_grid, game_state, ignore_requirements).map(|(index, position, teleport)| (index, TELEPORT | position, teleport)).collect();
+        Teleports { available, origin_cost: u32::MAX }
+    }
+
+    /// Calls `relax` with the destination index and edge of every teleport, and what to store as
+    /// [DijkstraCacheState::edge] of the destination, if they can be cast from the vertex at `origin` and it is
+    /// cheaper to reach than the previous origin. Searches call this for every vertex they expand, Dijkstra searches
+    /// only cast the teleports from the first one.
+    fn cast_from<F>(&mut self, origin: u32, cost: u32, mut relax: F) where F: FnMut(u32, &'a Edge, u32) {
+        if cost < self.origin_cost && can_teleport_from(origin) {
+            self.origin_cost = cost;
+            for (index, taken, teleport) in &self.available {
+                relax(*index, teleport, *taken);
+            }
+        }
+    }
+}
+
+/// Destination index and edge of every teleport whose requirements are met, or of all teleports if requirements are ignored
+fn available_teleports<'a: 'g, 'g>(nav_grid: &'a NavGrid, game_state: &'g GameState, ignore_requirements: bool) -> impl Iterator<Item=(u32, &'a Edge)> + 'g {
+    enumerate_available_teleports(nav_grid, game_state, ignore_requirements).map(|(index, _, teleport)| (index, teleport))
+}
+
+/// Like [available_teleports], along with the position of each teleport in the teleports of the NavGrid
+fn enumerate_available_teleports<'a: 'g, 'g>(nav_grid: &'a NavGrid, game_state: &'g GameState, ignore_requirements: bool) -> impl Iterator<Item=(u32, u32, &'a Edge)> + 'g {
+    nav_grid.teleports.iter().enumerate()
+        .filter(move |(_, teleport)| ignore_requirements || teleport.requirements.iter().all(|req| req.is_met(game_state)))
+        .filter_map(move |(position, teleport)| Some((teleport.resolve_destination(game_state)?.index(), position as u32, teleport)))
+}
+
+fn backtrack(nav_grid: &NavGrid, cache: &mut RegionCache<DijkstraCacheState>, start_index: u32, mut index: u32, annotate_requirements: bool) -> Vec<Step> {
+    let mut path = vec![];
+    while index != start_index {
+        let state = *cache.get_mut(index);
+        let edge = state.taken_edge(nav_grid, state.prev);
+        let (definition, kind) = match edge {
+            Some(edge) if state.is_teleport() => (edge.definition.clone(), StepKind::Teleport),
+            Some(edge) => (edge.definition.clone(), StepKind::Edge),
+            None => (EdgeDefinition::Step { position: Coordinate::from_index(index) }, StepKind::Walk),
+        };
+        let cost = state.cost - cache.get_mut(state.prev).cost;
+        let requirements = match edge {
+            Some(edge) if annotate_requirements => edge.requirements.clone(),
+            _ => Vec::new(),
+        };
+        let arrival = edge.and_then(|edge| edge.arrival);
+        path.push(Step { definition, kind, from: Coordinate::from_index(state.prev), to: Coordinate::from_index(index), cost, requirements, arrival });
+        index = state.prev;
+    }
+    path.reverse();
+    path
+}
+
+fn consumables_in_stock<'a>(nav_grid: &'a NavGrid, cache: &mut RegionCache<DijkstraCacheState>, start_index: u32, mut index: u32, game_state: &GameState) -> bool {
+    let mut consumed: Vec<(&'a Regex, u32)> = Vec::new();
+    while index != start_index {
+        let state = *cache.get_mut(index);
+        for requirement in state.taken_edge(nav_grid, state.prev).iter().flat_map(|edge| &edge.requirements) {
+            if let RequirementDefinition::Item { item, quantity, consumed: true } = requirement {
+                match consumed.iter_mut().find(|(i, _)| i.as_str() == item.as_str()) {
+                    Some((_, total)) => *total += quantity,
+                    None => consumed.push((item, *quantity)),
+                }
+            }
+        }
+        index = state.prev;
+    }
+    consumed.iter().all(|(item, total)| game_state.item_quantity(item) >= *total)
+}
+
+/// Maps every game tick of a path to the position the player is expected to be on at the end of that tick.
+/// Edges keep the player on their entry tile for their cost in ticks, walking covers one or two tiles per tick.
+/// A run segment of odd length finishes its last tile in a tick of its own.
+pub fn path_to_tick_timeline(path: &[Step], movement_mode: MovementMode) -> Vec<(u32, Coordinate)> {
+    let mut timeline = Vec::new();
+    let mut tick = 0;
+    let mut moved = 0;
+    if let Some(first) = path.first() {
+        timeline.push((tick, first.from));
+    }
+    for step in path {
+        if step.is_walk() {
+            moved += 1;
+            if moved == movement_mode.tiles_per_tick() {
+                tick += 1;
+                timeline.push((tick, step.to));
+                moved = 0;
+            }
+            continue;
+        }
+        if moved > 0 {
+            tick += 1;
+            timeline.push((tick, step.from));
+            moved = 0;
+        }
+        for _ in 1..step.cost {
+            tick += 1;
+            timeline.push((tick, step.from));
+        }
+        tick += 1;
+        timeline.push((tick, step.to));
+    }
+    if moved > 0 {
+        if let Some(last) = path.last() {
+            timeline.push((tick + 1, last.to));
+        }
+    }
+    timeline
+}
+
+/// Links between vertex groups that can be taken under the game state.
+/// Teleports have no source group, since they can be used from anywhere.
+pub fn group_links<'a>(nav_grid: &'a NavGrid, game_state: &GameState) -> Vec<(Option<u8>, u8, &'a Edge)> {
+    let group_of = |c: &Coordinate| nav_grid.vertices[c.index() as usize].get_group();
+    let mut links = Vec::new();
+    for (source, edges) in nav_grid.edges.iter_all() {
+        let source_group = nav_grid.vertices[*source as usize].get_group();
+        for edge in edges {
+            if let Some(destination) = edge.resolve_destination(game_state) {
+                if group_of(&destination) != source_group && edge.requirements.iter().all(|req| req.is_met(game_state)) {
+                    links.push((Some(source_group), group_of(&destination), edge));
+                }
+            }
+        }
+    }
+    for teleport in &nav_grid.teleports {
+        if let Some(destination) = teleport.resolve_destination(game_state) {
+            if teleport.requirements.iter().all(|req| req.is_met(game_state)) {
+                links.push((None, group_of(&destination), teleport));
+            }
+        }
+    }
+    links
+}
+
+pub fn flood<F>(nav_grid: &NavGrid, start: &Coordinate, mut visit_vertex: F) where F: FnMut(u32) -> bool {
+    let mut queue = VecDeque::new();
+    let mut cache = RegionCache::new(false);
+    queue.push_back(start.index());
+    *cache.get_mut(start.index()) = true;
+    while let Some(index) = queue.pop_front() {
+        let v = &nav_grid.vertices[index as usize];
+        if !visit_vertex(index) {
+            continue;
+        }
+        for (flag, dx, dy) in &DIRECTIONS {
+            if can_move(nav_grid, index, *flag, *dx, *dy) {
+                let adj_index = (index as i32 + (WIDTH as i32 * *dy) + *dx) as u32;
+                let visited = cache.get_mut(adj_index);
+                if !*visited {
+                    queue.push_back(adj_index);
+                    *visited = true;
+                }
+            }
+        }
+        if v.has_extra_edges() {
+            for edge in nav_grid.edges.get_vec(&index).into_iter().flatten() {
+                let visited = cache.get_mut(edge.destination.index());
+                if !*visited {
+                    queue.push_back(edge.destination.index());
+                    *visited = true;
+                }
+            }
+        }
+    }
+}
+
+/// Like [flood], but only visits vertices that can be reached for at most `max_cost`, in order of their cost
+pub fn flood_bounded<F>(nav_grid: &NavGrid, start: &Coordinate, max_cost: u32, mut visit_vertex: F) where F: FnMut(u32, u32) -> bool {
+    let mut queue = BinaryHeap::new();
+    let mut cache = RegionCache::new(u32::MAX);
+    queue.push(Reverse((0, start.index())));
+    *cache.get_mut(start.index()) = 0;
+    while let Some(Reverse((cost, index))) = queue.pop() {
+        if cost > *cache.get_mut(index) {
+            continue;
+        }
+        let v = &nav_grid.vertices[index as usize];
+        if !visit_vertex(index, cost) {
+            continue;
+        }
+        let mut relax = |adj_index: u32, adj_cost: u32| {
+            let adj = cache.get_mut(adj_index);
+            if adj_cost <= max_cost && adj_cost < *adj {
+                *adj = adj_cost;
+                queue.push(Reverse((adj_cost, adj_index)));
+            }
+        };
+        for (flag, dx, dy) in &DIRECTIONS {
+            if can_move(nav_grid, index, *flag, *dx, *dy) {
+                relax((index as i32 + (WIDTH as i32 * *dy) + *dx) as u32, cost + 1);
+            }
+        }
+        if v.has_extra_edges() {
+            for edge in nav_grid.edges.get_vec(&index).into_iter().flatten() {
+                relax(edge.destination.index(), cost.saturating_add(edge.cost));
+            }
+        }
+    }
+}
+
+/// Like [flood_bounded], but bounded by `max_cost` of the options if set, and gives up once the budget of the options
+/// is exceeded. The obstacles of the options are ignored, since edges are followed regardless of their requirements.
+pub fn flood_with_options<F>(nav_grid: &NavGrid, start: &Coordinate, options: &SearchOptions, mut visit_vertex: F) -> (usize, usize, Result<(), Aborted>) where F: FnMut(u32, u32) -> bool {
+    let max_cost = options.max_cost.unwrap_or(u32::MAX);
+    let budget = options.budget();
+    let mut queue = BinaryHeap::new();
+    let mut cache = RegionCache::new(u32::MAX);
+    let mut count = 0;
+    queue.push(Reverse((0, start.index())));
+    *cache.get_mut(start.index()) = 0;
+    while let Some(Reverse((cost, index))) = queue.pop() {
+        if cost > *cache.get_mut(index) {
+            continue;
+        }
+        if let Err(aborted) = budget.check(count, cost, || cache.mem_usage() + queue.capacity() * std::mem::size_of::<Reverse<(u32, u32)>>()) {
+            return (count, cache.mem_usage(), Err(aborted));
+        }
+        count += 1;
+        let v = &nav_grid.vertices[index as usize];
+        if !visit_vertex(index, cost) {
+            continue;
+        }
+        let mut relax = |adj_index: u32, adj_cost: u32| {
+            let adj = cache.get_mut(adj_index);
+            if adj_cost <= max_cost && adj_cost < *adj {
+                *adj = adj_cost;
+                queue.push(Reverse((adj_cost, adj_index)));
+            }
+        };
+        for (flag, dx, dy) in &DIRECTIONS {
+            if can_move(nav_grid, index, *flag, *dx, *dy) {
+                relax((index as i32 + (WIDTH as i32 * *dy) + *dx) as u32, cost + 1);
+            }
+        }
+        if v.has_extra_edges() {
+            for edge in nav_grid.edges.get_vec(&index).into_iter().flatten() {
+                relax(edge.destination.index(), cost.saturating_add(edge.cost));
+            }
+        }
+    }
+    (count, cache.mem_usage(), Ok(()))
+}
+
+/// Like [flood_bounded], but only follows edges whose requirements are met by the game state. Teleports aren't taken,
+/// so this is the area around the start that can be reached on foot.
+pub fn flood_available<F>(nav_grid: &NavGrid, start: &Coordinate, game_state: &GameState, max_cost: u32, visit_vertex: F) where F: FnMut(u32, u32) -> bool {
+    let options = SearchOptions { max_cost: Some(max_cost), ..SearchOptions::default() };
+    let _ = flood_available_with_options(nav_grid, start, game_state, &options, visit_vertex);
+}
+
+/// Like [flood_available], but bounded by `max_cost` of the options if set, and gives up once the budget of the
+/// options is exceeded
+pub fn flood_available_with_options<F>(nav_grid: &NavGrid, start: &Coordinate, game_state: &GameState, options: &SearchOptions, mut visit_vertex: F) -> (usize, usize, Result<(), Aborted>) where F: FnMut(u32, u32) -> bool {
+    let max_cost = options.max_cost.unwrap_or(u32::MAX);
+    let budget = options.budget();
+    let mut queue = BinaryHeap::new();
+    let mut cache = RegionCache::new(u32::MAX);
+    let mut count = 0;
+    let mut requirements = RequirementEvaluator::new(game_state).requirement_ids(nav_grid.requirement_ids()).ignore_requirements(options.ignore_requirements);
+    queue.push(Reverse((0, start.index())));
+    *cache.get_mut(start.index()) = 0;
+    while let Some(Reverse((cost, index))) = queue.pop() {
+        if cost > *cache.get_mut(index) {
+            continue;
+        }
+        if let Err(aborted) = budget.check(count, cost, || cache.mem_usage() + queue.capacity() * std::mem::size_of::<Reverse<(u32, u32)>>()) {
+            return (count, cache.mem_usage(), Err(aborted));
+        }
+        count += 1;
+        if !visit_vertex(index, cost) {
+            continue;
+        }
+        expand(nav_grid, index, &mut requirements, |adj_index, step_cost, _, _| {
+            let adj_cost = cost.saturating_add(step_cost);
+            let adj = cache.get_mut(adj_index);
+            if adj_cost <= max_cost && adj_cost < *adj {
+                *adj = adj_cost;
+                queue.push(Reverse((adj_cost, adj_index)));
+            }
+        });
+    }
+    (count, cache.mem_usage(), Ok(()))
+}
+
+/// Highest group id assigned by [par_label_groups], since group ids are 7 bits and 0 and 1 are reserved
+pub const MAX_GROUP: u8 = 127;
+
+/// Recomputes the groups of all walkable vertices in parallel, e.g. after adding edges to a NavGrid.
+/// Vertices connected by walking or edges in either direction form a component. The largest components containing a
+/// vertex accepted by `seed` are numbered from 2 by descending size, all other walkable vertices are assigned group 1.
+pub fn par_label_groups<F>(nav_grid: &mut NavGrid, seed: F) where F: Fn(&Coordinate) -> bool + Sync {
+    let parents: Vec<AtomicU32> = (0..nav_grid.vertices.len() as u32).into_par_iter().map(AtomicU32::new).collect();
+    let grid: &NavGrid = nav_grid;
+    (0..grid.vertices.len() as u32).into_par_iter().filter(|index| grid.vertices[*index as usize].flags != 0).for_each(|index| {
+        for (flag, dx, dy) in &DIRECTIONS {
+            if can_move(grid, index, *flag, *dx, *dy) {
+                union(&parents, index, (index as i32 + (WIDTH as i32 * *dy) + *dx) as u32);
+            }
+        }
+        if grid.vertices[index as usize].has_extra_edges() {
+            for edge in grid.edges.get_vec(&index).into_iter().flatten() {
+                union(&parents, index, edge.destination.index());
+            }
+        }
+    });
+    let sizes = (0..grid.vertices.len() as u32).into_par_iter()
+        .filter(|index| grid.vertices[*index as usize].flags != 0)
+        .fold(HashMap::new, |mut sizes: HashMap<u32, (usize, bool)>, index| {
+            let component = sizes.entry(find(&parents, index)).or_insert((0, false));
+            component.0 += 1;
+            component.1 |= seed(&Coordinate::from_index(index));
+            sizes
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (root, (size, seeded)) in b {
+                let component = a.entry(root).or_insert((0, false));
+                component.0 += size;
+                component.1 |= seeded;
+            }
+            a
+        });
+    let mut components: Vec<(u32, usize)> = sizes.into_iter().filter(|(_, (_, seeded))| *seeded).map(|(root, (size, _))| (root, size)).collect();
+    components.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let groups: HashMap<u32, u8> = components.into_iter().take((MAX_GROUP - 1) as usize).enumerate().map(|(i, (root, _))| (root, i as u8 + 2)).collect();
+    nav_grid.vertices.as_mut_slice().par_iter_mut().enumerate().filter(|(_, vertex)| vertex.flags != 0).for_each(|(index, vertex)| {
+        vertex.set_group(groups.get(&find(&parents, index as u32)).copied().unwrap_or(1));
+    });
+}
+
+/// Root of the component of the vertex in a concurrent union-find, halving the path to it on the way
+fn find(parents: &[AtomicU32], mut index: u32) -> u32 {
+    loop {
+        let parent = parents[index as usize].load(Ordering::Acquire);
+        if parent == index {
+            return index;
+        }
+        let grandparent = parents[parent as usize].load(Ordering::Acquire);
+        let _ = parents[index as usize].compare_exchange_weak(parent, grandparent, Ordering::AcqRel, Ordering::Relaxed);
+        index = grandparent;
+    }
+}
+
+/// Merges the components of both vertices. Roots are always linked to the lower root, so concurrent unions can't
+/// create cycles.
+fn union(parents: &[AtomicU32], a: u32, b: u32) {
+    loop {
+        let (a, b) = (find(parents, a), find(parents, b));
+        if a == b {
+            return;
+        }
+        let (high, low) = if a > b { (a, b) } else { (b, a) };
+        if parents[high as usize].compare_exchange(high, low, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use model::NavGridBuilder;
+    use model::definitions::PlaneOffset;
+
+    use super::*;
+
+    type Search = fn(&NavGrid, &Coordinate, &Coordinate, &GameState) -> (usize, usize, Option<Path>);
+
+    fn c(x: u16, y: u16) -> Coordinate {
+        Coordinate { x, y, plane: 0 }
+    }
+
+    fn area(min: Coordinate, max: Coordinate) -> Area {
+        Area { min, max }
+    }
+
+    /// Walkable rectangle with the walls left out
+    fn field(area: Area, walls: &[Coordinate]) -> NavGridBuilder {
+        let mut builder = NavGrid::builder();
+        for y in area.min.y..=area.max.y {
+            for x in area.min.x..=area.max.x {
+                if !walls.contains(&c(x, y)) {
+                    builder = builder.walkable(c(x, y));
+                }
+            }
+        }
+        builder
+    }
+
+    fn door(id: u32, position: Coordinate, destination: Coordinate) -> Edge {
+        Edge {
+            destination,
+            cost: 2,
+            definition: EdgeDefinition::Door { id, name: None, position, action: Regex::new("Open").unwrap() },
+            requirements: Vec::new(),
+            instance_plane: None,
+            arrival: None,
+        }
+    }
+
+    fn teleport(spell: &str, destination: Coordinate, cost: u32) -> Edge {
+        Edge {
+            destination,
+            cost,
+            definition: EdgeDefinition::SpellTeleport { spell: spell.to_string() },
+            requirements: Vec::new(),
+            instance_plane: None,
+            arrival: None,
+        }
+    }
+
+    fn cost(path: Option<Path>) -> Option<u32> {
+        path.map(|path| path.cost)
+    }
+
+    /// Pops all states in order, pushing the states `next` returns for each popped one at their absolute costs
+    fn drain(queue: &mut BucketRingBuffer<u32>, mut next: impl FnMut(u32) -> Vec<u32>) -> Vec<u32> {
+        let mut popped = Vec::new();
+        while queue.next_bin().is_some() {
+            while let Some(cost) = queue.pop() {
+                popped.push(cost);
+                for next_cost in next(cost) {
+                    queue.push(next_cost - cost, next_cost);
+                }
+            }
+            queue.increment();
+        }
+        popped
+    }
+
+    #[test]
+    fn bucket_ring_buffer_grows_for_costs_beyond_its_bins() {
+        let mut queue = BucketRingBuffer::new(2);
+        queue.push(0, 0);
+        // The ring has 3 bins and its cursor is past the start when 10 is pushed, so wrapping around would pop it as 1
+        let popped = drain(&mut queue, |cost| match cost {
+            0 => vec![1, 2],
+            1 => vec![10, 3],
+            2 => vec![3],
+            _ => vec![],
+        });
+        assert_eq!(popped, [0, 1, 2, 3, 3, 10]);
+    }
+
+    /// Linear congruential generator, so randomized tests are repeatable
+    fn random(seed: &mut u64) -> u32 {
+        *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (*seed >> 33) as u32
+    }
+
+    #[test]
+    fn bucket_ring_buffer_settles_the_distances_of_a_binary_heap() {
+        // Random graph with costs from 0 to well beyond the bins the ring starts with
+        let mut seed = 7;
+        let graph: Vec<Vec<(u32, u32)>> = (0..500).map(|_| (0..3).map(|_| (random(&mut seed) % 500, random(&mut seed) % 13)).collect()).collect();
+        let mut heap_distances = vec![u32::MAX; graph.len()];
+        let mut heap = BinaryHeap::new();
+        heap_distances[0] = 0;
+        heap.push(Reverse((0, 0)));
+        while let Some(Reverse((cost, node))) = heap.pop() {
+            if cost > heap_distances[node as usize] {
+                continue;
+            }
+            for (next, edge_cost) in &graph[node as usize] {
+                if cost + edge_cost < heap_distances[*next as usize] {
+                    heap_distances[*next as usize] = cost + edge_cost;
+                    heap.push(Reverse((cost + edge_cost, *next)));
+                }
+            }
+        }
+        let mut bucket_distances = vec![u32::MAX; graph.len()];
+        let mut queue = BucketRingBuffer::new(4);
+        let mut popped = Vec::new();
+        bucket_distances[0] = 0;
+        queue.push(0, (0, 0));
+        while queue.next_bin().is_some() {
+            while let Some((cost, node)) = queue.pop() {
+                if cost > bucket_distances[node as usize] {
+                    continue;
+                }
+                popped.push(cost);
+                for (next, edge_cost) in &graph[node as usize] {
+                    if cost + edge_cost < bucket_distances[*next as usize] {
+                        bucket_distances[*next as usize] = cost + edge_cost;
+                        queue.push(*edge_cost, (cost + edge_cost, *next));
+                    }
+                }
+            }
+            queue.increment();
+        }
+        assert_eq!(bucket_distances, heap_distances);
+        assert!(popped.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", popped);
+    }
+
+    #[test]
+    fn dijkstra_costs_match_the_searches_using_a_binary_heap() {
+        // Field strewn with walls, with doors through some of them and a teleport into its middle
+        let mut seed = 11;
+        let walls: Vec<Coordinate> = (0..300).map(|_| c((random(&mut seed) % 40) as u16, (random(&mut seed) % 40) as u16)).collect();
+        let mut builder = field(area(c(0, 0), c(39, 39)), &walls).teleport(teleport("Varrock Teleport", c(20, 20), 12));
+        for wall in walls.iter().filter(|wall| (1..39).contains(&wall.x)).take(30) {
+            let (west, east) = (c(wall.x - 1, wall.y), c(wall.x + 1, wall.y));
+            builder = builder.edge(west, door(wall.index(), *wall, east)).edge(east, door(wall.index(), *wall, west));
+        }
+        let nav_grid = builder.build();
+        let tiles: Vec<Coordinate> = (0..40).flat_map(|x| (0..40).map(move |y| c(x, y))).filter(|tile| !walls.contains(tile)).collect();
+        for _ in 0..50 {
+            let start = tiles[random(&mut seed) as usize % tiles.len()];
+            let end = tiles[random(&mut seed) as usize % tiles.len()];
+            let expected = cost(dijkstra(&nav_grid, &start, &end, &GameState::default()).2);
+            assert_eq!(cost(astar(&nav_grid, &start, &end, &GameState::default()).2), expected, "{} -> {}", start, end);
+            assert_eq!(cost(bidirectional(&nav_grid, &start, &end, &GameState::default()).2), expected, "{} -> {}", start, end);
+        }
+    }
+
+    #[test]
+    fn searches_skip_extra_edge_flags_without_edges() {
+        let mut nav_grid = NavGrid::builder().walkable_area(&area(c(10, 10), c(14, 10))).build();
+        nav_grid.vertices[c(12, 10).index() as usize].set_extra_edges(true);
+        let game_state = GameState::default();
+        assert_eq!(cost(dijkstra(&nav_grid, &c(10, 10), &c(14, 10), &game_state).2), Some(4));
+        assert_eq!(cost(astar(&nav_grid, &c(10, 10), &c(14, 10), &game_state).2), Some(4));
+        assert_eq!(cost(bidirectional(&nav_grid, &c(10, 10), &c(14, 10), &game_state).2), Some(4));
+    }
+
+    #[test]
+    fn searches_ignore_edges_without_flag() {
+        // Two rooms joined by a door whose source isn't flagged, so it can't be taken. The west room is larger, so the
+        // backward half of the bidirectional search reaches the door before the forward half runs out of tiles.
+        let mut nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(6, 10), c(12, 12)))
+            .walkable_area(&area(c(14, 10), c(16, 12)))
+            .edge(c(12, 11), door(1, c(13, 11), c(14, 11)))
+            .build();
+        nav_grid.vertices[c(12, 11).index() as usize].set_extra_edges(false);
+        let game_state = GameState::default();
+        assert_eq!(cost(dijkstra(&nav_grid, &c(10, 11), &c(16, 11), &game_state).2), None);
+        assert_eq!(cost(astar(&nav_grid, &c(10, 11), &c(16, 11), &game_state).2), None);
+        assert_eq!(cost(bidirectional(&nav_grid, &c(10, 11), &c(16, 11), &game_state).2), None);
+    }
+
+    /// Grids with a start and end each, which searches are compared on. The optimal path of the winding corridor is the
+    /// only one, the other grids have many paths of the same cost.
+    fn fixtures() -> Vec<(NavGrid, Coordinate, Coordinate)> {
+        vec![
+            // Open field
+            (NavGrid::builder().walkable_area(&area(c(0, 0), c(39, 39))).build(), c(2, 3), c(35, 30)),
+            // Wall with a gap at its north end
+            (NavGrid::builder()
+                 .walkable_area(&area(c(0, 0), c(19, 39)))
+                 .walkable_area(&area(c(21, 0), c(39, 39)))
+                 .walkable(c(20, 39))
+                 .build(), c(5, 5), c(35, 5)),
+            // Rooms joined by a door
+            (NavGrid::builder()
+                 .walkable_area(&area(c(0, 0), c(9, 9)))
+                 .walkable_area(&area(c(11, 0), c(19, 9)))
+                 .edge(c(9, 5), door(1, c(10, 5), c(11, 5)))
+                 .edge(c(11, 5), door(1, c(10, 5), c(9, 5)))
+                 .build(), c(2, 2), c(17, 8)),
+            winding_corridor(),
+        ]
+    }
+
+    /// Corridor one tile wide, leading east, north, west and north again, with a door halfway along its second leg
+    fn winding_corridor() -> (NavGrid, Coordinate, Coordinate) {
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(20, 0)))
+            .walkable_area(&area(c(20, 1), c(20, 4)))
+            .walkable_area(&area(c(20, 6), c(20, 10)))
+            .walkable_area(&area(c(5, 10), c(19, 10)))
+            .walkable_area(&area(c(5, 11), c(5, 20)))
+            .edge(c(20, 4), door(1, c(20, 5), c(20, 6)))
+            .edge(c(20, 6), door(1, c(20, 5), c(20, 4)))
+            .build();
+        (nav_grid, c(0, 0), c(5, 20))
+    }
+
+    /// Asserts that the path leads from the start to the end, each step starting where the previous one ended, and walks
+    /// only between tiles the grid allows moving between
+    fn assert_connected(nav_grid: &NavGrid, path: &Path, start: &Coordinate, end: &Coordinate) {
+        let mut position = *start;
+        for step in &path.steps {
+            assert_eq!(step.from, position, "{:?}", path);
+            if step.is_walk() {
+                let (dx, dy) = step.direction();
+                let (flag, _, _) = DIRECTIONS.iter().find(|(_, x, y)| (*x, *y) == (dx, dy)).expect("Walking steps move to an adjacent tile");
+                assert!(nav_grid.can_move(step.from.index(), *flag, dx, dy), "{} -> {} is blocked", step.from, step.to);
+            }
+            position = step.to;
+        }
+        assert_eq!(position, *end, "{:?}", path);
+        assert_eq!(path.cost, path.steps.iter().map(|step| step.cost).sum::<u32>());
+    }
+
+    fn same_path(a: &Path, b: &Path) -> bool {
+        a.cost == b.cost && same_steps(&a.steps, &b.steps) && a.steps.iter().zip(&b.steps).all(|(a, b)| a.kind == b.kind && a.cost == b.cost)
+    }
+
+    #[test]
+    fn astar_finds_the_path_of_dijkstra() {
+        let game_state = GameState::default();
+        for (nav_grid, start, end) in fixtures() {
+            let (_, _, expected) = dijkstra(&nav_grid, &start, &end, &game_state);
+            let (_, _, path) = astar(&nav_grid, &start, &end, &game_state);
+            let (expected, path) = (expected.unwrap(), path.unwrap());
+            assert_connected(&nav_grid, &path, &start, &end);
+            // Ties between paths of equal cost depend on the order vertices are expanded in, which differs
+            assert_eq!(path.cost, expected.cost, "{} -> {}", start, end);
+        }
+        let (nav_grid, start, end) = winding_corridor();
+        let (_, _, expected) = dijkstra(&nav_grid, &start, &end, &game_state);
+        let (_, _, path) = astar(&nav_grid, &start, &end, &game_state);
+        assert!(same_path(path.as_ref().unwrap(), expected.as_ref().unwrap()), "{:?} != {:?}", path, expected);
+    }
+
+    #[test]
+    fn astar_visits_fewer_vertices_than_dijkstra() {
+        let nav_grid = NavGrid::builder().walkable_area(&area(c(0, 0), c(299, 99))).build();
+        let game_state = GameState::default();
+        let (dijkstra_visited, _, expected) = dijkstra(&nav_grid, &c(5, 50), &c(295, 50), &game_state);
+        let (astar_visited, _, path) = astar(&nav_grid, &c(5, 50), &c(295, 50), &game_state);
+        assert_eq!(cost(path), cost(expected));
+        assert!(astar_visited < dijkstra_visited, "{} >= {}", astar_visited, dijkstra_visited);
+    }
+
+    #[test]
+    fn astar_with_heuristic_matches_dijkstra_costs() {
+        let game_state = GameState::default();
+        let options = SearchOptions::default();
+        for (nav_grid, start, end) in fixtures() {
+            let (_, _, expected) = dijkstra(&nav_grid, &start, &end, &game_state);
+            let (zero_visited, _, zero) = astar_with_heuristic(&nav_grid, &start, &end, &game_state, &options, |_| 0);
+            let (chebyshev_visited, _, path) = astar_with_heuristic(&nav_grid, &start, &end, &game_state, &options, |coordinate| chebyshev(coordinate, &end));
+            assert_eq!(cost(zero.unwrap()), cost(expected.clone()));
+            assert_eq!(cost(path.unwrap()), cost(expected));
+            assert!(chebyshev_visited <= zero_visited);
+        }
+    }
+
+    #[test]
+    fn paths_can_start_with_a_teleport() {
+        // The rooms aren't connected, the end can only be reached by teleporting
+        let mut nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(5, 5)))
+            .walkable_area(&area(c(20, 0), c(25, 5)))
+            .teleport(teleport("Varrock Teleport", c(21, 1), 5))
+            .build();
+        par_label_groups(&mut nav_grid, |_| true);
+        let game_state = GameState::default();
+        let (start, end) = (c(2, 2), c(24, 4));
+        let paths = [
+            dijkstra(&nav_grid, &start, &end, &game_state).2,
+            astar(&nav_grid, &start, &end, &game_state).2,
+            bidirectional(&nav_grid, &start, &end, &game_state).2,
+        ];
+        for path in paths {
+            let path = path.unwrap();
+            assert_connected(&nav_grid, &path, &start, &end);
+            assert_eq!(path.steps[0].kind, StepKind::Teleport);
+            assert_eq!(path.cost, 5 + 3);
+        }
+    }
+
+    #[test]
+    fn paths_go_around_wall_corners() {
+        // L-shaped wall with the start in its south east corner
+        //   4 . . . . .
+        //   3 . E . . .
+        //   2 . . # # .
+        //   1 . . # S .
+        //   0 . . . . .
+        //     0 1 2 3 4
+        let nav_grid = field(area(c(0, 0), c(4, 4)), &[c(2, 1), c(2, 2), c(3, 2)]).build();
+        let game_state = GameState::default();
+        let (start, end) = (c(3, 1), c(1, 3));
+        // Cutting the corners of the wall would take 4 steps, (3, 1) -> (4, 2) -> (3, 3) -> (2, 3) -> (1, 3)
+        let paths = [
+            dijkstra(&nav_grid, &start, &end, &game_state).2,
+            astar(&nav_grid, &start, &end, &game_state).2,
+            bidirectional(&nav_grid, &start, &end, &game_state).2,
+        ];
+        for path in paths {
+            let path = path.unwrap();
+            assert_connected(&nav_grid, &path, &start, &end);
+            assert_eq!(path.cost, 6);
+        }
+    }
+
+    #[test]
+    fn blocked_tiles_force_a_detour() {
+        // Wall with gaps at its south and north ends, the south gap is next to the start and end
+        let walls: Vec<Coordinate> = (1..=5).map(|y| c(5, y)).collect();
+        let nav_grid = field(area(c(0, 0), c(10, 6)), &walls).build();
+        let game_state = GameState::default();
+        let (start, end) = (c(2, 1), c(8, 1));
+        let (_, _, direct) = dijkstra(&nav_grid, &start, &end, &game_state);
+        let direct = direct.unwrap();
+        assert!(direct.steps.iter().any(|step| step.to == c(5, 0)));
+        let options = SearchOptions { blocked: vec![c(5, 0)], ..SearchOptions::default() };
+        let paths = [
+            dijkstra_with_options(&nav_grid, &start, &end, &game_state, &options).2,
+            astar_with_options(&nav_grid, &start, &end, &game_state, &options).2,
+            bidirectional_with_options(&nav_grid, &start, &end, &game_state, &options).2,
+        ];
+        for path in paths {
+            let path = path.unwrap().unwrap();
+            assert_connected(&nav_grid, &path, &start, &end);
+            assert!(path.steps.iter().all(|step| step.to != c(5, 0)), "{:?}", path);
+            assert!(path.steps.iter().any(|step| step.to == c(5, 6)), "{:?}", path);
+            assert!(path.cost > direct.cost);
+        }
+    }
+
+    #[test]
+    fn k_shortest_finds_both_corridors_in_order() {
+        // Parallel corridors between two halls, the northern one is longer
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(0, 6)))
+            .walkable_area(&area(c(10, 0), c(10, 6)))
+            .walkable_area(&area(c(1, 0), c(9, 0)))
+            .walkable_area(&area(c(1, 6), c(9, 6)))
+            .build();
+        let game_state = GameState::default();
+        let (start, end) = (c(0, 2), c(10, 2));
+        let (_, _, paths) = k_shortest(&nav_grid, &start, &end, &game_state, 2);
+        assert_eq!(paths.len(), 2);
+        for path in &paths {
+            assert_connected(&nav_grid, path, &start, &end);
+        }
+        assert!(paths[1].cost >= paths[0].cost);
+        assert!(paths[0].steps.iter().any(|step| step.to.y == 0 && (1..=9).contains(&step.to.x)));
+        assert!(paths[1].steps.iter().any(|step| step.to.y == 6 && (1..=9).contains(&step.to.x)));
+        assert_eq!(paths[0].cost, astar(&nav_grid, &start, &end, &game_state).2.unwrap().cost);
+    }
+
+    #[test]
+    fn floods_give_up_once_the_budget_is_exceeded() {
+        let nav_grid = NavGrid::builder().walkable_area(&area(c(0, 0), c(9, 9))).build();
+        let mut reachable = 0;
+        let (_, _, result) = flood_with_options(&nav_grid, &c(0, 0), &SearchOptions::default(), |_, _| {
+            reachable += 1;
+            true
+        });
+        assert!(result.is_ok());
+        assert_eq!(reachable, 100);
+
+        // Diagonal steps cost 1 as well, so the 4x4 tiles in the corner are within 3
+        let mut within = 0;
+        let (_, _, result) = flood_with_options(&nav_grid, &c(0, 0), &SearchOptions { max_cost: Some(3), ..SearchOptions::default() }, |_, _| {
+            within += 1;
+            true
+        });
+        assert!(result.is_ok());
+        assert_eq!(within, 16);
+
+        let (visited, _, result) = flood_with_options(&nav_grid, &c(0, 0), &SearchOptions { max_visited: Some(10), ..SearchOptions::default() }, |_, _| true);
+        assert!(result.is_err());
+        assert_eq!(visited, 10);
+    }
+
+    #[test]
+    fn floods_of_the_available_area_give_up_once_the_budget_is_exceeded() {
+        let nav_grid = NavGrid::builder().walkable_area(&area(c(0, 0), c(9, 9))).build();
+        let mut within = 0;
+        let options = SearchOptions { max_cost: Some(3), ..SearchOptions::default() };
+        let (_, _, result) = flood_available_with_options(&nav_grid, &c(0, 0), &GameState::default(), &options, |_, _| {
+            within += 1;
+            true
+        });
+        assert!(result.is_ok());
+        assert_eq!(within, 16);
+
+        let options = SearchOptions { max_visited: Some(10), ..SearchOptions::default() };
+        let (visited, _, result) = flood_available_with_options(&nav_grid, &c(0, 0), &GameState::default(), &options, |_, _| true);
+        assert_eq!(result, Err(Aborted::BudgetExceeded));
+        assert_eq!(visited, 10);
+    }
+
+    #[test]
+    fn contexts_can_be_reused_on_another_grid() {
+        let game_state = GameState::default();
+        let mut context = SearchContext::default();
+        {
+            let (nav_grid, start, end) = winding_corridor();
+            let expected = cost(dijkstra(&nav_grid, &start, &end, &game_state).2);
+            assert_eq!(cost(dijkstra_with_context(&mut context, &nav_grid, &start, &end, &game_state, &SearchOptions::default()).2.unwrap()), expected);
+        }
+        for (nav_grid, start, end) in fixtures() {
+            let expected = cost(dijkstra(&nav_grid, &start, &end, &game_state).2);
+            assert_eq!(cost(dijkstra_with_context(&mut context, &nav_grid, &start, &end, &game_state, &SearchOptions::default()).2.unwrap()), expected);
+            assert_eq!(cost(astar_with_context(&mut context, &nav_grid, &start, &end, &game_state, &SearchOptions::default()).2.unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn reachability_checks_respect_the_budget() {
+        let (nav_grid, start, end) = winding_corridor();
+        let game_state = GameState::default();
+        assert_eq!(is_reachable_with_options(&nav_grid, &start, &end, &game_state, &SearchOptions::default()), Ok(true));
+        let options = SearchOptions { max_visited: Some(5), ..SearchOptions::default() };
+        assert_eq!(is_reachable_with_options(&nav_grid, &start, &end, &game_state, &options), Err(Aborted::BudgetExceeded));
+    }
+
+    #[test]
+    fn cancelled_searches_are_not_reported_as_exceeding_the_budget() {
+        let (nav_grid, start, end) = winding_corridor();
+        let game_state = GameState::default();
+        let options = SearchOptions { cancelled: Some(Arc::new(AtomicBool::new(true))), ..SearchOptions::default() };
+        assert_eq!(dijkstra_with_options(&nav_grid, &start, &end, &game_state, &options).2.err(), Some(Aborted::Cancelled));
+        assert_eq!(astar_with_options(&nav_grid, &start, &end, &game_state, &options).2.err(), Some(Aborted::Cancelled));
+        assert_eq!(bidirectional_with_options(&nav_grid, &start, &end, &game_state, &options).2.err(), Some(Aborted::Cancelled));
+        assert!(matches!(astar_with_fallback(&mut SearchContext::default(), &nav_grid, &start, &end, &game_state, &options).2, SearchOutcome::Cancelled));
+    }
+
+    /// Steps of a path one per line, so changed routes show up line by line in the diff of a golden file
+    fn describe(path: &Option<Path>) -> String {
+        let path = match path {
+            Some(path) => path,
+            None => return "unreachable\n".to_string(),
+        };
+        let mut description = format!("cost {}\n", path.cost);
+        for step in &path.steps {
+            let via = match &step.definition {
+                EdgeDefinition::Step { .. } => "walk".to_string(),
+                EdgeDefinition::Door { id, .. } => format!("door {}", id),
+                EdgeDefinition::GameObject { id, .. } => format!("object {}", id),
+                EdgeDefinition::Npc { id, .. } => format!("npc {}", id),
+                EdgeDefinition::SpellTeleport { spell } => spell.clone(),
+                EdgeDefinition::ItemTeleport { item, .. } => item.as_str().to_string(),
+            };
+            description.push_str(&format!("{} -> {} {} {}\n", step.from, step.to, via, step.cost));
+        }
+        description
+    }
+
+    /// Paths of every search on the fixtures, compared to the files in `tests/golden`. Paths only change if tie-breaking
+    /// or the searches do, which has to be reviewed in the diff of the files. Run with `UPDATE_GOLDEN=1` to rewrite them.
+    #[test]
+    fn paths_match_the_golden_files() {
+        let mut fixtures: Vec<(&str, NavGrid, Coordinate, Coordinate)> = ["open_field", "wall_with_gap", "rooms_with_door", "winding_corridor"]
+            .into_iter()
+            .zip(fixtures())
+            .map(|(name, (nav_grid, start, end))| (name, nav_grid, start, end))
+            .collect();
+        let mut islands = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(5, 5)))
+            .walkable_area(&area(c(20, 0), c(25, 5)))
+            .teleport(teleport("Varrock Teleport", c(21, 1), 5))
+            .build();
+        par_label_groups(&mut islands, |_| true);
+        fixtures.push(("teleport_to_island", islands, c(2, 2), c(24, 4)));
+
+        let game_state = GameState::default();
+        let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        for (name, nav_grid, start, end) in fixtures {
+            let searches = [
+                ("dijkstra", dijkstra(&nav_grid, &start, &end, &game_state).2),
+                ("astar", astar(&nav_grid, &start, &end, &game_state).2),
+                ("bidirectional", bidirectional(&nav_grid, &start, &end, &game_state).2),
+            ];
+            let actual: String = searches.iter().map(|(search, path)| format!("# {} {} -> {}\n{}", search, start, end, describe(path))).collect();
+            let file = directory.join(format!("{}.txt", name));
+            if update {
+                std::fs::create_dir_all(&directory).unwrap();
+                std::fs::write(&file, &actual).unwrap();
+            }
+            let expected = std::fs::read_to_string(&file).unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+            assert_eq!(actual, expected, "Paths of {} changed", name);
+        }
+    }
+
+    #[test]
+    fn edge_steps_report_the_tiles_they_are_entered_and_left_at() {
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(9, 9)))
+            .walkable_area(&area(c(11, 0), c(19, 9)))
+            .walkable_area(&area(c(30, 0), c(35, 5)))
+            .edge(c(9, 5), door(1, c(10, 5), c(11, 5)))
+            .edge(c(11, 5), door(1, c(10, 5), c(9, 5)))
+            .teleport(teleport("Varrock Teleport", c(32, 2), 5))
+            .build();
+        let searches: [Search; 3] = [dijkstra, astar, bidirectional];
+        for search in searches {
+            let path = search(&nav_grid, &c(7, 5), &c(13, 5), &GameState::default()).2.unwrap();
+            let door = path.steps.iter().find(|step| step.kind == StepKind::Edge).unwrap();
+            assert_eq!((door.from, door.to), (c(9, 5), c(11, 5)));
+            let path = search(&nav_grid, &c(2, 2), &c(32, 3), &GameState::default()).2.unwrap();
+            assert_eq!(path.steps[0].kind, StepKind::Teleport);
+            assert_eq!((path.steps[0].from, path.steps[0].to), (c(2, 2), c(32, 2)));
+        }
+    }
+
+    #[test]
+    fn paths_to_objects_end_next_to_them() {
+        // The object stands in the middle of the field, its tile can't be walked on
+        let object = c(5, 5);
+        let nav_grid = field(area(c(0, 0), c(10, 10)), &[object]).build();
+        let path = dijkstra_adjacent(&nav_grid, &c(0, 5), &object, &GameState::default()).2.unwrap();
+        let end = path.steps.last().unwrap().to;
+        assert_connected(&nav_grid, &path, &c(0, 5), &end);
+        assert_eq!(path.cost, 4);
+        assert!(end.x.abs_diff(object.x) <= 1 && end.y.abs_diff(object.y) <= 1 && end != object, "{} isn't next to {}", end, object);
+        let path = dijkstra_adjacent(&nav_grid, &c(6, 6), &object, &GameState::default()).2.unwrap();
+        assert!(path.steps.is_empty());
+        assert_eq!(path.cost, 0);
+    }
+
+    #[test]
+    fn tick_timelines_take_as_long_as_walking_or_running_the_path() {
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(9, 9)))
+            .walkable_area(&area(c(11, 0), c(19, 9)))
+            .edge(c(9, 5), door(1, c(10, 5), c(11, 5)))
+            .build();
+        // Five tiles up to the door, which takes two ticks, and three tiles past it
+        let (start, end) = (c(4, 5), c(14, 5));
+        let path = astar(&nav_grid, &start, &end, &GameState::default()).2.unwrap();
+        for (movement_mode, ticks) in [(MovementMode::Walk, 5 + 2 + 3), (MovementMode::Run, 3 + 2 + 2)] {
+            let timeline = path_to_tick_timeline(&path.steps, movement_mode);
+            assert_eq!(timeline.len(), ticks + 1, "{:?}", movement_mode);
+            assert!(timeline.iter().enumerate().all(|(i, (tick, _))| *tick as usize == i), "{:?}", timeline);
+            assert_eq!(timeline[0].1, start);
+            assert_eq!(timeline[ticks].1, end);
+        }
+        assert_eq!(path_to_tick_timeline(&path.steps, MovementMode::Walk).len() as u32, path.cost + 1);
+        assert!(path_to_tick_timeline(&[], MovementMode::Run).is_empty());
+    }
+
+    #[test]
+    fn strict_consumables_reject_routes_using_more_items_than_carried() {
+        // Teleporting to the second island and crossing to the third each cost a tab
+        let tab = || RequirementDefinition::Item { item: Regex::new("Varrock teleport").unwrap(), quantity: 1, consumed: true };
+        let mut varrock = teleport("Varrock Teleport", c(21, 1), 5);
+        varrock.requirements.push(tab());
+        let mut ferry = door(1, c(25, 2), c(41, 2));
+        ferry.requirements.push(tab());
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(5, 5)))
+            .walkable_area(&area(c(20, 0), c(25, 5)))
+            .walkable_area(&area(c(40, 0), c(45, 5)))
+            .teleport(varrock)
+            .edge(c(25, 2), ferry)
+            .build();
+        let (start, end) = (c(2, 2), c(44, 2));
+        let one_tab = GameState::builder().item("Varrock teleport", 1).build();
+        let two_tabs = GameState::builder().item("Varrock teleport", 2).build();
+        let strict = SearchOptions { strict_consumables: true, ..SearchOptions::default() };
+        let searches = [dijkstra_with_options, astar_with_options, bidirectional_with_options];
+        for search in searches {
+            assert!(search(&nav_grid, &start, &end, &one_tab, &SearchOptions::default()).2.unwrap().is_some());
+            assert!(search(&nav_grid, &start, &end, &one_tab, &strict).2.unwrap().is_none());
+            let path = search(&nav_grid, &start, &end, &two_tabs, &strict).2.unwrap().unwrap();
+            assert_connected(&nav_grid, &path, &start, &end);
+        }
+    }
+
+    #[test]
+    fn group_links_join_islands_only_by_their_teleport() {
+        let mut lumbridge = teleport("Lumbridge Teleport", c(2, 2), 5);
+        lumbridge.requirements.push(RequirementDefinition::Skill { skill: "MAGIC".to_string(), level: 31 });
+        let mut nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(5, 5)))
+            .walkable_area(&area(c(20, 0), c(25, 5)))
+            // A door within the first island doesn't link it to anything
+            .edge(c(1, 1), door(1, c(1, 2), c(1, 3)))
+            .teleport(teleport("Varrock Teleport", c(21, 1), 5))
+            .teleport(lumbridge)
+            .build();
+        par_label_groups(&mut nav_grid, |_| true);
+        let island = nav_grid.vertices[c(21, 1).index() as usize].get_group();
+        assert_ne!(island, nav_grid.vertices[c(2, 2).index() as usize].get_group());
+        let links = group_links(&nav_grid, &GameState::builder().skill("Magic", 25).build());
+        assert_eq!(links.len(), 1);
+        let (source, destination, edge) = links[0];
+        assert_eq!((source, destination), (None, island));
+        assert_eq!(edge.definition, EdgeDefinition::SpellTeleport { spell: "Varrock Teleport".to_string() });
+    }
+
+    #[test]
+    fn per_goal_costs_match_independent_searches() {
+        let (nav_grid, start, _) = winding_corridor();
+        let outside = Coordinate { x: u16::MAX, y: 0, plane: 0 };
+        // Goals along the corridor, on the far side of its door, on a wall, and outside of the map
+        let goals = [c(10, 0), c(20, 8), outside, c(5, 20), c(10, 5), c(20, 8)];
+        let (_, _, paths) = dijkstra_to_all(&nav_grid, &start, &goals, &GameState::default());
+        assert_eq!(paths.len(), goals.len());
+        assert!(paths[2].is_none());
+        for (goal, path) in goals.iter().zip(paths).filter(|(goal, _)| goal.validate()) {
+            assert_eq!(cost(path), cost(dijkstra(&nav_grid, &start, goal, &GameState::default()).2), "{}", goal);
+        }
+    }
+
+    #[test]
+    fn observers_see_every_expanded_vertex_in_order_of_cost() {
+        let nav_grid = NavGrid::builder().walkable_area(&area(c(0, 0), c(19, 19))).build();
+        let (start, end) = (c(5, 5), c(15, 12));
+        let mut expanded = Vec::new();
+        let (count, _, path) = dijkstra_with_observer(&nav_grid, &start, &end, &GameState::default(), &SearchOptions::default(), |index| expanded.push(index));
+        assert_eq!(cost(path.unwrap()), Some(10));
+        assert_eq!(expanded.len(), count);
+        assert_eq!(expanded.first(), Some(&start.index()));
+        assert_eq!(expanded.last(), Some(&end.index()));
+        assert_eq!(expanded.iter().collect::<HashSet<_>>().len(), count, "Vertices are expanded once");
+        // Walking costs one per tile in any direction, so the cost of a tile in the open field is its distance
+        let distances: Vec<u16> = expanded.iter().map(|index| Coordinate::from_index(*index)).map(|c| c.x.abs_diff(start.x).max(c.y.abs_diff(start.y))).collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", distances);
+    }
+
+    #[test]
+    fn weighted_astar_costs_at_most_its_weight_times_the_optimum() {
+        for (nav_grid, start, end) in fixtures() {
+            let optimum = dijkstra(&nav_grid, &start, &end, &GameState::default()).2.unwrap().cost;
+            for weight in [1.0, 1.5, 2.0, 4.0] {
+                let path = weighted_astar(&nav_grid, &start, &end, &GameState::default(), weight).2.unwrap();
+                assert_connected(&nav_grid, &path, &start, &end);
+                assert!(path.cost as f32 <= optimum as f32 * weight, "{} -> {} costs {} at weight {}, the optimum is {}", start, end, path.cost, weight, optimum);
+            }
+        }
+    }
+
+    #[test]
+    fn teleport_steps_serialize_under_their_own_tag() {
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(5, 5)))
+            .walkable_area(&area(c(20, 0), c(25, 5)))
+            .teleport(teleport("Varrock Teleport", c(21, 1), 5))
+            .build();
+        let path = astar(&nav_grid, &c(2, 2), &c(22, 1), &GameState::default()).2.unwrap();
+        let steps = serde_json::to_value(&path.steps).unwrap();
+        assert_eq!(steps[0]["kind"], "Teleport");
+        assert_eq!(steps[0]["type"], "SpellTeleport");
+        assert_eq!(steps[0]["spell"], "Varrock Teleport");
+        assert_eq!(steps[1]["kind"], "Walk");
+        assert_eq!(steps[1]["type"], "Step");
+    }
+
+    fn bank(position: Coordinate) -> PointOfInterest {
+        PointOfInterest { kind: PoiKind::Bank, position, name: None }
+    }
+
+    #[test]
+    fn round_trips_pass_the_bank_minimizing_the_whole_loop() {
+        // The west bank is nearest to the start, but the east bank lies on the way to the target
+        let nav_grid = field(area(c(0, 0), c(30, 3)), &[])
+            .point_of_interest(bank(c(0, 1)))
+            .point_of_interest(bank(c(25, 1)))
+            .build();
+        let (start, target) = (c(10, 1), c(28, 1));
+        let (game_state, options) = (GameState::default(), SearchOptions::default());
+        let (nearest, path) = nearest_bank(&nav_grid, &start, &game_state, &options).2.unwrap().unwrap();
+        assert_eq!((nearest.position, path.cost), (c(0, 1), 10));
+        assert_connected(&nav_grid, &path, &start, &c(0, 1));
+
+        let trip = round_trip(&nav_grid, &start, &target, None, &game_state, &options).2.unwrap().unwrap();
+        assert_eq!((trip.bank.position, trip.cost), (c(25, 1), 15 + 3));
+        assert_connected(&nav_grid, &trip.to_bank, &start, &c(25, 1));
+        assert_connected(&nav_grid, &trip.from_bank, &c(25, 1), &target);
+
+        let trip = round_trip(&nav_grid, &start, &target, Some(&c(0, 1)), &game_state, &options).2.unwrap().unwrap();
+        assert_eq!((trip.bank.position, trip.cost), (c(0, 1), 10 + 28));
+    }
+
+    #[test]
+    fn points_of_interest_are_sorted_by_the_cost_of_reaching_them() {
+        // The island bank is farthest away, but the teleport makes it the cheapest. The walled in bank can't be reached.
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(10, 3)))
+            .walkable_area(&area(c(20, 0), c(22, 3)))
+            .walkable_area(&area(c(40, 0), c(45, 3)))
+            .teleport(teleport("Island Teleport", c(41, 1), 3))
+            .point_of_interest(bank(c(10, 1)))
+            .point_of_interest(bank(c(21, 1)))
+            .point_of_interest(bank(c(42, 1)))
+            .build();
+        let (_, _, by_cost) = points_of_interest_by_cost(&nav_grid, &c(0, 1), PoiKind::Bank, &GameState::default(), &SearchOptions::default());
+        let by_cost: Vec<(Coordinate, u32)> = by_cost.unwrap().into_iter().map(|(bank, cost)| (bank.position, cost)).collect();
+        assert_eq!(by_cost, [(c(42, 1), 3 + 1), (c(10, 1), 10)]);
+    }
+
+    #[test]
+    fn cost_matrices_hold_the_cost_of_the_cheapest_path_of_each_pair() {
+        let (nav_grid, start, end) = winding_corridor();
+        let game_state = GameState::default();
+        let sources = [start, c(20, 10), end];
+        // A tile off the corridor and a coordinate outside of the map can't be reached
+        let destinations = [end, c(20, 0), c(10, 5), Coordinate { x: WIDTH as u16, y: 0, plane: 0 }, start];
+        let (_, _, matrix) = cost_matrix(&nav_grid, &sources, &destinations, &game_state, &SearchOptions::default());
+        let matrix = matrix.unwrap();
+        assert_eq!(matrix.len(), sources.len());
+        for (source, row) in sources.iter().zip(&matrix) {
+            let expected: Vec<Option<u32>> = destinations.iter()
+                .map(|destination| if destination.validate() { cost(dijkstra(&nav_grid, source, destination, &game_state).2) } else { None })
+                .collect();
+            assert_eq!(*row, expected, "from {}", source);
+            assert_eq!(row[2..4], [None, None]);
+        }
+        assert_eq!(matrix[0][4], Some(0));
+    }
+
+    #[test]
+    fn searches_out_of_budget_fall_back_to_the_path_to_the_closest_tile() {
+        let (nav_grid, start, end) = winding_corridor();
+        let game_state = GameState::default();
+        let mut context = SearchContext::default();
+        let (_, _, outcome) = astar_with_fallback(&mut context, &nav_grid, &start, &end, &game_state, &SearchOptions::default());
+        assert!(matches!(outcome, SearchOutcome::Found(path) if path.cost == cost(dijkstra(&nav_grid, &start, &end, &game_state).2).unwrap()));
+
+        let options = SearchOptions { max_visited: Some(30), ..SearchOptions::default() };
+        let (_, _, outcome) = astar_with_fallback(&mut context, &nav_grid, &start, &end, &game_state, &options);
+        let SearchOutcome::Budget { best_partial_path, reached } = outcome else { panic!("{:?}", outcome) };
+        assert_connected(&nav_grid, &best_partial_path, &start, &reached);
+        assert!(chebyshev(&reached, &end) < chebyshev(&start, &end), "{}", reached);
+
+        // Nothing but the start is expanded, so there is no way to go
+        let options = SearchOptions { max_visited: Some(1), ..SearchOptions::default() };
+        let (_, _, outcome) = astar_with_fallback(&mut context, &nav_grid, &start, &end, &game_state, &options);
+        assert!(matches!(outcome, SearchOutcome::Budget { best_partial_path, reached } if best_partial_path.steps.is_empty() && reached == start));
+    }
+
+    #[test]
+    fn teleports_are_only_cast_if_their_requirements_are_met() {
+        // The rooms aren't connected, the end can only be reached by teleporting
+        let mut varrock = teleport("Varrock Teleport", c(21, 1), 5);
+        varrock.requirements.push(RequirementDefinition::Skill { skill: "MAGIC".to_string(), level: 25 });
+        let mut nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(5, 5)))
+            .walkable_area(&area(c(20, 0), c(25, 5)))
+            .teleport(varrock)
+            .build();
+        par_label_groups(&mut nav_grid, |_| true);
+        let (start, end) = (c(2, 2), c(24, 4));
+        let novice = GameState::builder().skill("Magic", 24).build();
+        let mage = GameState::builder().skill("Magic", 25).build();
+        let searches: [Search; 3] = [dijkstra, astar, bidirectional];
+        for search in searches {
+            assert!(search(&nav_grid, &start, &end, &novice).2.is_none());
+            assert_eq!(search(&nav_grid, &start, &end, &mage).2.unwrap().steps[0].kind, StepKind::Teleport);
+        }
+        let options = SearchOptions { ignore_requirements: true, ..SearchOptions::default() };
+        assert_eq!(cost(astar_with_options(&nav_grid, &start, &end, &novice, &options).2.unwrap()), Some(5 + 3));
+    }
+
+    #[test]
+    fn comparisons_report_the_savings_and_edges_of_unlocked_teleports() {
+        let mut varrock = teleport("Varrock Teleport", c(28, 1), 5);
+        varrock.requirements.push(RequirementDefinition::Skill { skill: "MAGIC".to_string(), level: 25 });
+        let nav_grid = field(area(c(0, 0), c(30, 3)), &[]).teleport(varrock).build();
+        let (start, end) = (c(0, 1), c(29, 1));
+        let novice = GameState::builder().skill("Magic", 24).build();
+        let mage = GameState::builder().skill("Magic", 25).build();
+        let comparison = compare(&nav_grid, &start, &end, &novice, &mage, &SearchOptions::default()).2.unwrap();
+        assert_eq!(cost(comparison.a), Some(29));
+        assert_eq!(cost(comparison.b), Some(5 + 1));
+        assert_eq!(comparison.savings, Some(29 - 6));
+        assert!(comparison.only_a.is_empty());
+        assert_eq!(comparison.only_b.len(), 1);
+        assert_eq!(comparison.only_b[0].definition, EdgeDefinition::SpellTeleport { spell: "Varrock Teleport".to_string() });
+        let comparison = compare(&nav_grid, &start, &end, &mage, &novice, &SearchOptions::default()).2.unwrap();
+        assert_eq!(comparison.savings, Some(6 - 29));
+        assert_eq!(comparison.only_a.len(), 1);
+        assert!(comparison.only_b.is_empty());
+    }
+
+    #[test]
+    fn gated_edges_are_only_taken_if_their_requirements_are_met() {
+        // Rooms split by a wall with a gap at its east end, and a shortcut through it that requires Agility
+        let mut wall = door(1, c(5, 2), c(5, 3));
+        wall.requirements.push(RequirementDefinition::Skill { skill: "AGILITY".to_string(), level: 50 });
+        let mut back = door(1, c(5, 2), c(5, 1));
+        back.requirements = wall.requirements.clone();
+        let nav_grid = field(area(c(0, 0), c(10, 3)), &[c(0, 2), c(1, 2), c(2, 2), c(3, 2), c(4, 2), c(5, 2), c(6, 2), c(7, 2), c(8, 2), c(9, 2)])
+            .edge(c(5, 1), wall)
+            .edge(c(5, 3), back)
+            .build();
+        let (start, end) = (c(5, 1), c(5, 3));
+        let novice = GameState::builder().skill("Agility", 49).build();
+        let agile = GameState::builder().skill("Agility", 50).build();
+        let searches: [Search; 3] = [dijkstra, astar, bidirectional];
+        for search in searches {
+            let walked = search(&nav_grid, &start, &end, &novice).2.unwrap();
+            assert_connected(&nav_grid, &walked, &start, &end);
+            // Diagonal steps past the end of the wall are blocked by its corner
+            assert!(walked.steps.iter().all(Step::is_walk));
+            assert_eq!(walked.cost, 5 + 1 + 1 + 5);
+            let climbed = search(&nav_grid, &start, &end, &agile).2.unwrap();
+            assert_eq!(climbed.steps.len(), 1);
+            assert_eq!(climbed.cost, 2);
+        }
+    }
+
+    #[test]
+    fn instanced_teleports_land_on_the_plane_of_the_instance() {
+        // The same island on the surface and in an instance on the plane above, neither connected to the start
+        let mut arena = teleport("Arena Teleport", c(21, 1), 5);
+        arena.instance_plane = Some(PlaneOffset::Varbit { index: 4 });
+        let instance = Area { min: Coordinate { x: 20, y: 0, plane: 1 }, max: Coordinate { x: 25, y: 5, plane: 1 } };
+        let nav_grid = NavGrid::builder()
+            .walkable_area(&area(c(0, 0), c(5, 5)))
+            .walkable_area(&area(c(20, 0), c(25, 5)))
+            .walkable_area(&instance)
+            .teleport(arena)
+            .build();
+        let start = c(2, 2);
+        let (surface_end, instance_end) = (c(24, 4), Coordinate { x: 24, y: 4, plane: 1 });
+        let surface = GameState::builder().varbit(4, 0).build();
+        let instanced = GameState::builder().varbit(4, 1).build();
+        let searches: [Search; 3] = [dijkstra, astar, bidirectional];
+        for search in searches {
+            let path = search(&nav_grid, &start, &surface_end, &surface).2.unwrap();
+            assert_eq!(path.steps[0].to, c(21, 1));
+            assert!(search(&nav_grid, &start, &instance_end, &surface).2.is_none());
+            let path = search(&nav_grid, &start, &instance_end, &instanced).2.unwrap();
+            assert_connected(&nav_grid, &path, &start, &instance_end);
+            assert_eq!(path.steps[0].to, Coordinate { x: 21, y: 1, plane: 1 });
+            assert!(search(&nav_grid, &start, &surface_end, &instanced).2.is_none());
         }
     }
 }
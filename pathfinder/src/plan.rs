@@ -0,0 +1,54 @@
+use serde::Serialize;
+
+use model::{Area, Coordinate};
+use model::definitions::EdgeDefinition;
+
+use crate::{Step, StepKind};
+
+/// What a client has to do next to follow a path
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "instruction")]
+pub enum Instruction {
+    /// Walk from `from` to `destination`, without any interaction on the way. The cost is the number of tiles walked.
+    WalkTo { from: Coordinate, destination: Coordinate, cost: u32 },
+    /// Interact with the object or NPC of the definition at `from`, which leads to `to`
+    Interact {
+        #[serde(flatten)]
+        definition: EdgeDefinition,
+        from: Coordinate,
+        to: Coordinate,
+        cost: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        arrival: Option<Area>,
+    },
+    /// Cast the spell or use the item of the definition, which leads to `to`
+    Teleport {
+        #[serde(flatten)]
+        definition: EdgeDefinition,
+        to: Coordinate,
+        cost: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        arrival: Option<Area>,
+    },
+}
+
+/// Turns the steps of a path into instructions, grouping consecutive walking steps into a single [Instruction::WalkTo]
+/// to the tile the last of them ends on
+pub fn to_plan(steps: &[Step]) -> Vec<Instruction> {
+    let mut plan: Vec<Instruction> = Vec::new();
+    for step in steps {
+        match step.kind {
+            StepKind::Walk => match plan.last_mut() {
+                Some(Instruction::WalkTo { destination, cost, .. }) => {
+                    *destination = step.to;
+                    *cost += step.cost;
+                }
+                _ => plan.push(Instruction::WalkTo { from: step.from, destination: step.to, cost: step.cost }),
+            },
+            StepKind::Edge => plan.push(Instruction::Interact { definition: step.definition.clone(), from: step.from, to: step.to, cost: step.cost, arrival: step.arrival }),
+            StepKind::Teleport => plan.push(Instruction::Teleport { definition: step.definition.clone(), to: step.to, cost: step.cost, arrival: step.arrival }),
+        }
+    }
+    plan
+}
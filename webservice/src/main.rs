@@ -1,102 +1,1910 @@
 #[macro_use]
 extern crate rocket;
 
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use clap::Parser;
+use clap::{ArgEnum, Parser};
 use expect_exit::ExpectedWithError;
-use flate2::read::GzDecoder;
-use rocket::{Build, Rocket, State};
-use rocket::response::status::BadRequest;
-use rocket::serde::json::Json;
+use lru::LruCache;
+use rayon::prelude::*;
+use rocket::{Build, Data, Response, Rocket, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::Responder;
+use rocket::response::content::{RawHtml, RawJson};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::{json, Json, Value};
+use rocket::futures::{SinkExt, StreamExt};
 use rocket_prometheus::PrometheusMetrics;
+use rocket::tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use rocket_prometheus::prometheus::{exponential_buckets, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry};
+use rocket_ws::{Channel, Message, WebSocket};
+use rocket_ws::stream::DuplexStream;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
 
-use model::{Coordinate, NavGrid};
-use model::definitions::{EdgeDefinition, GameState, RequirementDefinition};
+use model::{Area, Coordinate, Diagnostic, NavGrid, PoiKind, PointOfInterest};
+use model::util::NavGridError;
+use model::definitions::{DataSelection, EdgeDefinition, GameState};
+use pathfinder::{Aborted, Alternative, Comparison, PathResult, RoundTrip, SearchContext, SearchOptions, SearchOutcome, Step};
+use pathfinder::ch::ContractionHierarchy;
+use pathfinder::hpa::RegionGraph;
+use pathfinder::plan::Instruction;
+
+use crate::openapi::OpenApi;
+
+mod openapi;
 
 #[derive(Parser)]
 struct Options {
-    /// Path to NavGrid file
-    #[clap(short, long)]
-    navgrid: PathBuf,
+    /// Path to NavGrid file, as NAME=PATH to serve multiple grids selected by name. The first one is the default
+    #[clap(short, long, required = true, parse(try_from_str = parse_grid_source))]
+    navgrid: Vec<(String, PathBuf)>,
+    /// Log searches taking longer than this many milliseconds as warnings
+    #[clap(long)]
+    slow_query_ms: Option<u64>,
+    /// Abort searches after expanding this many vertices
+    #[clap(long)]
+    max_visited: Option<u32>,
+    /// Abort searches after allocating this many megabytes
+    #[clap(long)]
+    max_search_memory_mb: Option<usize>,
+    /// Abort searches after running for this many milliseconds
+    #[clap(long)]
+    max_search_ms: Option<u64>,
+    /// Run at most this many searches at once, further requests wait for a running search to finish
+    #[clap(long)]
+    max_concurrent_searches: Option<usize>,
+    /// Reject requests with 429 Too Many Requests while this many requests are waiting
+    #[clap(long, default_value = "64")]
+    max_queued_searches: usize,
+    /// Cache the responses of this many recent path requests
+    #[clap(long)]
+    cache_size: Option<usize>,
+    /// Discard cached responses after this many seconds
+    #[clap(long, default_value = "300")]
+    cache_ttl_secs: u64,
+    /// Log filter, a level like `debug` or directives like `info,rocket=warn`
+    #[clap(long, default_value = "info")]
+    log_level: String,
+    /// Log as human readable text or as one JSON object per line for log ingestion
+    #[clap(long, arg_enum, default_value = "text")]
+    log_format: LogFormat,
+    /// JSON file with the API keys allowed to use the service, read from OSRS_NAV_API_KEYS if absent. Without keys,
+    /// the service is open to everyone
+    #[clap(long)]
+    api_keys: Option<PathBuf>,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+fn init_logging(filter: &str, format: LogFormat) {
+    let subscriber = tracing_subscriber::fmt().with_env_filter(EnvFilter::new(filter));
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+}
+
+/// Parses `NAME=PATH`, or a plain `PATH` of a grid named `default`
+fn parse_grid_source(source: &str) -> Result<(String, PathBuf), String> {
+    match source.split_once('=') {
+        Some(("", _)) => Err("Missing grid name".to_string()),
+        Some((name, path)) => Ok((name.to_string(), PathBuf::from(path))),
+        None => Ok(("default".to_string(), PathBuf::from(source))),
+    }
+}
+
+/// Searches exceeding the threshold are logged as warnings, all others at debug level
+struct SlowQueryThreshold(Option<Duration>);
+
+impl SlowQueryThreshold {
+    fn is_slow(&self, duration: Duration) -> bool {
+        self.0.map_or(false, |threshold| duration > threshold)
+    }
+
+    fn log(&self, duration: Duration, message: impl FnOnce() -> String) {
+        if self.is_slow(duration) {
+            tracing::warn!("{}", message());
+        } else {
+            tracing::debug!("{}", message());
+        }
+    }
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identifier of a request and the time it spent in each phase, logged once the response is sent. Handlers enter a span
+/// carrying the identifier, so their own logs can be matched with the request.
+struct RequestTimings {
+    id: u64,
+    begin: Instant,
+    queue_wait_us: AtomicU64,
+    search_us: AtomicU64,
+    serialization_us: AtomicU64,
+}
+
+impl RequestTimings {
+    fn new() -> RequestTimings {
+        RequestTimings {
+            id: NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+            begin: Instant::now(),
+            queue_wait_us: AtomicU64::new(0),
+            search_us: AtomicU64::new(0),
+            serialization_us: AtomicU64::new(0),
+        }
+    }
+
+    fn of<'r>(request: &'r rocket::Request<'_>) -> &'r RequestTimings {
+        request.local_cache(RequestTimings::new)
+    }
+
+    fn record(counter: &AtomicU64, duration: Duration) {
+        counter.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn search(&self, duration: Duration) {
+        RequestTimings::record(&self.search_us, duration);
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r RequestTimings {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<&'r RequestTimings, ()> {
+        Outcome::Success(RequestTimings::of(request))
+    }
+}
+
+fn millis(micros: &AtomicU64) -> f64 {
+    micros.load(Ordering::Relaxed) as f64 / 1000f64
+}
+
+/// Assigns every request its [RequestTimings], returns the identifier as `X-Request-Id` and logs the request once
+/// it is answered
+struct RequestLog;
+
+#[rocket::async_trait]
+impl Fairing for RequestLog {
+    fn info(&self) -> Info {
+        Info { name: "Request log", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, request: &mut rocket::Request<'_>, _: &mut Data<'_>) {
+        RequestTimings::of(request);
+    }
+
+    async fn on_response<'r>(&self, request: &'r rocket::Request<'_>, response: &mut Response<'r>) {
+        let timings = RequestTimings::of(request);
+        response.set_header(Header::new("X-Request-Id", timings.id.to_string()));
+        tracing::info!(
+            request_id = timings.id,
+            method = %request.method(),
+            uri = %request.uri(),
+            status = response.status().code,
+            duration_ms = (Instant::now() - timings.begin).as_secs_f64() * 1000f64,
+            queue_wait_ms = millis(&timings.queue_wait_us),
+            search_ms = millis(&timings.search_us),
+            serialization_ms = millis(&timings.serialization_us),
+            "Request answered",
+        );
+    }
 }
 
+/// Responds with the inner responder, recording how long that took in the [RequestTimings], which for JSON responses
+/// is the time spent serializing
+struct Timed<R>(R);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Timed<R> {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        let begin = Instant::now();
+        let response = self.0.respond_to(request);
+        RequestTimings::record(&RequestTimings::of(request).serialization_us, Instant::now() - begin);
+        response
+    }
+}
+
+/// Prometheus metrics of path searches
+struct SearchMetrics {
+    duration: Histogram,
+    visited: Histogram,
+    path_steps: Histogram,
+    memory: IntGauge,
+    unreachable: IntCounter,
+    budget_exceeded: IntCounter,
+    cancelled: IntCounter,
+    teleports: IntCounterVec,
+}
+
+impl SearchMetrics {
+    fn new(registry: &Registry) -> rocket_prometheus::prometheus::Result<SearchMetrics> {
+        let metrics = SearchMetrics {
+            duration: Histogram::with_opts(HistogramOpts::new("osrs_nav_search_duration_seconds", "Duration of path searches")
+                .buckets(exponential_buckets(0.0005, 2.0, 14)?))?,
+            visited: Histogram::with_opts(HistogramOpts::new("osrs_nav_search_visited_vertices", "Vertices expanded by path searches")
+                .buckets(exponential_buckets(100.0, 4.0, 10)?))?,
+            path_steps: Histogram::with_opts(HistogramOpts::new("osrs_nav_path_steps", "Steps of the paths found")
+                .buckets(exponential_buckets(1.0, 2.0, 14)?))?,
+            memory: IntGauge::new("osrs_nav_search_memory_bytes", "Memory allocated by the most recent path search")?,
+            unreachable: IntCounter::new("osrs_nav_unreachable_total", "Searches that found no path")?,
+            budget_exceeded: IntCounter::new("osrs_nav_budget_exceeded_total", "Searches aborted for exceeding the budget")?,
+            cancelled: IntCounter::new("osrs_nav_cancelled_total", "Searches cancelled because the client disconnected")?,
+            teleports: IntCounterVec::new(Opts::new("osrs_nav_teleports_total", "Teleports taken by the paths found"), &["teleport"])?,
+        };
+        registry.register(Box::new(metrics.duration.clone()))?;
+        registry.register(Box::new(metrics.visited.clone()))?;
+        registry.register(Box::new(metrics.path_steps.clone()))?;
+        registry.register(Box::new(metrics.memory.clone()))?;
+        registry.register(Box::new(metrics.unreachable.clone()))?;
+        registry.register(Box::new(metrics.budget_exceeded.clone()))?;
+        registry.register(Box::new(metrics.cancelled.clone()))?;
+        registry.register(Box::new(metrics.teleports.clone()))?;
+        Ok(metrics)
+    }
+
+    fn observe(&self, duration: Duration, visited: usize, mem_usage: usize, path: Result<Option<&pathfinder::Path>, Aborted>) {
+        self.duration.observe(duration.as_secs_f64());
+        self.visited.observe(visited as f64);
+        self.memory.set(mem_usage as i64);
+        match path {
+            Ok(Some(path)) => {
+                self.path_steps.observe(path.steps.len() as f64);
+                path.steps.iter()
+                    .filter_map(|step| teleport_name(&step.definition))
+                    .for_each(|name| self.teleports.with_label_values(&[&name]).inc());
+            }
+            Ok(None) => self.unreachable.inc(),
+            Err(Aborted::BudgetExceeded) => self.budget_exceeded.inc(),
+            Err(Aborted::Cancelled) => self.cancelled.inc(),
+        }
+    }
+}
+
+/// Label of a teleport in metrics, the spell or the item and action used
+fn teleport_name(definition: &EdgeDefinition) -> Option<String> {
+    match definition {
+        EdgeDefinition::SpellTeleport { spell } => Some(spell.clone()),
+        EdgeDefinition::ItemTeleport { item, action } => Some(format!("{} ({})", item, action)),
+        _ => None,
+    }
+}
+
+/// Upper bounds for the vertices expanded, the memory allocated and the time taken by a single search, overriding
+/// larger budgets of requests
+struct SearchBudget {
+    max_visited: Option<u32>,
+    max_memory: Option<usize>,
+    max_millis: Option<u64>,
+}
+
+impl SearchBudget {
+    fn apply(&self, options: &mut SearchOptions) {
+        options.max_visited = match (options.max_visited, self.max_visited) {
+            (Some(requested), Some(max_visited)) => Some(requested.min(max_visited)),
+            (requested, max_visited) => requested.or(max_visited),
+        };
+        options.max_memory = match (options.max_memory, self.max_memory) {
+            (Some(requested), Some(max_memory)) => Some(requested.min(max_memory)),
+            (requested, max_memory) => requested.or(max_memory),
+        };
+        options.max_millis = match (options.max_millis, self.max_millis) {
+            (Some(requested), Some(max_millis)) => Some(requested.min(max_millis)),
+            (requested, max_millis) => requested.or(max_millis),
+        };
+    }
+}
+
+/// Seconds clients are told to wait before retrying a request rejected by the [SearchLimiter]
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Bounds the number of searches running at once, since each can allocate hundreds of megabytes. Requests wait for a
+/// running search to finish, unless `max_queued` requests are waiting already.
+struct SearchLimiter {
+    permits: Option<Arc<Semaphore>>,
+    max_queued: usize,
+    queued: AtomicUsize,
+    queue_depth: IntGauge,
+}
+
+/// Rejection of a search because the [SearchLimiter] queue is full
+struct Saturated;
+
+/// Counts a request as queued until it gets its permit or is dropped while waiting
+struct Queued<'a>(&'a SearchLimiter);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::AcqRel);
+        self.0.queue_depth.dec();
+    }
+}
+
+impl SearchLimiter {
+    fn new(max_concurrent: Option<usize>, max_queued: usize, registry: &Registry) -> rocket_prometheus::prometheus::Result<SearchLimiter> {
+        let limiter = SearchLimiter {
+            permits: max_concurrent.map(|permits| Arc::new(Semaphore::new(permits))),
+            max_queued,
+            queued: AtomicUsize::new(0),
+            queue_depth: IntGauge::new("osrs_nav_search_queue_depth", "Requests waiting for a search to finish")?,
+        };
+        registry.register(Box::new(limiter.queue_depth.clone()))?;
+        Ok(limiter)
+    }
+
+    /// Waits for a permit to search, which is released once dropped. Unlimited searches don't need permits.
+    async fn acquire(&self) -> Result<Option<OwnedSemaphorePermit>, Saturated> {
+        let permits = match &self.permits {
+            Some(permits) => permits.clone(),
+            None => return Ok(None),
+        };
+        if let Ok(permit) = permits.clone().try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+        if self.queued.fetch_add(1, Ordering::AcqRel) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            return Err(Saturated);
+        }
+        self.queue_depth.inc();
+        let _queued = Queued(self);
+        // The semaphore is never closed
+        Ok(permits.acquire_owned().await.ok())
+    }
+}
+
+/// Request guard holding a [SearchLimiter] permit until the handler returns
+struct SearchPermit(Option<OwnedSemaphorePermit>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SearchPermit {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<SearchPermit, &'static str> {
+        let limiter = request.rocket().state::<SearchLimiter>().expect("SearchLimiter is managed");
+        let begin = Instant::now();
+        let acquired = limiter.acquire().await;
+        RequestTimings::record(&RequestTimings::of(request).queue_wait_us, Instant::now() - begin);
+        match acquired {
+            Ok(permit) => Outcome::Success(SearchPermit(permit)),
+            Err(Saturated) => Outcome::Failure((Status::TooManyRequests, "Too many searches in progress")),
+        }
+    }
+}
+
+/// Body of every error response
+#[derive(Serialize, JsonSchema)]
+struct ApiError {
+    /// Kind of the error, e.g. `invalid_request`, which clients can match on
+    code: &'static str,
+    /// Description of the error, which may change between versions
+    message: String,
+    /// Further information depending on the code, e.g. `retry_after` in seconds for `rate_limited`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Value>,
+}
+
+/// Error response with an [ApiError] body
+struct Failure {
+    status: Status,
+    error: ApiError,
+    headers: Vec<Header<'static>>,
+}
+
+impl Failure {
+    fn new(status: Status, code: &'static str, message: impl Into<String>) -> Failure {
+        Failure { status, error: ApiError { code, message: message.into(), details: None }, headers: Vec::new() }
+    }
+
+    fn invalid_request(reason: &str) -> Failure {
+        Failure::new(Status::BadRequest, "invalid_request", reason)
+    }
+
+    fn unknown_grid(reason: &str) -> Failure {
+        Failure::new(Status::BadRequest, "unknown_grid", reason)
+    }
+
+    fn malformed_request(reason: &str) -> Failure {
+        Failure::new(Status::BadRequest, "malformed_request", reason)
+    }
+
+    /// The search would have exceeded the [SearchBudget], though the end may well be reachable, or was cancelled
+    /// because the client disconnected, in which case the response reaches no one
+    fn aborted(aborted: Aborted) -> Failure {
+        match aborted {
+            Aborted::BudgetExceeded => Failure::new(Status::ServiceUnavailable, "budget_exceeded", "Search budget exceeded"),
+            // Client Closed Request, as nginx logs it
+            Aborted::Cancelled => Failure::new(Status::new(499), "cancelled", "Search cancelled"),
+        }
+    }
+
+    /// Tells the client to wait before retrying, in the details and the `Retry-After` header
+    fn retry_after(mut self, seconds: u64) -> Failure {
+        self.error.details = Some(json!({ "retry_after": seconds }));
+        self.header(Header::new("Retry-After", seconds.to_string()))
+    }
+
+    fn header(mut self, header: Header<'static>) -> Failure {
+        self.headers.push(header);
+        self
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Failure {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let mut response = Response::build_from(Json(self.error).respond_to(request)?);
+        response.status(self.status);
+        for header in self.headers {
+            response.header(header);
+        }
+        response.ok()
+    }
+}
+
+/// Answers requests rejected by the [SearchLimiter] or by the rate limit or quota of their [ApiKey]
+#[catch(429)]
+fn handle_too_many_requests(request: &rocket::Request<'_>) -> Failure {
+    match request.local_cache(|| None::<Denied>) {
+        Some(denied) => denied.failure(),
+        None => Failure::new(Status::TooManyRequests, "too_many_searches", "Too many searches in progress").retry_after(RETRY_AFTER_SECS),
+    }
+}
+
+#[catch(401)]
+fn handle_unauthorized(request: &rocket::Request<'_>) -> Failure {
+    request.local_cache(|| None::<Denied>).unwrap_or(Denied::Missing).failure()
+}
+
+#[catch(403)]
+fn handle_forbidden(request: &rocket::Request<'_>) -> Failure {
+    request.local_cache(|| None::<Denied>).unwrap_or(Denied::NotAdmin).failure()
+}
+
+/// Answers all other errors without a body of their own, e.g. request bodies that aren't valid JSON or don't match the
+/// expected schema
+#[catch(default)]
+fn handle_error(status: Status, _request: &rocket::Request<'_>) -> Failure {
+    let code = match status.code {
+        400 => "malformed_request",
+        404 => "not_found",
+        422 => "unprocessable",
+        500..=599 => "internal_error",
+        _ => "error",
+    };
+    Failure::new(status, code, status.reason_lossy())
+}
+
+/// Environment variable holding the API keys as JSON, if `--api-keys` isn't passed
+const API_KEYS_VAR: &str = "OSRS_NAV_API_KEYS";
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Key a client authenticates with, along with the limits of its requests
 #[derive(Deserialize)]
+struct ApiKey {
+    /// Name of the client in metrics, so the key itself isn't exposed
+    name: String,
+    key: String,
+    /// Requests allowed per minute, in bursts of up to as many requests
+    requests_per_minute: Option<u32>,
+    /// Requests allowed per UTC day
+    requests_per_day: Option<u64>,
+    /// Whether the key may use the admin routes, like `/admin/reload`
+    #[serde(default)]
+    admin: bool,
+}
+
+/// Requests of a key within its rate limit and quota
+struct KeyUsage {
+    /// Requests the key can make right away, replenished at the rate limit
+    tokens: f64,
+    refilled: Instant,
+    /// Days since the epoch of `requests_today`
+    day: u64,
+    requests_today: u64,
+}
+
+/// Why a request was rejected by [ApiKeys], kept in the request's local cache for the catchers
+#[derive(Clone, Copy)]
+enum Denied {
+    Missing,
+    Invalid,
+    RateLimited { retry_after: u64 },
+    QuotaExceeded { retry_after: u64 },
+    NotAdmin,
+}
+
+impl Denied {
+    fn failure(self) -> Failure {
+        match self {
+            Denied::Missing => Failure::new(Status::Unauthorized, "missing_api_key", "Missing API key").header(Header::new("WWW-Authenticate", "Bearer")),
+            Denied::Invalid => Failure::new(Status::Unauthorized, "invalid_api_key", "Invalid API key").header(Header::new("WWW-Authenticate", "Bearer")),
+            Denied::RateLimited { retry_after } => Failure::new(Status::TooManyRequests, "rate_limited", "Rate limit exceeded").retry_after(retry_after),
+            Denied::QuotaExceeded { retry_after } => Failure::new(Status::TooManyRequests, "quota_exceeded", "Daily quota exceeded").retry_after(retry_after),
+            Denied::NotAdmin => Failure::new(Status::Forbidden, "admin_required", "Admin API key required"),
+        }
+    }
+}
+
+/// Name of the key a request was authenticated with, kept in the request's local cache for [ApiUsage]
+struct ClientName(Option<String>);
+
+/// API keys allowed to use the service. Without keys configured, every request is allowed.
+struct ApiKeys {
+    /// Keys by their secret, `None` if authentication is disabled
+    keys: Option<BTreeMap<String, ApiKey>>,
+    usage: Mutex<BTreeMap<String, KeyUsage>>,
+    requests: IntCounterVec,
+}
+
+impl ApiKeys {
+    /// Reads the keys from the file, or from [API_KEYS_VAR] if no file is passed, a JSON array of [ApiKey]
+    fn load(path: Option<&Path>, registry: &Registry) -> Result<ApiKeys, String> {
+        let json = match path {
+            Some(path) => Some(std::fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?),
+            None => std::env::var(API_KEYS_VAR).ok(),
+        };
+        let keys = match json {
+            Some(json) => {
+                let keys: Vec<ApiKey> = rocket::serde::json::from_str(&json).map_err(|e| format!("Invalid API keys: {}", e))?;
+                Some(keys.into_iter().map(|key| (key.key.clone(), key)).collect())
+            }
+            None => None,
+        };
+        let requests = IntCounterVec::new(Opts::new("osrs_nav_api_requests_total", "Requests answered by API key and status"), &["key", "status"])
+            .map_err(|e| e.to_string())?;
+        registry.register(Box::new(requests.clone())).map_err(|e| e.to_string())?;
+        Ok(ApiKeys { keys, usage: Mutex::new(BTreeMap::new()), requests })
+    }
+
+    /// Key passed as `X-Api-Key` or `Authorization: Bearer` header, or as `api_key` query parameter for WebSockets and
+    /// event streams, which browsers open without custom headers
+    fn key_of<'r>(request: &'r rocket::Request<'_>) -> Option<&'r str> {
+        request.headers().get_one("X-Api-Key")
+            .or_else(|| request.headers().get_one("Authorization").and_then(|value| value.strip_prefix("Bearer ")))
+            .or_else(|| request.query_value::<&str>("api_key").and_then(Result::ok))
+    }
+
+    /// Looks up the key of a request and counts the request against its limits
+    fn authorize(&self, key: Option<&str>) -> Result<(), Denied> {
+        let keys = match &self.keys {
+            Some(keys) => keys,
+            None => return Ok(()),
+        };
+        let key = keys.get(key.ok_or(Denied::Missing)?).ok_or(Denied::Invalid)?;
+        let now = Instant::now();
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(key.name.clone()).or_insert_with(|| KeyUsage {
+            tokens: key.requests_per_minute.unwrap_or(0) as f64,
+            refilled: now,
+            day: since_epoch / SECONDS_PER_DAY,
+            requests_today: 0,
+        });
+        if usage.day != since_epoch / SECONDS_PER_DAY {
+            usage.day = since_epoch / SECONDS_PER_DAY;
+            usage.requests_today = 0;
+        }
+        if key.requests_per_day.map_or(false, |quota| usage.requests_today >= quota) {
+            return Err(Denied::QuotaExceeded { retry_after: SECONDS_PER_DAY - since_epoch % SECONDS_PER_DAY });
+        }
+        if let Some(per_minute) = key.requests_per_minute {
+            let per_second = per_minute as f64 / 60f64;
+            usage.tokens = (usage.tokens + (now - usage.refilled).as_secs_f64() * per_second).min(per_minute as f64);
+            usage.refilled = now;
+            if usage.tokens < 1f64 {
+                return Err(Denied::RateLimited { retry_after: ((1f64 - usage.tokens) / per_second).ceil() as u64 });
+            }
+            usage.tokens -= 1f64;
+        }
+        usage.requests_today += 1;
+        Ok(())
+    }
+}
+
+/// Request guard admitting requests with a valid API key within its limits, or any request if no keys are configured.
+/// Rejections are answered with `401 Unauthorized` or `429 Too Many Requests`.
+struct ApiClient;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiClient {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<ApiClient, &'static str> {
+        let api_keys = request.rocket().state::<ApiKeys>().expect("ApiKeys is managed");
+        let key = ApiKeys::key_of(request);
+        // Rate limited requests are still counted under their key's name
+        if let Some(key) = key.and_then(|key| api_keys.keys.as_ref()?.get(key)) {
+            request.local_cache(|| ClientName(Some(key.name.clone())));
+        }
+        match api_keys.authorize(key) {
+            Ok(_) => Outcome::Success(ApiClient),
+            Err(denied) => {
+                request.local_cache(|| Some(denied));
+                match denied {
+                    Denied::Missing => Outcome::Failure((Status::Unauthorized, "Missing API key")),
+                    Denied::Invalid => Outcome::Failure((Status::Unauthorized, "Invalid API key")),
+                    Denied::RateLimited { .. } => Outcome::Failure((Status::TooManyRequests, "Rate limit exceeded")),
+                    Denied::QuotaExceeded { .. } => Outcome::Failure((Status::TooManyRequests, "Daily quota exceeded")),
+                    Denied::NotAdmin => Outcome::Failure((Status::Forbidden, "Admin API key required")),
+                }
+            }
+        }
+    }
+}
+
+/// Request guard admitting requests like [ApiClient], if their key is marked as `admin`. Unlike other routes, admin
+/// routes aren't open to everyone without keys, so they are rejected with `403 Forbidden` unless an admin key is
+/// configured and passed.
+struct AdminClient;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminClient {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<AdminClient, &'static str> {
+        rocket::outcome::try_outcome!(ApiClient::from_request(request).await);
+        let api_keys = request.rocket().state::<ApiKeys>().expect("ApiKeys is managed");
+        let key = ApiKeys::key_of(request).and_then(|key| api_keys.keys.as_ref()?.get(key));
+        if key.map_or(false, |key| key.admin) {
+            Outcome::Success(AdminClient)
+        } else {
+            request.local_cache(|| Some(Denied::NotAdmin));
+            Outcome::Failure((Status::Forbidden, "Admin API key required"))
+        }
+    }
+}
+
+/// Counts the answered requests of each API key by status, if API keys are configured
+struct ApiUsage;
+
+#[rocket::async_trait]
+impl Fairing for ApiUsage {
+    fn info(&self) -> Info {
+        Info { name: "API usage", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r rocket::Request<'_>, response: &mut Response<'r>) {
+        let api_keys = request.rocket().state::<ApiKeys>().expect("ApiKeys is managed");
+        if api_keys.keys.is_some() {
+            let name = request.local_cache(|| ClientName(None)).0.as_deref().unwrap_or("none");
+            api_keys.requests.with_label_values(&[name, &response.status().code.to_string()]).inc();
+        }
+    }
+}
+
+/// Identifies path requests that are answered with the same response
+#[derive(Clone, Eq, PartialEq, Hash)]
+struct PathKey {
+    grid: String,
+    start: u32,
+    end: u32,
+    /// Hash of the relevant game state, options and flags of the request
+    fingerprint: u64,
+}
+
+impl PathKey {
+    /// Only the game state data the edges reachable from the start's group depend on is part of the key, so requests
+    /// differing in e.g. the varps of another continent share it
+    fn new(name: &str, grid: &Grid, request: &Request, simplify: bool, compress: bool) -> PathKey {
+        let mut hasher = DefaultHasher::new();
+        let group = grid.nav_grid.vertices[request.start.index() as usize].get_group();
+        grid.group_selections.get(&group).unwrap_or(&grid.data_selection).fingerprint(&request.game_state).hash(&mut hasher);
+        // Regexes of disabled edges don't implement Hash, their patterns are part of the debug output
+        format!("{:?}", request.options).hash(&mut hasher);
+        (request.closest, request.alternative, request.algorithm, request.plan, request.partial, simplify, compress).hash(&mut hasher);
+        PathKey { grid: name.to_string(), start: request.start.index(), end: request.end.index(), fingerprint: hasher.finish() }
+    }
+}
+
+/// Responses of recent path requests, so clients asking for the same path again skip the search.
+/// Disabled unless `--cache-size` is passed, cleared whenever a grid is reloaded.
+struct PathCache {
+    entries: Option<Mutex<LruCache<PathKey, (Instant, Option<PathResponse>)>>>,
+    ttl: Duration,
+    hits: IntCounter,
+    misses: IntCounter,
+    hit_ratio: Gauge,
+}
+
+impl PathCache {
+    fn new(size: Option<usize>, ttl: Duration, registry: &Registry) -> rocket_prometheus::prometheus::Result<PathCache> {
+        let cache = PathCache {
+            entries: size.filter(|size| *size > 0).map(|size| Mutex::new(LruCache::new(size))),
+            ttl,
+            hits: IntCounter::new("osrs_nav_path_cache_hits_total", "Path requests answered from the cache")?,
+            misses: IntCounter::new("osrs_nav_path_cache_misses_total", "Path requests not found in the cache")?,
+            hit_ratio: Gauge::new("osrs_nav_path_cache_hit_ratio", "Share of path requests answered from the cache")?,
+        };
+        registry.register(Box::new(cache.hits.clone()))?;
+        registry.register(Box::new(cache.misses.clone()))?;
+        registry.register(Box::new(cache.hit_ratio.clone()))?;
+        Ok(cache)
+    }
+
+    fn get(&self, key: &PathKey) -> Option<Option<PathResponse>> {
+        let mut entries = self.entries.as_ref()?.lock().unwrap();
+        let response = match entries.get(key) {
+            Some((inserted, response)) if inserted.elapsed() < self.ttl => Some(response.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        };
+        if response.is_some() { self.hits.inc() } else { self.misses.inc() }
+        self.hit_ratio.set(self.hits.get() as f64 / (self.hits.get() + self.misses.get()) as f64);
+        response
+    }
+
+    fn insert(&self, key: PathKey, response: &Option<PathResponse>) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().put(key, (Instant::now(), response.clone()));
+        }
+    }
+
+    fn clear(&self) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().clear();
+        }
+    }
+}
+
+/// Search contexts of finished searches, borrowed by new searches to reuse their memory.
+/// They don't borrow the NavGrid, so they can be reused after it was reloaded.
+#[derive(Clone, Default)]
+struct SearchContexts(Arc<Mutex<Vec<SearchContext>>>);
+
+impl SearchContexts {
+    fn with<F, R>(&self, search: F) -> R where F: FnOnce(&mut SearchContext) -> R {
+        let mut context = self.0.lock().unwrap().pop().unwrap_or_default();
+        let result = search(&mut context);
+        self.0.lock().unwrap().push(context);
+        result
+    }
+}
+
+/// Cancels the search the flag was passed to when dropped. Handlers hold it while awaiting a search on a blocking
+/// thread, and are dropped along with it if the client disconnects, so the search stops instead of finishing for no one.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl CancelOnDrop {
+    fn new() -> CancelOnDrop {
+        CancelOnDrop(Arc::new(AtomicBool::new(false)))
+    }
+
+    fn flag(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs a search on a blocking thread within the span, holding `cancel` until it finishes. The permit is released once
+/// the search stops rather than when the client disconnects.
+async fn spawn_search<T, F>(span: tracing::Span, permit: SearchPermit, cancel: CancelOnDrop, search: F) -> Result<T, Failure> where T: Send + 'static, F: FnOnce() -> T + Send + 'static {
+    let _cancel = cancel;
+    let search = rocket::tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let _span = span.entered();
+        search()
+    });
+    search.await.map_err(|_| Failure::new(Status::InternalServerError, "search_failed", "Search failed"))
+}
+
+/// NavGrid and the data derived from it once it is loaded
+struct Grid {
+    nav_grid: NavGrid,
+    data_selection: DataSelection,
+    /// Number of vertices per group
+    group_sizes: BTreeMap<u8, usize>,
+    /// Data points needed by paths starting in each group
+    group_selections: BTreeMap<u8, DataSelection>,
+    /// Contraction hierarchy built by the generator, if there is one next to the NavGrid
+    hierarchy: Option<ContractionHierarchy>,
+    /// Chunk graph searched by requests for approximate paths
+    region_graph: RegionGraph,
+}
+
+impl Grid {
+    fn load(path: &Path) -> Result<Grid, NavGridError> {
+        let mut nav_grid = model::util::load_nav_grid(path)?;
+        // Points of interest can be added without regenerating the grid, as a JSON array in a file next to it
+        let poi_path = path.with_extension("poi.json");
+        if poi_path.exists() {
+            let points_of_interest: Vec<PointOfInterest> = rocket::serde::json::from_slice(&std::fs::read(&poi_path)?).map_err(std::io::Error::from)?;
+            nav_grid.points_of_interest.extend(points_of_interest);
+        }
+        // Fatal diagnostics fail the startup, or the reload, which keeps the previous grid
+        let (fatal, diagnostics): (Vec<_>, Vec<_>) = nav_grid.validate().into_iter().partition(Diagnostic::is_fatal);
+        for diagnostic in diagnostics {
+            tracing::warn!("[NavGrid] {}", diagnostic);
+        }
+        if !fatal.is_empty() {
+            return Err(NavGridError::Invalid(fatal));
+        }
+        let data_selection = nav_grid.data_selection();
+        let mut group_sizes = BTreeMap::new();
+        nav_grid.vertices.iter().filter(|v| v.flags != 0).for_each(|v| *group_sizes.entry(v.get_group()).or_insert(0) += 1);
+        let group_selections = group_sizes.keys().map(|group| (*group, nav_grid.group_data_selection(*group))).collect();
+        let hierarchy_path = path.with_extension("ch");
+        let hierarchy = if hierarchy_path.exists() {
+            let checksum = model::util::read_header(path)?.map_or(0, |header| header.checksum);
+            Some(ContractionHierarchy::load(&hierarchy_path, &nav_grid, checksum)?)
+        } else {
+            None
+        };
+        let region_graph = RegionGraph::build(&nav_grid);
+        Ok(Grid { nav_grid, data_selection, group_sizes, group_selections, hierarchy, region_graph })
+    }
+
+    /// Searches the region graph for approximate paths. Exact searches use the contraction hierarchy if it covers the
+    /// search, the grid otherwise.
+    fn find_path(&self, contexts: &SearchContexts, algorithm: Algorithm, start: &Coordinate, end: &Coordinate, game_state: &GameState, options: &SearchOptions) -> (usize, usize, Result<Option<pathfinder::Path>, Aborted>) {
+        match (algorithm, &self.hierarchy) {
+            (Algorithm::Hpa, _) => pathfinder::hpa::find_path(&self.nav_grid, &self.region_graph, start, end, game_state, options),
+            (Algorithm::Dijkstra, Some(hierarchy)) if hierarchy.covers(&self.nav_grid, start, end, game_state, options) => hierarchy.query(&self.nav_grid, start, end, game_state, options),
+            (Algorithm::Dijkstra, _) => contexts.with(|context| pathfinder::astar_with_context(context, &self.nav_grid, start, end, game_state, options)),
+        }
+    }
+}
+
+/// The grid served for new requests. Reloading replaces it, while requests in progress finish on the previous grid.
+struct CurrentGrid {
+    path: PathBuf,
+    grid: RwLock<Arc<Grid>>,
+    reloading: AtomicBool,
+}
+
+impl CurrentGrid {
+    fn get(&self) -> Arc<Grid> {
+        self.grid.read().unwrap().clone()
+    }
+}
+
+/// Grids by name, e.g. of the live game and a beta world with a different map
+struct Grids {
+    grids: BTreeMap<String, CurrentGrid>,
+    /// Grid used by requests not naming one
+    default: String,
+}
+
+impl Grids {
+    fn get(&self, name: Option<&str>) -> Option<&CurrentGrid> {
+        self.grids.get(name.unwrap_or(&self.default))
+    }
+
+    /// Grid currently served under the name
+    fn current(&self, name: Option<&str>) -> Result<Arc<Grid>, &'static str> {
+        self.get(name).map(CurrentGrid::get).ok_or("Unknown grid")
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 struct Request {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    start: Coordinate,
+    end: Coordinate,
+    #[serde(default)]
+    game_state: GameState,
+    #[serde(default)]
+    options: SearchOptions,
+    /// Return the path to the closest reachable tile if the end can't be reached
+    #[serde(default)]
+    closest: bool,
+    /// Also search the path regardless of requirements, to tell which unmet requirements would make it cheaper
+    #[serde(default)]
+    alternative: bool,
+    #[serde(default)]
+    algorithm: Algorithm,
+    /// Also return the path as instructions, with walking steps grouped between interactions
+    #[serde(default)]
+    plan: bool,
+    /// Return the path towards the end found so far if the search runs out of budget
+    #[serde(default)]
+    partial: bool,
+}
+
+/// How a path is searched
+#[derive(Clone, Copy, Debug, Deserialize, Hash, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Algorithm {
+    /// Cheapest path, searched on the contraction hierarchy or the grid
+    Dijkstra,
+    /// Approximate path, searched on the chunk graph in a fraction of the time. Ignores the search options other than
+    /// the requirements and budget.
+    Hpa,
+}
+
+impl Default for Algorithm {
+    fn default() -> Self {
+        Algorithm::Dijkstra
+    }
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+struct PathResponse {
+    #[serde(flatten)]
+    path: PathResult,
+    /// Whether the path ends at the requested end, only present if the closest tile or a partial path was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reached: Option<bool>,
+    /// Whether the search ran out of budget before reaching the end, only present if a partial path was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget_exceeded: Option<bool>,
+    /// Cheaper path needing requirements the game state doesn't meet, only present if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternative: Option<Alternative>,
+    /// Instructions to follow the path, only present if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plan: Option<Vec<Instruction>>,
+}
+
+impl Request {
+    fn validate(&self) -> Result<(), &'static str> {
+        if !self.start.validate() || !self.end.validate() {
+            Err("Coordinate out of bounds")
+        } else if !self.options.avoid.iter().all(Area::validate) {
+            Err("Avoided area out of bounds")
+        } else if self.options.avoid.iter().any(|area| area.contains(&self.start) || area.contains(&self.end)) {
+            Err("Start or end inside of an avoided area")
+        } else if !self.options.constraints.permits(&self.start) || !self.options.constraints.permits(&self.end) {
+            Err("Start or end violates the path constraints")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct BatchEntry {
+    path: Option<pathfinder::Path>,
+    /// Duration of the search in milliseconds, absent for invalid requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct NearestRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    start: Coordinate,
+    ends: Vec<Coordinate>,
+    #[serde(default)]
+    game_state: GameState,
+    #[serde(default)]
+    options: SearchOptions,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct NearestResponse {
+    /// Position of the reached end in `ends`
+    end: usize,
+    #[serde(flatten)]
+    path: pathfinder::Path,
+}
+
+impl NearestRequest {
+    fn validate(&self) -> Result<(), &'static str> {
+        if !self.start.validate() || !self.ends.iter().all(Coordinate::validate) {
+            Err("Coordinate out of bounds")
+        } else if !self.options.avoid.iter().all(Area::validate) {
+            Err("Avoided area out of bounds")
+        } else if self.options.avoid.iter().any(|area| area.contains(&self.start)) {
+            Err("Start inside of an avoided area")
+        } else if !self.options.constraints.permits(&self.start) {
+            Err("Start violates the path constraints")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct BankRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    start: Coordinate,
+    /// Where a round trip ends, the start if absent
+    #[serde(default)]
+    target: Option<Coordinate>,
+    /// Bank a round trip has to pass, the one making the cheapest loop if absent
+    #[serde(default)]
+    via_bank: Option<Coordinate>,
+    #[serde(default)]
+    game_state: GameState,
+    #[serde(default)]
+    options: SearchOptions,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct NearestBankResponse {
+    bank: PointOfInterest,
+    #[serde(flatten)]
+    path: pathfinder::Path,
+}
+
+impl BankRequest {
+    fn validate(&self) -> Result<(), &'static str> {
+        let coordinates = || std::iter::once(&self.start).chain(&self.target).chain(&self.via_bank);
+        if !coordinates().all(Coordinate::validate) {
+            Err("Coordinate out of bounds")
+        } else if !self.options.avoid.iter().all(Area::validate) {
+            Err("Avoided area out of bounds")
+        } else if self.options.avoid.iter().any(|area| coordinates().any(|c| area.contains(c))) {
+            Err("Start, target or bank inside of an avoided area")
+        } else if !coordinates().all(|c| self.options.constraints.permits(c)) {
+            Err("Start, target or bank violates the path constraints")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Most cells of a cost matrix computed per request
+const MAX_MATRIX_SIZE: usize = 10_000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct MatrixRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    sources: Vec<Coordinate>,
+    destinations: Vec<Coordinate>,
+    #[serde(default)]
+    game_state: GameState,
+    #[serde(default)]
+    options: SearchOptions,
+}
+
+impl MatrixRequest {
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.sources.len() * self.destinations.len() > MAX_MATRIX_SIZE {
+            Err("Too many sources and destinations")
+        } else if !self.sources.iter().chain(&self.destinations).all(Coordinate::validate) {
+            Err("Coordinate out of bounds")
+        } else if !self.options.avoid.iter().all(Area::validate) {
+            Err("Avoided area out of bounds")
+        } else if self.options.avoid.iter().any(|area| self.sources.iter().any(|source| area.contains(source))) {
+            Err("Source inside of an avoided area")
+        } else if !self.sources.iter().all(|source| self.options.constraints.permits(source)) {
+            Err("Source violates the path constraints")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct PoiCandidate {
+    #[serde(flatten)]
+    poi: PointOfInterest,
+    /// Cost of the cheapest path to the point of interest
+    cost: u32,
+}
+
+/// Parses a coordinate given as `x,y,plane`
+fn parse_coordinate(s: &str) -> Option<Coordinate> {
+    let mut parts = s.split(',').map(|part| part.trim());
+    let coordinate = Coordinate { x: parts.next()?.parse().ok()?, y: parts.next()?.parse().ok()?, plane: parts.next()?.parse().ok()? };
+    parts.next().is_none().then_some(coordinate)
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CompareRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
     start: Coordinate,
     end: Coordinate,
     #[serde(default)]
+    state_a: GameState,
+    #[serde(default)]
+    state_b: GameState,
+    /// Options of both searches
+    #[serde(default)]
+    options: SearchOptions,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GroupGraphRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    #[serde(default)]
+    game_state: GameState,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GroupLink {
+    /// Source group, absent for teleports
+    from: Option<u8>,
+    to: u8,
+    edge: EdgeDefinition,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GroupGraph {
+    /// Number of vertices per group
+    groups: BTreeMap<u8, usize>,
+    links: Vec<GroupLink>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct ReachableRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    start: Coordinate,
+    /// Only include tiles reachable for at most this cost
+    max_cost: Option<u32>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GroupReachableRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    from: Coordinate,
+    to: Coordinate,
+    #[serde(default)]
+    game_state: GameState,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GroupReachability {
+    /// False if no path can exist, true if one may exist
+    reachable: bool,
+    from_group: u8,
+    to_group: u8,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct AreaRequest {
+    /// Name of the grid to search, the default grid if absent
+    #[serde(default)]
+    grid: Option<String>,
+    center: Coordinate,
+    max_cost: u32,
+    #[serde(default)]
     game_state: GameState,
 }
 
-#[derive(Clone, Default, Serialize)]
-struct DataSelection {
-    varps: HashSet<u32>,
-    varbits: HashSet<u32>,
-    items: HashSet<String>,
-    skills: HashSet<String>,
+/// Tiles shown by the debug page, `size` by `size` tiles with `x, y` at the south-west corner
+#[derive(Serialize, JsonSchema)]
+struct DebugTiles {
+    x: u16,
+    y: u16,
+    plane: u8,
+    /// Movement flags, row by row from the south
+    flags: Vec<u8>,
+    /// Tiles with extra edges
+    edges: Vec<Coordinate>,
+    /// Tiles teleports lead to
+    teleports: Vec<Coordinate>,
+}
+
+/// Largest number of tiles per side the debug page can request at once
+const MAX_DEBUG_TILES: u16 = 512;
+
+/// Horizontal line of `length` reachable tiles, starting at `x`
+#[derive(Serialize, JsonSchema)]
+struct TileRun {
+    x: u16,
+    y: u16,
+    plane: u8,
+    length: u16,
 }
 
+/// Straight lines of walking steps are merged into a single step if `simplify` is set,
+/// all walking steps leading directly to a tile if `compress` is set.
+/// Cached responses carry the `visited` and `duration_ms` of the search that generated them.
+/// The search runs on a blocking thread and is cancelled if the client disconnects before it finishes.
+#[post("/?<simplify>&<compress>", data = "<request>")]
+async fn handle_path_request(request: Json<Request>, simplify: Option<bool>, compress: Option<bool>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, contexts: &State<SearchContexts>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>, metrics: &State<SearchMetrics>, cache: &State<PathCache>) -> Result<Timed<Json<Option<PathResponse>>>, Failure> {
+    let span = tracing::info_span!("path", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        let mut request = request.into_inner();
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        if let Err(reason) = request.validate() {
+            tracing::debug!("[Path] {} -> {} invalid request: {}", request.start, request.end, reason);
+            return Err(Failure::invalid_request(reason));
+        }
+        budget.apply(&mut request.options);
+        let key = PathKey::new(request.grid.as_deref().unwrap_or(&grids.default), &grid, &request, simplify.unwrap_or(false), compress.unwrap_or(false));
+        if let Some(response) = cache.get(&key) {
+            return Ok(Timed(Json(response)));
+        }
+        let cancel = CancelOnDrop::new();
+        request.options.cancelled = Some(cancel.flag());
+        let search_grid = grid.clone();
+        let contexts = contexts.inner().clone();
+        let (request, duration, visited, mem_usage, result, alternative) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let (visited, mem_usage, result) = if request.closest {
+                let (visited, mem_usage, result) = pathfinder::closest_reachable(&search_grid.nav_grid, &request.start, &request.end, &request.game_state, &request.options);
+                (visited, mem_usage, result.map(|(path, reached)| Some((path, Some(reached), None))))
+            } else if request.partial {
+                let (visited, mem_usage, outcome) = contexts.with(|context| pathfinder::astar_with_fallback(context, &search_grid.nav_grid, &request.start, &request.end, &request.game_state, &request.options));
+                (visited, mem_usage, match outcome {
+                    SearchOutcome::Found(path) => Ok(Some((path, Some(true), Some(false)))),
+                    SearchOutcome::Unreachable => Ok(None),
+                    SearchOutcome::Budget { best_partial_path, .. } => Ok(Some((best_partial_path, Some(false), Some(true)))),
+                    SearchOutcome::Cancelled => Err(Aborted::Cancelled),
+                })
+            } else {
+                let (visited, mem_usage, result) = search_grid.find_path(&contexts, request.algorithm, &request.start, &request.end, &request.game_state, &request.options);
+                (visited, mem_usage, result.map(|path| path.map(|path| (path, None, None))))
+            };
+            let duration = Instant::now() - begin;
+            // Searched while the request is still connected, since cancelling on disconnect ends with the search
+            let alternative = match &result {
+                Ok(Some((path, reached, _))) if request.alternative && *reached != Some(false) => {
+                    let (_, _, alternative) = pathfinder::unrestricted_alternative(&search_grid.nav_grid, &request.start, &request.end, &request.game_state, &request.options, Some(path.cost));
+                    alternative.unwrap_or(None)
+                }
+                _ => None,
+            };
+            (request, duration, visited, mem_usage, result, alternative)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[Path] {} -> {} in {:.2}ms, {}Kb, {} visited, {:?}", request.start, request.end, duration.as_secs_f64() * 1000f64, mem_usage / 1024, visited, request));
+        metrics.observe(duration, visited, mem_usage, match &result {
+            Ok(Some((_, _, Some(true)))) => Err(Aborted::BudgetExceeded),
+            Ok(Some((path, reached, _))) if *reached != Some(false) => Ok(Some(path)),
+            Ok(_) => Ok(None),
+            Err(e) => Err(*e),
+        });
+        let result = result.map_err(Failure::aborted)?;
+        let post_process = |steps: Vec<Step>| if compress.unwrap_or(false) {
+            pathfinder::compress_path(&grid.nav_grid, &steps)
+        } else if simplify.unwrap_or(false) {
+            pathfinder::simplify(steps)
+        } else {
+            steps
+        };
+        let response = result.map(|(path, reached, budget_exceeded)| {
+            let alternative = alternative.map(|mut alternative| {
+                alternative.path.steps = post_process(std::mem::take(&mut alternative.path.steps));
+                alternative
+            });
+            let mut path = PathResult::new(path, visited, duration);
+            path.path.steps = post_process(std::mem::take(&mut path.path.steps));
+            let plan = request.plan.then(|| pathfinder::plan::to_plan(&path.path.steps));
+            PathResponse { path, reached, budget_exceeded, alternative, plan }
+        });
+        cache.insert(key, &response);
+        Ok(Timed(Json(response)))
+    }.instrument(span).await
+}
+
+/// Generates the paths for all requests in parallel, in the same order. Invalid requests and searches exceeding the
+/// budget yield `null` instead of failing the whole batch.
+/// The searches run on a blocking thread and are cancelled if the client disconnects before they finish.
+#[post("/batch", data = "<requests>")]
+async fn handle_batch_request(requests: Json<Vec<Request>>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, contexts: &State<SearchContexts>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>, metrics: &State<SearchMetrics>) -> Result<Timed<Json<Vec<BatchEntry>>>, Failure> {
+    let span = tracing::info_span!("batch", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        let cancel = CancelOnDrop::new();
+        // Grids are looked up before searching, invalid requests don't get one
+        let requests: Vec<(Request, Option<Arc<Grid>>)> = requests.into_inner().into_iter()
+            .map(|mut request| {
+                budget.apply(&mut request.options);
+                request.options.cancelled = Some(cancel.flag());
+                let grid = request.validate().ok().and_then(|_| grids.current(request.grid.as_deref()).ok());
+                (request, grid)
+            })
+            .collect();
+        let count = requests.len();
+        let contexts = contexts.inner().clone();
+        let (duration, searches) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let searches: Vec<_> = requests.par_iter()
+                .map(|(request, grid)| {
+                    let grid = grid.as_ref()?;
+                    let begin = Instant::now();
+                    let (visited, mem_usage, path) = grid.find_path(&contexts, request.algorithm, &request.start, &request.end, &request.game_state, &request.options);
+                    Some((Instant::now() - begin, visited, mem_usage, path))
+                })
+                .collect();
+            (Instant::now() - begin, searches)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[Batch] {} requests in {:.2}ms", count, duration.as_secs_f64() * 1000f64));
+        let entries = searches.into_iter()
+            .map(|search| match search {
+                Some((duration, visited, mem_usage, path)) => {
+                    metrics.observe(duration, visited, mem_usage, path.as_ref().map(Option::as_ref).map_err(|e| *e));
+                    BatchEntry { path: path.unwrap_or(None), duration_ms: Some(duration.as_secs_f64() * 1000f64) }
+                }
+                None => BatchEntry { path: None, duration_ms: None },
+            })
+            .collect();
+        Ok(Timed(Json(entries)))
+    }.instrument(span).await
+}
+
+/// Generates the path to whichever of the ends is the cheapest to reach
+#[post("/nearest", data = "<request>")]
+async fn handle_nearest_request(request: Json<NearestRequest>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>, metrics: &State<SearchMetrics>) -> Result<Timed<Json<Option<NearestResponse>>>, Failure> {
+    let span = tracing::info_span!("nearest", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        request.validate().map_err(Failure::invalid_request)?;
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        let mut request = request.into_inner();
+        budget.apply(&mut request.options);
+        let cancel = CancelOnDrop::new();
+        request.options.cancelled = Some(cancel.flag());
+        let (request, duration, visited, mem_usage, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let (visited, mem_usage, result) = pathfinder::dijkstra_multi_with_options(&grid.nav_grid, &request.start, &request.ends, &request.game_state, &request.options);
+            (request, Instant::now() - begin, visited, mem_usage, result)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[Nearest] {} -> {} ends in {:.2}ms, {}Kb, {} visited, {:?}", request.start, request.ends.len(), duration.as_secs_f64() * 1000f64, mem_usage / 1024, visited, request));
+        metrics.observe(duration, visited, mem_usage, result.as_ref().map(|reached| reached.as_ref().map(|(_, path)| path)).map_err(|e| *e));
+        let result = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(result.map(|(end, path)| NearestResponse { end, path }))))
+    }.instrument(span).await
+}
+
+/// Generates the path to whichever bank is the cheapest to reach
+#[post("/nearest", data = "<request>")]
+async fn handle_nearest_bank_request(request: Json<BankRequest>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>, metrics: &State<SearchMetrics>) -> Result<Timed<Json<Option<NearestBankResponse>>>, Failure> {
+    let span = tracing::info_span!("nearest_bank", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        request.validate().map_err(Failure::invalid_request)?;
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        let mut request = request.into_inner();
+        budget.apply(&mut request.options);
+        let cancel = CancelOnDrop::new();
+        request.options.cancelled = Some(cancel.flag());
+        let (request, duration, visited, mem_usage, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let (visited, mem_usage, result) = pathfinder::nearest_bank(&grid.nav_grid, &request.start, &request.game_state, &request.options);
+            let result = result.map(|reached| reached.map(|(bank, path)| (bank.clone(), path)));
+            (request, Instant::now() - begin, visited, mem_usage, result)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[Bank] {} in {:.2}ms, {}Kb, {} visited, {:?}", request.start, duration.as_secs_f64() * 1000f64, mem_usage / 1024, visited, request));
+        metrics.observe(duration, visited, mem_usage, result.as_ref().map(|reached| reached.as_ref().map(|(_, path)| path)).map_err(|e| *e));
+        let result = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(result.map(|(bank, path)| NearestBankResponse { bank, path }))))
+    }.instrument(span).await
+}
+
+/// Generates the cheapest loop from the start via a bank to the target, e.g. from a resource spot to the bank and back
+#[post("/round-trip", data = "<request>")]
+async fn handle_round_trip_request(request: Json<BankRequest>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>, metrics: &State<SearchMetrics>) -> Result<Timed<Json<Option<RoundTrip>>>, Failure> {
+    let span = tracing::info_span!("round_trip", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        request.validate().map_err(Failure::invalid_request)?;
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        let mut request = request.into_inner();
+        budget.apply(&mut request.options);
+        let cancel = CancelOnDrop::new();
+        request.options.cancelled = Some(cancel.flag());
+        let target = request.target.unwrap_or(request.start);
+        let (request, duration, visited, mem_usage, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let (visited, mem_usage, result) = pathfinder::round_trip(&grid.nav_grid, &request.start, &target, request.via_bank.as_ref(), &request.game_state, &request.options);
+            (request, Instant::now() - begin, visited, mem_usage, result)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[Round trip] {} -> {} in {:.2}ms, {}Kb, {} visited, {:?}", request.start, target, duration.as_secs_f64() * 1000f64, mem_usage / 1024, visited, request));
+        metrics.observe(duration, visited, mem_usage, result.as_ref().map(|trip| trip.as_ref().map(|trip| &trip.to_bank)).map_err(|e| *e));
+        let result = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(result)))
+    }.instrument(span).await
+}
+
+/// Returns the costs from every source to every destination as rows by source, `null` where a destination can't be
+/// reached. Only one search per source is run, however many destinations there are.
 #[post("/", data = "<request>")]
-fn handle_path_request(request: Json<Request>, nav_grid: &State<NavGrid>) -> Result<Json<Option<Vec<EdgeDefinition>>>, BadRequest<&str>> {
-    if !request.start.validate() || !request.end.validate() {
-        println!("[Path] {} -> {} invalid coordinates", request.start, request.end);
-        Err(BadRequest(Some("Coordinate out of bounds")))
-    } else {
+async fn handle_matrix_request(request: Json<MatrixRequest>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>) -> Result<Timed<Json<pathfinder::CostMatrix>>, Failure> {
+    let span = tracing::info_span!("matrix", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        request.validate().map_err(Failure::invalid_request)?;
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        let mut request = request.into_inner();
+        budget.apply(&mut request.options);
+        let cancel = CancelOnDrop::new();
+        request.options.cancelled = Some(cancel.flag());
+        let (request, duration, visited, mem_usage, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let (visited, mem_usage, result) = pathfinder::cost_matrix(&grid.nav_grid, &request.sources, &request.destinations, &request.game_state, &request.options);
+            (request, Instant::now() - begin, visited, mem_usage, result)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[Matrix] {}x{} in {:.2}ms, {}Kb, {} visited", request.sources.len(), request.destinations.len(), duration.as_secs_f64() * 1000f64, mem_usage / 1024, visited));
+        let costs = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(costs)))
+    }.instrument(span).await
+}
+
+/// Returns the points of interest of a kind, e.g. all furnaces, sorted by the cost of the path from `near` to them.
+/// The game state is passed as JSON in the query, the default game state if absent.
+#[get("/?<tag>&<near>&<grid>&<limit>&<game_state>")]
+async fn handle_poi_request(tag: &str, near: &str, grid: Option<&str>, limit: Option<usize>, game_state: Option<&str>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>) -> Result<Timed<Json<Vec<PoiCandidate>>>, Failure> {
+    let span = tracing::info_span!("poi", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        let kind: PoiKind = tag.parse().map_err(|_| Failure::invalid_request("Unknown tag"))?;
+        let near = parse_coordinate(near).filter(Coordinate::validate).ok_or_else(|| Failure::invalid_request("Expected near as x,y,plane within the map"))?;
+        let game_state: GameState = match game_state {
+            Some(game_state) => rocket::serde::json::from_str(game_state).map_err(|_| Failure::malformed_request("Malformed game state"))?,
+            None => GameState::default(),
+        };
+        let grid = grids.current(grid).map_err(Failure::unknown_grid)?;
+        let mut options = SearchOptions::default();
+        budget.apply(&mut options);
+        let cancel = CancelOnDrop::new();
+        options.cancelled = Some(cancel.flag());
+        let (duration, visited, mem_usage, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let (visited, mem_usage, result) = pathfinder::points_of_interest_by_cost(&grid.nav_grid, &near, kind, &game_state, &options);
+            let result = result.map(|candidates| candidates.into_iter()
+                .take(limit.unwrap_or(usize::MAX))
+                .map(|(poi, cost)| PoiCandidate { poi: poi.clone(), cost })
+                .collect::<Vec<_>>());
+            (Instant::now() - begin, visited, mem_usage, result)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[POI] {} near {} in {:.2}ms, {}Kb, {} visited", kind.tag(), near, duration.as_secs_f64() * 1000f64, mem_usage / 1024, visited));
+        let candidates = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(candidates)))
+    }.instrument(span).await
+}
+
+/// Streams the expansion order of a search as server-sent events, in batches of `batch` coordinates, followed by a
+/// `complete` event carrying the path, or a `budget_exceeded` event if the search was aborted.
+/// The request is passed as JSON in the query, since EventSource only supports GET.
+/// The search runs on a blocking thread while its batches are sent, and is cancelled if the client disconnects.
+#[get("/trace?<request>&<batch>")]
+fn handle_trace_request(request: &str, batch: Option<usize>, _client: ApiClient, permit: SearchPermit, grids: &State<Grids>, budget: &State<SearchBudget>) -> Result<EventStream![], Failure> {
+    let mut request: Request = rocket::serde::json::from_str(request).map_err(|_| Failure::malformed_request("Malformed request"))?;
+    request.validate().map_err(Failure::invalid_request)?;
+    let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+    budget.apply(&mut request.options);
+    let batch = batch.unwrap_or(1000).max(1);
+    // Dropped along with the stream if the client disconnects
+    let cancel = CancelOnDrop::new();
+    request.options.cancelled = Some(cancel.flag());
+    let (sender, mut events) = rocket::tokio::sync::mpsc::unbounded_channel();
+    rocket::tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let mut expanded = Vec::with_capacity(batch);
+        let (_, _, path) = pathfinder::dijkstra_with_observer(&grid.nav_grid, &request.start, &request.end, &request.game_state, &request.options, |index| {
+            expanded.push(Coordinate::from_index(index));
+            if expanded.len() == batch {
+                let _ = sender.send(Event::json(&expanded).event("expand"));
+                expanded.clear();
+            }
+        });
+        if !expanded.is_empty() {
+            let _ = sender.send(Event::json(&expanded).event("expand"));
+        }
+        let _ = sender.send(match path {
+            Ok(path) => Event::json(&path).event("complete"),
+            Err(Aborted::BudgetExceeded) => Event::empty().event("budget_exceeded"),
+            // The client is gone
+            Err(Aborted::Cancelled) => return,
+        });
+    });
+    Ok(EventStream! {
+        let _cancel = cancel;
+        while let Some(event) = events.recv().await {
+            yield event;
+        }
+    })
+}
+
+/// Expanded vertices between progress messages of WebSocket searches
+const PROGRESS_INTERVAL: usize = 10_000;
+
+/// Message sent by the `/ws/path` WebSocket
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum PathMessage {
+    /// Number of vertices expanded so far, sent periodically while searching
+    Progress { visited: usize },
+    /// Result of the search, `null` if the end can't be reached
+    Complete { path: Option<PathResult> },
+    /// The request was invalid or the search was aborted
+    Error { reason: String },
+}
+
+/// Takes `/path` requests as text messages and answers each with progress messages while searching, followed by the
+/// result. Requests are handled one after another, so a client can keep the socket open for many searches.
+#[get("/path")]
+fn handle_ws_path_request<'r>(_client: ApiClient, ws: WebSocket, grids: &'r State<Grids>, budget: &'r State<SearchBudget>, limiter: &'r State<SearchLimiter>, metrics: &'r State<SearchMetrics>) -> Channel<'r> {
+    ws.channel(move |mut stream| Box::pin(async move {
+        while let Some(message) = stream.next().await {
+            let request = match message? {
+                Message::Text(request) => request,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let message = match search_ws_request(&request, grids, budget, limiter, metrics, &mut stream).await? {
+                Ok(path) => PathMessage::Complete { path },
+                Err(reason) => PathMessage::Error { reason },
+            };
+            stream.send(Message::Text(rocket::serde::json::to_string(&message).unwrap())).await?;
+        }
+        Ok(())
+    }))
+}
+
+/// Searches the path of a WebSocket request on a blocking thread, forwarding its progress to the stream
+async fn search_ws_request(request: &str, grids: &Grids, budget: &SearchBudget, limiter: &SearchLimiter, metrics: &SearchMetrics, stream: &mut DuplexStream) -> rocket_ws::result::Result<Result<Option<PathResult>, String>> {
+    let mut request: Request = match rocket::serde::json::from_str(request) {
+        Ok(request) => request,
+        Err(e) => return Ok(Err(format!("Malformed request: {}", e))),
+    };
+    if let Err(reason) = request.validate() {
+        return Ok(Err(reason.to_string()));
+    }
+    let grid = match grids.current(request.grid.as_deref()) {
+        Ok(grid) => grid,
+        Err(reason) => return Ok(Err(reason.to_string())),
+    };
+    budget.apply(&mut request.options);
+    let _permit = match limiter.acquire().await {
+        Ok(permit) => permit,
+        Err(Saturated) => return Ok(Err("Too many searches in progress".to_string())),
+    };
+    // Stops the search if the client closes the socket while it runs
+    let cancel = CancelOnDrop::new();
+    request.options.cancelled = Some(cancel.flag());
+    let (sender, mut progress) = rocket::tokio::sync::mpsc::unbounded_channel();
+    let search = rocket::tokio::task::spawn_blocking(move || {
         let begin = Instant::now();
-        let (visited, mem_usage, path) = pathfinder::dijkstra(&nav_grid, &request.start, &request.end, &request.game_state);
-        let duration = Instant::now() - begin;
-        println!("[Path] {} -> {} in {:.2}ms, {}Kb, {} visited", request.start, request.end, duration.as_secs_f64() * 1000f64, mem_usage / 1024, visited);
-        Ok(Json(path))
+        let mut expanded = 0;
+        let (visited, mem_usage, path) = pathfinder::dijkstra_with_observer(&grid.nav_grid, &request.start, &request.end, &request.game_state, &request.options, |_| {
+            expanded += 1;
+            if expanded % PROGRESS_INTERVAL == 0 {
+                let _ = sender.send(expanded);
+            }
+        });
+        (visited, mem_usage, path, Instant::now() - begin)
+    });
+    while let Some(visited) = progress.recv().await {
+        stream.send(Message::Text(rocket::serde::json::to_string(&PathMessage::Progress { visited }).unwrap())).await?;
+    }
+    let (visited, mem_usage, path, duration) = match search.await {
+        Ok(search) => search,
+        Err(e) => return Ok(Err(format!("Search failed: {}", e))),
+    };
+    metrics.observe(duration, visited, mem_usage, path.as_ref().map(Option::as_ref).map_err(|e| *e));
+    Ok(path.map(|path| path.map(|path| PathResult::new(path, visited, duration))).map_err(|aborted| match aborted {
+        Aborted::BudgetExceeded => "Search budget exceeded".to_string(),
+        Aborted::Cancelled => "Search cancelled".to_string(),
+    }))
+}
+
+#[post("/", data = "<request>")]
+async fn handle_compare_request(request: Json<CompareRequest>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, slow_query: &State<SlowQueryThreshold>, budget: &State<SearchBudget>) -> Result<Timed<Json<Comparison>>, Failure> {
+    let span = tracing::info_span!("compare", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        let mut request = request.into_inner();
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        if !request.start.validate() || !request.end.validate() {
+            tracing::debug!("[Compare] {} -> {} invalid coordinates", request.start, request.end);
+            return Err(Failure::invalid_request("Coordinate out of bounds"));
+        }
+        budget.apply(&mut request.options);
+        let cancel = CancelOnDrop::new();
+        request.options.cancelled = Some(cancel.flag());
+        let (request, duration, visited, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let (visited, _, result) = pathfinder::compare(&grid.nav_grid, &request.start, &request.end, &request.state_a, &request.state_b, &request.options);
+            (request, Instant::now() - begin, visited, result)
+        }).await?;
+        timings.search(duration);
+        slow_query.log(duration, || format!("[Compare] {} -> {} in {:.2}ms, {} visited, {:?}", request.start, request.end, duration.as_secs_f64() * 1000f64, visited, request));
+        let comparison = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(comparison)))
+    }.instrument(span).await
+}
+
+#[post("/graph", data = "<request>")]
+fn handle_group_graph_request(request: Json<GroupGraphRequest>, _client: ApiClient, grids: &State<Grids>) -> Result<Json<GroupGraph>, Failure> {
+    let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+    let mut links: Vec<GroupLink> = pathfinder::group_links(&grid.nav_grid, &request.game_state).into_iter()
+        .map(|(from, to, edge)| GroupLink { from, to, edge: edge.definition.clone() })
+        .collect();
+    links.sort_by_key(|link| (link.from, link.to));
+    links.dedup_by(|a, b| a.from == b.from && a.to == b.to && a.edge == b.edge);
+    Ok(Json(GroupGraph { groups: grid.group_sizes.clone(), links }))
+}
+
+/// Returns the tiles reachable from the start, encoded as horizontal runs ordered by plane, y and x.
+/// Edges are followed regardless of their requirements.
+#[post("/", data = "<request>")]
+async fn handle_reachable_request(request: Json<ReachableRequest>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, budget: &State<SearchBudget>) -> Result<Timed<Json<Vec<TileRun>>>, Failure> {
+    let span = tracing::info_span!("reachable", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        if !request.start.validate() {
+            tracing::debug!("[Reachable] {} invalid coordinate", request.start);
+            return Err(Failure::invalid_request("Coordinate out of bounds"));
+        }
+        let mut options = SearchOptions { max_cost: request.max_cost, ..SearchOptions::default() };
+        budget.apply(&mut options);
+        let cancel = CancelOnDrop::new();
+        options.cancelled = Some(cancel.flag());
+        let start = request.start;
+        let (duration, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let mut reachable = Vec::new();
+            let (_, _, result) = pathfinder::flood_with_options(&grid.nav_grid, &start, &options, |index, _| {
+                reachable.push(index);
+                true
+            });
+            (Instant::now() - begin, result.map(|_| tile_runs(reachable)))
+        }).await?;
+        timings.search(duration);
+        let runs = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(runs)))
+    }.instrument(span).await
+}
+
+/// Merges the vertex indices into horizontal runs, ordered by plane, y and x
+fn tile_runs(mut indices: Vec<u32>) -> Vec<TileRun> {
+    indices.sort_unstable();
+    let mut runs: Vec<TileRun> = Vec::new();
+    for coordinate in indices.into_iter().map(Coordinate::from_index) {
+        match runs.last_mut() {
+            Some(run) if run.plane == coordinate.plane && run.y == coordinate.y && run.x + run.length == coordinate.x => run.length += 1,
+            _ => runs.push(TileRun { x: coordinate.x, y: coordinate.y, plane: coordinate.plane, length: 1 }),
+        }
+    }
+    runs
+}
+
+/// Returns the tiles reachable on foot from the center for at most `max_cost`, encoded like `/reachable`.
+/// Unlike `/reachable`, only edges whose requirements are met by the game state are followed.
+#[post("/", data = "<request>")]
+async fn handle_area_request(request: Json<AreaRequest>, _client: ApiClient, timings: &RequestTimings, permit: SearchPermit, grids: &State<Grids>, budget: &State<SearchBudget>) -> Result<Timed<Json<Vec<TileRun>>>, Failure> {
+    let span = tracing::info_span!("area", request_id = timings.id);
+    let search_span = span.clone();
+    async move {
+        let request = request.into_inner();
+        let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+        if !request.center.validate() {
+            tracing::debug!("[Area] {} invalid coordinate", request.center);
+            return Err(Failure::invalid_request("Coordinate out of bounds"));
+        }
+        let mut options = SearchOptions { max_cost: Some(request.max_cost), ..SearchOptions::default() };
+        budget.apply(&mut options);
+        let cancel = CancelOnDrop::new();
+        options.cancelled = Some(cancel.flag());
+        let (duration, result) = spawn_search(search_span, permit, cancel, move || {
+            let begin = Instant::now();
+            let mut area = Vec::new();
+            let (_, _, result) = pathfinder::flood_available_with_options(&grid.nav_grid, &request.center, &request.game_state, &options, |index, _| {
+                area.push(index);
+                true
+            });
+            (Instant::now() - begin, result.map(|_| tile_runs(area)))
+        }).await?;
+        timings.search(duration);
+        let runs = result.map_err(Failure::aborted)?;
+        Ok(Timed(Json(runs)))
+    }.instrument(span).await
+}
+
+/// Answers from the groups of the tiles whether a path can exist, without searching
+#[post("/groups", data = "<request>")]
+fn handle_group_reachable_request(request: Json<GroupReachableRequest>, _client: ApiClient, grids: &State<Grids>) -> Result<Json<GroupReachability>, Failure> {
+    let grid = grids.current(request.grid.as_deref()).map_err(Failure::unknown_grid)?;
+    if !request.from.validate() || !request.to.validate() {
+        tracing::debug!("[Reachable] {} -> {} invalid coordinates", request.from, request.to);
+        return Err(Failure::invalid_request("Coordinates out of bounds"));
     }
+    Ok(Json(GroupReachability {
+        reachable: pathfinder::groups_linked(&grid.nav_grid, &request.from, &request.to, &request.game_state),
+        from_group: grid.nav_grid.vertices[request.from.index() as usize].get_group(),
+        to_group: grid.nav_grid.vertices[request.to.index() as usize].get_group(),
+    }))
+}
+
+#[get("/?<grid>")]
+fn handle_select_request(grid: Option<&str>, _client: ApiClient, grids: &State<Grids>) -> Result<Json<DataSelection>, Failure> {
+    let grid = grids.current(grid).map_err(Failure::unknown_grid)?;
+    Ok(Json(grid.data_selection.clone()))
+}
+
+/// OpenAPI description of the routes, with the schemas of the request and response bodies
+#[get("/openapi.json")]
+fn handle_openapi_request(spec: &State<OpenApi>) -> RawJson<&str> {
+    RawJson(&spec.0)
 }
 
+/// Map of the grid to click paths on in a browser, showing the steps taken and their costs
 #[get("/")]
-fn handle_select_request(data_selection: &State<DataSelection>) -> Json<DataSelection> {
-    Json(data_selection.inner().clone())
+fn handle_debug_page() -> RawHtml<&'static str> {
+    RawHtml(include_str!("../static/debug.html"))
+}
+
+#[get("/tiles?<grid>&<x>&<y>&<plane>&<size>")]
+fn handle_debug_tiles_request(grid: Option<&str>, x: u16, y: u16, plane: u8, size: u16, _client: ApiClient, grids: &State<Grids>) -> Result<Json<DebugTiles>, Failure> {
+    let grid = grids.current(grid).map_err(Failure::unknown_grid)?;
+    let max = Coordinate { x: x.saturating_add(size).saturating_sub(1), y: y.saturating_add(size).saturating_sub(1), plane };
+    if size == 0 || size > MAX_DEBUG_TILES || !max.validate() {
+        return Err(Failure::invalid_request("Tiles out of bounds"));
+    }
+    let area = Area { min: Coordinate { x, y, plane }, max };
+    let mut tiles = DebugTiles { x, y, plane, flags: Vec::with_capacity(size as usize * size as usize), edges: Vec::new(), teleports: Vec::new() };
+    for y in area.min.y..=area.max.y {
+        for x in area.min.x..=area.max.x {
+            let coordinate = Coordinate { x, y, plane };
+            let vertex = &grid.nav_grid.vertices[coordinate.index() as usize];
+            tiles.flags.push(vertex.flags);
+            if vertex.has_extra_edges() {
+                tiles.edges.push(coordinate);
+            }
+        }
+    }
+    tiles.teleports = grid.nav_grid.teleports.iter().map(|teleport| teleport.destination).filter(|destination| area.contains(destination)).collect();
+    Ok(Json(tiles))
+}
+
+/// Loads the NavGrid file of the grid again in the background and swaps it in once it is loaded.
+/// Requests in progress finish on the previous grid, concurrent reloads are rejected.
+#[post("/reload?<grid>")]
+async fn handle_reload_request(grid: Option<&str>, _client: AdminClient, grids: &State<Grids>, cache: &State<PathCache>) -> Result<Status, Failure> {
+    let grid = grids.get(grid).ok_or_else(|| Failure::unknown_grid("Unknown grid"))?;
+    if grid.reloading.swap(true, Ordering::AcqRel) {
+        return Err(Failure::new(Status::Conflict, "reload_in_progress", "Reload already in progress"));
+    }
+    let path = grid.path.clone();
+    let begin = Instant::now();
+    let loaded = rocket::tokio::task::spawn_blocking(move || Grid::load(&path)).await;
+    grid.reloading.store(false, Ordering::Release);
+    match loaded {
+        Ok(Ok(loaded)) => {
+            *grid.grid.write().unwrap() = Arc::new(loaded);
+            cache.clear();
+            tracing::info!("[Reload] {} loaded in {:.2}s", grid.path.display(), (Instant::now() - begin).as_secs_f64());
+            Ok(Status::NoContent)
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("[Reload] {} failed: {}", grid.path.display(), e);
+            Err(Failure::new(Status::InternalServerError, "reload_failed", format!("Error loading NavGrid: {}", e)))
+        }
+        Err(e) => Err(Failure::new(Status::InternalServerError, "reload_failed", format!("Error loading NavGrid: {}", e))),
+    }
 }
 
 #[launch]
 fn rocket() -> Rocket<Build> {
     let options = Options::parse();
-    let nav_grid = load_nav_grid(&options.navgrid).or_exit_e_("Error loading NavGrid");
-    let mut data_selection = DataSelection::default();
-    nav_grid.iter_edges().flat_map(|e| &e.requirements).for_each(|r| {
-        match r {
-            RequirementDefinition::Varp { index, .. } => data_selection.varps.insert(*index),
-            RequirementDefinition::Varbit { index, .. } => data_selection.varbits.insert(*index),
-            RequirementDefinition::Item { item, .. } => data_selection.items.insert(item.to_string()),
-            RequirementDefinition::Skill { skill, .. } => data_selection.skills.insert(skill.clone()),
-            _ => false
-        };
-    });
+    init_logging(&options.log_level, options.log_format);
+    let default = options.navgrid[0].0.clone();
+    let mut grids = BTreeMap::new();
+    for (name, path) in options.navgrid {
+        if grids.contains_key(&name) {
+            eprintln!("Duplicate grid name {}", name);
+            std::process::exit(1);
+        }
+        let grid = Grid::load(&path).or_exit_e_("Error loading NavGrid");
+        grids.insert(name, CurrentGrid { path, grid: RwLock::new(Arc::new(grid)), reloading: AtomicBool::new(false) });
+    }
     let prometheus = PrometheusMetrics::new();
+    let metrics = SearchMetrics::new(prometheus.registry()).or_exit_e_("Error registering metrics");
+    let limiter = SearchLimiter::new(options.max_concurrent_searches, options.max_queued_searches, prometheus.registry()).or_exit_e_("Error registering metrics");
+    let cache = PathCache::new(options.cache_size, Duration::from_secs(options.cache_ttl_secs), prometheus.registry()).or_exit_e_("Error registering metrics");
+    let api_keys = ApiKeys::load(options.api_keys.as_deref(), prometheus.registry()).or_exit_e_("Error loading API keys");
     rocket::build()
+        .attach(RequestLog)
+        .attach(ApiUsage)
         .attach(prometheus.clone())
+        .register("/", catchers![handle_too_many_requests, handle_unauthorized, handle_forbidden, handle_error])
+        .mount("/", routes![handle_openapi_request])
         .mount("/metrics", prometheus)
-        .mount("/path", routes![handle_path_request])
+        .mount("/path", routes![handle_path_request, handle_batch_request, handle_nearest_request, handle_trace_request])
+        .mount("/compare", routes![handle_compare_request])
+        .mount("/bank", routes![handle_nearest_bank_request, handle_round_trip_request])
+        .mount("/poi", routes![handle_poi_request])
+        .mount("/matrix", routes![handle_matrix_request])
+        .mount("/groups", routes![handle_group_graph_request])
+        .mount("/area", routes![handle_area_request])
+        .mount("/reachable", routes![handle_reachable_request, handle_group_reachable_request])
         .mount("/select", routes![handle_select_request])
-        .manage(nav_grid)
-        .manage(data_selection)
-}
-
-fn load_nav_grid(path: impl AsRef<Path>) -> Result<NavGrid, ciborium::de::Error<std::io::Error>> {
-    let file = File::open(path)?;
-    let decoder = GzDecoder::new(file);
-    let mut reader = BufReader::new(decoder);
-    let mut nav_grid = NavGrid::new();
-    for vertex in &mut nav_grid.vertices {
-        let mut buf = [0; 2];
-        reader.read_exact(&mut buf)?;
-        vertex.flags = buf[0];
-        vertex.extra_edges_and_group = buf[1];
-    }
-    nav_grid.edges = ciborium::de::from_reader(&mut reader)?;
-    nav_grid.teleports = ciborium::de::from_reader(&mut reader)?;
-    Ok(nav_grid)
+        .mount("/ws", routes![handle_ws_path_request])
+        .mount("/admin", routes![handle_reload_request])
+        .mount("/debug", routes![handle_debug_page, handle_debug_tiles_request])
+        .manage(Grids { grids, default })
+        .manage(SearchContexts::default())
+        .manage(SlowQueryThreshold(options.slow_query_ms.map(Duration::from_millis)))
+        .manage(SearchBudget { max_visited: options.max_visited, max_memory: options.max_search_memory_mb.map(|mb| mb * 1024 * 1024), max_millis: options.max_search_ms })
+        .manage(limiter)
+        .manage(metrics)
+        .manage(cache)
+        .manage(api_keys)
+        .manage(openapi::spec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_searches_above_the_slow_query_threshold_are_slow() {
+        let threshold = SlowQueryThreshold(Some(Duration::from_millis(50)));
+        assert!(!threshold.is_slow(Duration::from_millis(10)));
+        assert!(!threshold.is_slow(Duration::from_millis(50)));
+        assert!(threshold.is_slow(Duration::from_millis(51)));
+        assert!(!SlowQueryThreshold(None).is_slow(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn errors_are_answered_with_a_code_message_and_details() {
+        let failure = Failure::invalid_request("Start outside of the map");
+        assert_eq!(failure.status, Status::BadRequest);
+        assert_eq!(rocket::serde::json::to_value(&failure.error).unwrap(), json!({ "code": "invalid_request", "message": "Start outside of the map" }));
+        let failure = Denied::RateLimited { retry_after: 5 }.failure();
+        assert_eq!(failure.status, Status::TooManyRequests);
+        assert_eq!(rocket::serde::json::to_value(&failure.error).unwrap(), json!({ "code": "rate_limited", "message": "Rate limit exceeded", "details": { "retry_after": 5 } }));
+        assert!(failure.headers.iter().any(|header| header.name().as_str() == "Retry-After" && header.value() == "5"));
+    }
+
+    fn api_keys(json: &str) -> ApiKeys {
+        let path = std::env::temp_dir().join(format!("api-keys-{}.json", std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let api_keys = ApiKeys::load(Some(&path), &Registry::new());
+        std::fs::remove_file(&path).unwrap();
+        api_keys.unwrap()
+    }
+
+    #[test]
+    fn api_keys_reject_requests_over_their_rate_limit_or_quota() {
+        let api_keys = api_keys(r#"[
+            {"name": "burst", "key": "a", "requests_per_minute": 2},
+            {"name": "daily", "key": "b", "requests_per_day": 1}
+        ]"#);
+        assert!(matches!(api_keys.authorize(None), Err(Denied::Missing)));
+        assert!(matches!(api_keys.authorize(Some("c")), Err(Denied::Invalid)));
+        assert!(api_keys.authorize(Some("a")).is_ok());
+        assert!(api_keys.authorize(Some("a")).is_ok());
+        // The burst is used up, one request is replenished every 30 seconds
+        assert!(matches!(api_keys.authorize(Some("a")), Err(Denied::RateLimited { retry_after: 30 })));
+        assert!(api_keys.authorize(Some("b")).is_ok());
+        assert!(matches!(api_keys.authorize(Some("b")), Err(Denied::QuotaExceeded { retry_after }) if retry_after <= SECONDS_PER_DAY));
+    }
+
+    #[test]
+    fn requests_are_allowed_without_api_keys() {
+        let api_keys = ApiKeys { keys: None, usage: Mutex::new(BTreeMap::new()), requests: IntCounterVec::new(Opts::new("requests", "Requests"), &["key", "status"]).unwrap() };
+        assert!(api_keys.authorize(None).is_ok());
+        assert!(api_keys.authorize(Some("a")).is_ok());
+    }
 }
@@ -0,0 +1,195 @@
+use rocket::serde::json::{json, Value};
+use rocket::serde::json::serde_json::Map;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+
+use model::definitions::DataSelection;
+use pathfinder::{Comparison, CostMatrix, RoundTrip};
+
+use crate::{ApiError, AreaRequest, BankRequest, BatchEntry, CompareRequest, DebugTiles, GroupGraph, GroupGraphRequest, GroupReachability, GroupReachableRequest, MatrixRequest, NearestBankResponse, NearestRequest, NearestResponse, PathResponse, PoiCandidate, ReachableRequest, Request, TileRun};
+
+/// OpenAPI 3 description of the routes, served at `/openapi.json`. The schemas of request and response bodies are
+/// derived from the types the routes (de)serialize, so they can't drift apart.
+pub struct OpenApi(pub String);
+
+/// Collects the operations of the spec, along with the schemas they refer to
+struct Spec {
+    generator: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl Spec {
+    fn new() -> Spec {
+        Spec { generator: SchemaSettings::openapi3().into_generator(), paths: Map::new() }
+    }
+
+    fn schema<T: JsonSchema>(&mut self) -> Value {
+        json!(self.generator.subschema_for::<T>())
+    }
+
+    fn body<T: JsonSchema>(&mut self) -> Value {
+        json!({ "required": true, "content": { "application/json": { "schema": self.schema::<T>() } } })
+    }
+
+    fn response<T: JsonSchema>(&mut self, description: &str) -> Value {
+        json!({ "description": description, "content": { "application/json": { "schema": self.schema::<T>() } } })
+    }
+
+    /// Query parameter, which is required unless `T` is an `Option`
+    fn query<T: JsonSchema>(&mut self, name: &str, description: &str) -> Value {
+        let schema = self.schema::<T>();
+        // OpenAPI 3 schemas of options are nullable
+        let required = schema.get("nullable").and_then(Value::as_bool) != Some(true);
+        json!({ "name": name, "in": "query", "description": description, "required": required, "schema": schema })
+    }
+
+    /// Adds an operation, which answers errors with an [ApiError] body
+    fn operation(&mut self, method: &str, path: &str, mut operation: Value) {
+        let error = self.response::<ApiError>("Error, see `code` for its kind");
+        operation["responses"]["default"] = error;
+        let item = self.paths.entry(path.to_string()).or_insert_with(|| json!({}));
+        item[method] = operation;
+    }
+
+    fn finish(self) -> Value {
+        let schemas: Map<String, Value> = self.generator.definitions().iter().map(|(name, schema)| (name.clone(), json!(schema))).collect();
+        json!({
+            "openapi": "3.0.3",
+            "info": { "title": "osrs-nav", "version": env!("CARGO_PKG_VERSION") },
+            "paths": self.paths,
+            // Keys are only required if the service is started with API keys
+            "security": [{}, { "apiKey": [] }, { "bearer": [] }],
+            "components": {
+                "schemas": schemas,
+                "securitySchemes": {
+                    "apiKey": { "type": "apiKey", "in": "header", "name": "X-Api-Key" },
+                    "bearer": { "type": "http", "scheme": "bearer" },
+                },
+            },
+        })
+    }
+}
+
+/// Describes every route but the debug page, `/metrics` and the WebSocket, which OpenAPI has no notion of
+pub fn spec() -> OpenApi {
+    let mut spec = Spec::new();
+    let grid = spec.query::<Option<String>>("grid", "Name of the grid to search, the default grid if absent");
+
+    let operation = json!({
+        "summary": "Generates the path between two coordinates",
+        "parameters": [
+            spec.query::<Option<bool>>("simplify", "Merge straight lines of walking steps into a single step"),
+            spec.query::<Option<bool>>("compress", "Merge all walking steps leading directly to a tile into a single step"),
+        ],
+        "requestBody": spec.body::<Request>(),
+        "responses": { "200": spec.response::<Option<PathResponse>>("Path, `null` if the end can't be reached") },
+    });
+    spec.operation("post", "/path", operation);
+    let operation = json!({
+        "summary": "Generates the paths for all requests in the same order",
+        "requestBody": spec.body::<Vec<Request>>(),
+        "responses": { "200": spec.response::<Vec<BatchEntry>>("Paths, `null` for invalid requests and searches exceeding the budget") },
+    });
+    spec.operation("post", "/path/batch", operation);
+    let operation = json!({
+        "summary": "Generates the path to whichever of the ends is the cheapest to reach",
+        "requestBody": spec.body::<NearestRequest>(),
+        "responses": { "200": spec.response::<Option<NearestResponse>>("Path, `null` if no end can be reached") },
+    });
+    spec.operation("post", "/path/nearest", operation);
+    let operation = json!({
+        "summary": "Streams the expansion order of a search as server-sent events",
+        "parameters": [
+            spec.query::<String>("request", "Path request as JSON"),
+            spec.query::<Option<usize>>("batch", "Coordinates per `expand` event, 1000 if absent"),
+        ],
+        "responses": { "200": { "description": "`expand` events, followed by a `complete` or `budget_exceeded` event", "content": { "text/event-stream": {} } } },
+    });
+    spec.operation("get", "/path/trace", operation);
+    let operation = json!({
+        "summary": "Generates the path to whichever bank is the cheapest to reach",
+        "requestBody": spec.body::<BankRequest>(),
+        "responses": { "200": spec.response::<Option<NearestBankResponse>>("Bank and path, `null` if no bank can be reached") },
+    });
+    spec.operation("post", "/bank/nearest", operation);
+    let operation = json!({
+        "summary": "Generates the cheapest loop from the start via a bank to the target",
+        "requestBody": spec.body::<BankRequest>(),
+        "responses": { "200": spec.response::<Option<RoundTrip>>("Round trip, `null` if no loop exists") },
+    });
+    spec.operation("post", "/bank/round-trip", operation);
+    let operation = json!({
+        "summary": "Returns the points of interest of a kind, cheapest to reach first",
+        "parameters": [
+            spec.query::<String>("tag", "Kind of the points of interest, e.g. `bank`"),
+            spec.query::<String>("near", "Coordinate the paths start at, as `x,y,plane`"),
+            grid.clone(),
+            spec.query::<Option<usize>>("limit", "Most points of interest returned"),
+            spec.query::<Option<String>>("game_state", "Game state as JSON, the default game state if absent"),
+        ],
+        "responses": { "200": spec.response::<Vec<PoiCandidate>>("Reachable points of interest and the costs of the paths to them") },
+    });
+    spec.operation("get", "/poi", operation);
+    let operation = json!({
+        "summary": "Returns the path costs between multiple sources and destinations",
+        "requestBody": spec.body::<MatrixRequest>(),
+        "responses": { "200": spec.response::<CostMatrix>("Costs as rows by source, `null` where a destination can't be reached") },
+    });
+    spec.operation("post", "/matrix", operation);
+    let operation = json!({
+        "summary": "Compares the paths generated for two different game states",
+        "requestBody": spec.body::<CompareRequest>(),
+        "responses": { "200": spec.response::<Comparison>("Both paths and the edges only one of them takes") },
+    });
+    spec.operation("post", "/compare", operation);
+    let operation = json!({
+        "summary": "Returns how the walkable groups are linked by edges and teleports",
+        "requestBody": spec.body::<GroupGraphRequest>(),
+        "responses": { "200": spec.response::<GroupGraph>("Group sizes and links") },
+    });
+    spec.operation("post", "/groups/graph", operation);
+    let operation = json!({
+        "summary": "Returns the tiles reachable from a coordinate",
+        "requestBody": spec.body::<ReachableRequest>(),
+        "responses": { "200": spec.response::<Vec<TileRun>>("Reachable tiles as horizontal runs, ordered by plane, y and x") },
+    });
+    spec.operation("post", "/reachable", operation);
+    let operation = json!({
+        "summary": "Returns whether a path can exist between two coordinates, without searching",
+        "requestBody": spec.body::<GroupReachableRequest>(),
+        "responses": { "200": spec.response::<GroupReachability>("Whether the groups of the coordinates are linked") },
+    });
+    spec.operation("post", "/reachable/groups", operation);
+    let operation = json!({
+        "summary": "Returns the tiles reachable on foot within a cost under a game state",
+        "requestBody": spec.body::<AreaRequest>(),
+        "responses": { "200": spec.response::<Vec<TileRun>>("Reachable tiles as horizontal runs, ordered by plane, y and x") },
+    });
+    spec.operation("post", "/area", operation);
+    let operation = json!({
+        "summary": "Returns the data points of the game state the grid's requirements depend on",
+        "parameters": [grid.clone()],
+        "responses": { "200": spec.response::<DataSelection>("Data points to send as game state") },
+    });
+    spec.operation("get", "/select", operation);
+    let operation = json!({
+        "summary": "Returns the movement flags, edges and teleport destinations of a square of tiles",
+        "parameters": [
+            grid.clone(),
+            spec.query::<u16>("x", "West edge of the tiles"),
+            spec.query::<u16>("y", "South edge of the tiles"),
+            spec.query::<u8>("plane", "Plane of the tiles"),
+            spec.query::<u16>("size", "Tiles per side"),
+        ],
+        "responses": { "200": spec.response::<DebugTiles>("Tiles, row by row from the south") },
+    });
+    spec.operation("get", "/debug/tiles", operation);
+    let operation = json!({
+        "summary": "Reloads the NavGrid file without interrupting requests",
+        "parameters": [grid],
+        "responses": { "204": { "description": "Reloaded" } },
+    });
+    spec.operation("post", "/admin/reload", operation);
+
+    OpenApi(spec.finish().to_string())
+}